@@ -0,0 +1,57 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use x509_cert::der::{Decode, Encode};
+use x509_cert::Certificate;
+
+/// Common name of the certificate generated by the Android SDK debug keystore
+const DEBUG_CERT_CN: &str = "CN=Android Debug";
+
+/// Get the subject of a DER encoded X.509 certificate (RFC 4514 format)
+pub fn certificate_subject(der: &[u8]) -> Result<String> {
+    let cert = Certificate::from_der(der)?;
+    Ok(cert.tbs_certificate.subject.to_string())
+}
+
+/// SHA-256 of the DER encoded SubjectPublicKeyInfo of a certificate, identifies the signing key
+/// across certificate renewals
+pub fn certificate_public_key_hash(der: &[u8]) -> Result<Vec<u8>> {
+    let cert = Certificate::from_der(der)?;
+    let spki = cert.tbs_certificate.subject_public_key_info.to_der()?;
+    Ok(Sha256::digest(spki).to_vec())
+}
+
+/// Check if a DER encoded certificate is the Android SDK debug certificate
+pub fn is_debug_certificate(der: &[u8]) -> bool {
+    certificate_subject(der)
+        .map(|s| s.split(',').any(|rdn| rdn == DEBUG_CERT_CN))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Self-signed P-256 certificate with the debug keystore subject
+    const DEBUG_CERT: &[u8] = include_bytes!("../testdata/debug_cert.der");
+
+    #[test]
+    fn debug_certificate() -> Result<()> {
+        assert_eq!(
+            certificate_subject(DEBUG_CERT)?,
+            "C=US,O=Android,CN=Android Debug"
+        );
+        assert!(is_debug_certificate(DEBUG_CERT));
+        assert!(!is_debug_certificate(b"not a certificate"));
+        Ok(())
+    }
+
+    #[test]
+    fn public_key_hash() -> Result<()> {
+        // openssl x509 -pubkey -noout | openssl pkey -pubin -outform DER | sha256sum
+        assert_eq!(
+            hex::encode(certificate_public_key_hash(DEBUG_CERT)?),
+            "40a1180e260804584354d636178fd4189cf1629bd20d73458d56105d27502333"
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
+mod certificate;
 mod manifest;
+mod resources;
 mod signing_block;
 
+pub use apk::manifest::{Feature, Permission};
 pub use apk::*;
+pub use certificate::*;
 pub use manifest::*;
+pub use resources::*;
 pub use signing_block::*;
@@ -1,12 +1,28 @@
 use anyhow::{bail, Result};
-use apk::res::Chunk;
+use apk::manifest::{Activity, Feature, IntentFilter, Permission};
+use apk::res::{Chunk, ResXmlAttribute};
 use apk::AndroidManifest;
 use log::debug;
 use std::collections::HashMap;
 use std::io::Cursor;
 
+/// Manifest attributes the `apk` crate has no field for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestExtras {
+    /// `android:allowBackup` of the application
+    pub allow_backup: Option<bool>,
+    /// `android:installLocation`, `auto`, `internalOnly` or `preferExternal`
+    pub install_location: Option<String>,
+}
+
 /// Parse android manifest from AndroidManifest.xml file data
 pub fn parse_android_manifest(data: &[u8]) -> Result<AndroidManifest> {
+    Ok(parse_android_manifest_extras(data)?.0)
+}
+
+/// Parse android manifest from AndroidManifest.xml file data, with the attributes
+/// [AndroidManifest] can't hold
+pub fn parse_android_manifest_extras(data: &[u8]) -> Result<(AndroidManifest, ManifestExtras)> {
     let chunks = if let Chunk::Xml(chunks) = Chunk::parse(&mut Cursor::new(data))? {
         chunks
     } else {
@@ -14,12 +30,7 @@ pub fn parse_android_manifest(data: &[u8]) -> Result<AndroidManifest> {
     };
 
     let strings = if let Chunk::StringPool(strings, _) = &chunks[0] {
-        HashMap::from_iter(
-            strings
-                .iter()
-                .enumerate()
-                .map(|(i, s)| (s.to_string(), i as i32)),
-        )
+        XmlStrings::new(strings, &chunks)
     } else {
         bail!("invalid manifest 1");
     };
@@ -51,49 +62,496 @@ pub fn parse_android_manifest(data: &[u8]) -> Result<AndroidManifest> {
     res.application.theme = find_value_in(&strings, &chunks, "application", "theme");
     res.application.label = find_value_in(&strings, &chunks, "application", "label");
     res.application.icon = find_value_in(&strings, &chunks, "application", "icon");
+    res.application.debuggable =
+        find_value_in(&strings, &chunks, "application", "debuggable").map(|v| v == "true");
+    res.application.activities = parse_activities(&strings, &chunks);
 
-    Ok(res)
-}
-
-fn find_value_in(
-    strings: &HashMap<String, i32>,
-    chunks: &Vec<Chunk>,
-    node: &str,
-    attr: &str,
-) -> Option<String> {
-    let idx_node = if let Some(i) = strings.get(node) {
-        *i
-    } else {
-        return None;
+    let extras = ManifestExtras {
+        allow_backup: find_value_in(&strings, &chunks, "application", "allowBackup")
+            .map(|v| v == "true"),
+        install_location: find_value_in(&strings, &chunks, "manifest", "installLocation").map(
+            |v| match v.as_str() {
+                "0" => "auto".to_string(),
+                "1" => "internalOnly".to_string(),
+                "2" => "preferExternal".to_string(),
+                _ => v,
+            },
+        ),
     };
 
-    let idx_attr = if let Some(i) = strings.get(attr) {
-        *i
-    } else {
-        return None;
-    };
+    res.uses_permission = ["uses-permission", "uses-permission-sdk-23"]
+        .iter()
+        .flat_map(|node| find_elements(&strings, &chunks, node))
+        .filter_map(|attrs| {
+            Some(Permission {
+                name: get_attr(&strings, attrs, "name")?,
+                max_sdk_version: get_attr(&strings, attrs, "maxSdkVersion")
+                    .and_then(|v| v.parse().ok()),
+            })
+        })
+        .collect();
+
+    res.uses_feature = find_elements(&strings, &chunks, "uses-feature")
+        .filter_map(|attrs| {
+            Some(Feature {
+                name: Some(get_attr(&strings, attrs, "name")?),
+                // features are required unless declared otherwise
+                required: Some(
+                    get_attr(&strings, attrs, "required")
+                        .map(|v| v == "true")
+                        .unwrap_or(true),
+                ),
+                version: get_attr(&strings, attrs, "version").and_then(|v| v.parse().ok()),
+                opengles_version: None,
+            })
+        })
+        .collect();
 
-    chunks.iter().find_map(|chunk| {
-        if let Chunk::XmlStartElement(_, el, attrs) = chunk {
-            match el.name {
-                x if x == idx_node => attrs.iter().find(|e| e.name == idx_attr).and_then(|e| {
-                    debug!("{}, {}, {:?}", node, attr, e);
-                    match e.typed_value.data_type {
-                        3 => strings
-                            .iter()
-                            .find(|(_, v)| **v == e.raw_value)
-                            .map(|(k, _)| k.clone()),
-                        16 => Some(e.typed_value.data.to_string()),
-                        _ => {
-                            debug!("unknown data type {},{},{:?}", node, attr, e);
-                            None
+    Ok((res, extras))
+}
+
+/// Name of the activity started from the launcher, the one with a `MAIN` / `LAUNCHER` intent
+/// filter
+pub fn launcher_activity(manifest: &AndroidManifest) -> Option<&str> {
+    manifest
+        .application
+        .activities
+        .iter()
+        .find(|a| {
+            a.intent_filters.iter().any(|f| {
+                f.actions.iter().any(|a| a == "android.intent.action.MAIN")
+                    && f.categories
+                        .iter()
+                        .any(|c| c == "android.intent.category.LAUNCHER")
+            })
+        })
+        .and_then(|a| a.name.as_deref())
+}
+
+/// Activities (and activity aliases) with their intent filters
+fn parse_activities(strings: &XmlStrings, chunks: &[Chunk]) -> Vec<Activity> {
+    let activity_names = [strings.get("activity"), strings.get("activity-alias")];
+    let mut activities: Vec<Activity> = vec![];
+    // intent filters of services and receivers look the same, only keep the ones nested in an
+    // activity
+    let mut in_activity = false;
+    for chunk in chunks {
+        match chunk {
+            Chunk::XmlStartElement(_, el, attrs) => {
+                let name = strings.string(el.name);
+                if activity_names.contains(&Some(el.name)) {
+                    in_activity = true;
+                    activities.push(Activity {
+                        name: get_attr(strings, attrs, "name"),
+                        exported: get_attr(strings, attrs, "exported").map(|v| v == "true"),
+                        ..Default::default()
+                    });
+                    continue;
+                }
+                let Some(activity) = activities.last_mut().filter(|_| in_activity) else {
+                    continue;
+                };
+                match name {
+                    Some("intent-filter") => activity.intent_filters.push(IntentFilter::default()),
+                    Some("action") | Some("category") => {
+                        let (Some(filter), Some(value)) = (
+                            activity.intent_filters.last_mut(),
+                            get_attr(strings, attrs, "name"),
+                        ) else {
+                            continue;
+                        };
+                        if name == Some("action") {
+                            filter.actions.push(value);
+                        } else {
+                            filter.categories.push(value);
                         }
                     }
-                }),
+                    Some("meta-data") | Some("data") => {}
+                    // any other component ends the activity
+                    _ => in_activity = false,
+                }
+            }
+            Chunk::XmlEndElement(_, el) if activity_names.contains(&Some(el.name)) => {
+                in_activity = false
+            }
+            _ => {}
+        }
+    }
+    activities
+}
+
+/// Resource ids of the framework attributes (`android.R.attr`) read from the manifest
+const FRAMEWORK_ATTRS: &[(&str, u32)] = &[
+    ("theme", 0x01010000),
+    ("label", 0x01010001),
+    ("icon", 0x01010002),
+    ("name", 0x01010003),
+    ("debuggable", 0x0101000f),
+    ("exported", 0x01010010),
+    ("minSdkVersion", 0x0101020c),
+    ("versionCode", 0x0101021b),
+    ("versionName", 0x0101021c),
+    ("targetSdkVersion", 0x01010270),
+    ("maxSdkVersion", 0x01010271),
+    ("allowBackup", 0x01010280),
+    ("glEsVersion", 0x01010281),
+    ("required", 0x0101028e),
+    ("installLocation", 0x010102b7),
+    ("compileSdkVersion", 0x01010572),
+    ("compileSdkVersionCodename", 0x01010573),
+];
+
+fn framework_attr_id(attr: &str) -> Option<u32> {
+    FRAMEWORK_ATTRS
+        .iter()
+        .find(|(name, _)| *name == attr)
+        .map(|(_, id)| *id)
+}
+
+/// String pool of a binary XML file with the resource ids of its attribute names
+struct XmlStrings<'a> {
+    pool: &'a [String],
+    index: HashMap<&'a str, i32>,
+    /// Resource id of the string at the same index, only set for attribute names
+    resource_ids: Vec<u32>,
+}
+
+impl<'a> XmlStrings<'a> {
+    fn new(pool: &'a [String], chunks: &[Chunk]) -> Self {
+        let resource_ids = chunks
+            .iter()
+            .find_map(|c| match c {
+                Chunk::XmlResourceMap(ids) => Some(ids.clone()),
                 _ => None,
+            })
+            .unwrap_or_default();
+        // keep the first index of duplicate strings
+        let mut index = HashMap::new();
+        for (i, s) in pool.iter().enumerate() {
+            index.entry(s.as_str()).or_insert(i as i32);
+        }
+        XmlStrings {
+            pool,
+            index,
+            resource_ids,
+        }
+    }
+
+    fn get(&self, s: &str) -> Option<i32> {
+        self.index.get(s).copied()
+    }
+
+    fn string(&self, idx: i32) -> Option<&'a str> {
+        self.pool
+            .get(usize::try_from(idx).ok()?)
+            .map(|s| s.as_str())
+    }
+
+    fn resource_id(&self, idx: i32) -> Option<u32> {
+        self.resource_ids.get(usize::try_from(idx).ok()?).copied()
+    }
+}
+
+fn find_value_in(strings: &XmlStrings, chunks: &[Chunk], node: &str, attr: &str) -> Option<String> {
+    find_elements(strings, chunks, node).find_map(|attrs| get_attr(strings, attrs, attr))
+}
+
+/// Attributes of every element named `node`
+fn find_elements<'a>(
+    strings: &XmlStrings,
+    chunks: &'a [Chunk],
+    node: &str,
+) -> impl Iterator<Item = &'a [ResXmlAttribute]> {
+    let idx_node = strings.get(node);
+    chunks.iter().filter_map(move |chunk| match chunk {
+        Chunk::XmlStartElement(_, el, attrs) if Some(el.name) == idx_node => Some(attrs.as_slice()),
+        _ => None,
+    })
+}
+
+/// Get the value of an attribute as a string
+fn get_attr(strings: &XmlStrings, attrs: &[ResXmlAttribute], attr: &str) -> Option<String> {
+    // namespaced attributes are identified by their resource id, the name string may be
+    // missing or stripped, plain attributes like `package` only have the name
+    let by_id = framework_attr_id(attr).and_then(|id| {
+        attrs
+            .iter()
+            .find(|e| strings.resource_id(e.name) == Some(id))
+    });
+    let attribute = by_id.or_else(|| {
+        let idx_attr = strings.get(attr)?;
+        attrs.iter().find(|e| e.name == idx_attr)
+    });
+    attribute.and_then(|e| {
+        debug!("{}, {:?}", attr, e);
+        match e.typed_value.data_type {
+            3 => strings.string(e.raw_value).map(|s| s.to_string()),
+            1 => Some(format!("@{:#010x}", e.typed_value.data)),
+            16 => Some(e.typed_value.data.to_string()),
+            18 => Some((e.typed_value.data != 0).to_string()),
+            _ => {
+                debug!("unknown data type {},{:?}", attr, e);
+                None
             }
-        } else {
-            None
         }
     })
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use apk::res::{ResValue, ResXmlNodeHeader, ResXmlStartElement};
+
+    /// Builds a binary AndroidManifest.xml from `(element, [(attribute, value)])` pairs,
+    /// values are written as strings unless they parse as an integer or boolean
+    pub fn build_manifest(elements: &[(&str, &[(&str, &str)])]) -> Vec<u8> {
+        build_xml(elements, false)
+    }
+
+    /// Like [build_manifest], framework attributes come first in the string pool and are
+    /// mapped to their resource ids, with `strip_names` their names are left empty like
+    /// in APKs processed by resource obfuscators
+    fn build_xml(elements: &[(&str, &[(&str, &str)])], strip_names: bool) -> Vec<u8> {
+        let mut framework: Vec<&str> = vec![];
+        for (_, attrs) in elements {
+            for (k, _) in attrs.iter() {
+                if framework_attr_id(k).is_some() && !framework.contains(k) {
+                    framework.push(k);
+                }
+            }
+        }
+        let resource_ids: Vec<u32> = framework
+            .iter()
+            .filter_map(|k| framework_attr_id(k))
+            .collect();
+        let mut strings: Vec<String> = framework
+            .iter()
+            .map(|k| {
+                if strip_names {
+                    String::new()
+                } else {
+                    k.to_string()
+                }
+            })
+            .collect();
+        let mut idx = |s: &str| -> i32 {
+            if let Some(i) = framework.iter().position(|x| *x == s) {
+                return i as i32;
+            }
+            if let Some(i) = strings.iter().position(|x| x == s) {
+                i as i32
+            } else {
+                strings.push(s.to_string());
+                strings.len() as i32 - 1
+            }
+        };
+        let mut chunks = vec![];
+        for (name, attrs) in elements {
+            let name = idx(name);
+            let attrs: Vec<ResXmlAttribute> = attrs
+                .iter()
+                .map(|(k, v)| {
+                    let name = idx(k);
+                    let (raw_value, data_type, data) = if let Ok(n) = v.parse::<u32>() {
+                        (-1, 16, n)
+                    } else if let Ok(b) = v.parse::<bool>() {
+                        (-1, 18, if b { u32::MAX } else { 0 })
+                    } else {
+                        let i = idx(v);
+                        (i, 3, i as u32)
+                    };
+                    ResXmlAttribute {
+                        namespace: -1,
+                        name,
+                        raw_value,
+                        typed_value: ResValue {
+                            size: 8,
+                            res0: 0,
+                            data_type,
+                            data,
+                        },
+                    }
+                })
+                .collect();
+            chunks.push(Chunk::XmlStartElement(
+                ResXmlNodeHeader {
+                    line_number: 1,
+                    comment: -1,
+                },
+                ResXmlStartElement {
+                    name,
+                    attribute_count: attrs.len() as u16,
+                    ..Default::default()
+                },
+                attrs,
+            ));
+        }
+        chunks.insert(0, Chunk::XmlResourceMap(resource_ids));
+        chunks.insert(0, Chunk::StringPool(strings, vec![]));
+
+        let mut out = Cursor::new(vec![]);
+        Chunk::Xml(chunks).write(&mut out).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn parse_permissions() -> Result<()> {
+        let data = build_manifest(&[
+            (
+                "manifest",
+                &[("package", "com.example.app"), ("versionCode", "42")],
+            ),
+            (
+                "uses-permission",
+                &[("name", "android.permission.INTERNET")],
+            ),
+            (
+                "uses-permission",
+                &[
+                    ("name", "android.permission.READ_EXTERNAL_STORAGE"),
+                    ("maxSdkVersion", "32"),
+                ],
+            ),
+            (
+                "uses-permission-sdk-23",
+                &[("name", "android.permission.CAMERA")],
+            ),
+        ]);
+        let manifest = parse_android_manifest(&data)?;
+        assert_eq!(manifest.package.as_deref(), Some("com.example.app"));
+        assert_eq!(manifest.version_code, Some(42));
+        let permissions: Vec<_> = manifest
+            .uses_permission
+            .iter()
+            .map(|p| (p.name.as_str(), p.max_sdk_version))
+            .collect();
+        assert_eq!(
+            permissions,
+            [
+                ("android.permission.INTERNET", None),
+                ("android.permission.READ_EXTERNAL_STORAGE", Some(32)),
+                ("android.permission.CAMERA", None),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_stripped_attribute_names() -> Result<()> {
+        let elements: &[(&str, &[(&str, &str)])] = &[
+            (
+                "manifest",
+                &[
+                    ("package", "com.example.app"),
+                    ("versionCode", "42"),
+                    ("versionName", "1.2.3"),
+                ],
+            ),
+            (
+                "uses-sdk",
+                &[("minSdkVersion", "24"), ("targetSdkVersion", "34")],
+            ),
+            (
+                "uses-permission",
+                &[("name", "android.permission.INTERNET")],
+            ),
+        ];
+        for strip_names in [false, true] {
+            let manifest = parse_android_manifest(&build_xml(elements, strip_names))?;
+            assert_eq!(manifest.package.as_deref(), Some("com.example.app"));
+            assert_eq!(manifest.version_code, Some(42));
+            assert_eq!(manifest.version_name.as_deref(), Some("1.2.3"));
+            assert_eq!(manifest.sdk.min_sdk_version, Some(24));
+            assert_eq!(manifest.sdk.target_sdk_version, Some(34));
+            assert_eq!(
+                manifest.uses_permission[0].name,
+                "android.permission.INTERNET"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_application_flags() -> Result<()> {
+        let data = build_manifest(&[
+            (
+                "manifest",
+                &[("package", "com.example.app"), ("installLocation", "2")],
+            ),
+            (
+                "application",
+                &[("debuggable", "true"), ("allowBackup", "false")],
+            ),
+        ]);
+        let (manifest, extras) = parse_android_manifest_extras(&data)?;
+        assert_eq!(manifest.application.debuggable, Some(true));
+        assert_eq!(extras.allow_backup, Some(false));
+        assert_eq!(extras.install_location.as_deref(), Some("preferExternal"));
+
+        let data = build_manifest(&[("manifest", &[("package", "com.example.app")])]);
+        let (manifest, extras) = parse_android_manifest_extras(&data)?;
+        assert_eq!(manifest.application.debuggable, None);
+        assert_eq!(extras, ManifestExtras::default());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_launcher_activity() -> Result<()> {
+        let data = build_manifest(&[
+            ("manifest", &[("package", "com.example.app")]),
+            ("application", &[]),
+            ("activity", &[("name", "com.example.app.SettingsActivity")]),
+            ("intent-filter", &[]),
+            ("action", &[("name", "android.intent.action.VIEW")]),
+            ("receiver", &[("name", "com.example.app.BootReceiver")]),
+            ("intent-filter", &[]),
+            ("action", &[("name", "android.intent.action.MAIN")]),
+            ("category", &[("name", "android.intent.category.LAUNCHER")]),
+            (
+                "activity",
+                &[
+                    ("name", "com.example.app.MainActivity"),
+                    ("exported", "true"),
+                ],
+            ),
+            ("intent-filter", &[]),
+            ("action", &[("name", "android.intent.action.MAIN")]),
+            ("category", &[("name", "android.intent.category.LAUNCHER")]),
+        ]);
+        let manifest = parse_android_manifest(&data)?;
+        assert_eq!(manifest.application.activities.len(), 2);
+        assert_eq!(manifest.application.activities[1].exported, Some(true));
+        assert_eq!(
+            launcher_activity(&manifest),
+            Some("com.example.app.MainActivity")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_features() -> Result<()> {
+        let data = build_manifest(&[
+            ("manifest", &[("package", "com.example.app")]),
+            (
+                "uses-feature",
+                &[("name", "android.hardware.camera"), ("required", "false")],
+            ),
+            ("uses-feature", &[("name", "android.hardware.bluetooth")]),
+            ("uses-feature", &[("glEsVersion", "131072")]),
+        ]);
+        let manifest = parse_android_manifest(&data)?;
+        let features: Vec<_> = manifest
+            .uses_feature
+            .iter()
+            .map(|f| (f.name.as_deref().unwrap(), f.required))
+            .collect();
+        assert_eq!(
+            features,
+            [
+                ("android.hardware.camera", Some(false)),
+                ("android.hardware.bluetooth", Some(true)),
+            ]
+        );
+        Ok(())
+    }
+}
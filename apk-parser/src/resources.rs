@@ -0,0 +1,196 @@
+use anyhow::{bail, Result};
+use apk::res::{Chunk, ResTableValue};
+use std::io::Cursor;
+
+/// Compiled resource table (resources.arsc)
+pub struct ResourceTable {
+    /// Global value string pool
+    strings: Vec<String>,
+    /// Package id and its chunks
+    packages: Vec<(u8, Vec<Chunk>)>,
+}
+
+impl ResourceTable {
+    /// Parse resource table from resources.arsc file data
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let chunks = if let Chunk::Table(_, chunks) = Chunk::parse(&mut Cursor::new(data))? {
+            chunks
+        } else {
+            bail!("Invalid resource table");
+        };
+
+        let mut strings = vec![];
+        let mut packages = vec![];
+        for chunk in chunks {
+            match chunk {
+                Chunk::StringPool(s, _) => strings = s,
+                Chunk::TablePackage(header, chunks) => packages.push((header.id as u8, chunks)),
+                _ => {}
+            }
+        }
+        Ok(Self { strings, packages })
+    }
+
+    /// Resolve all string values of a resource across its configurations,
+    /// as `(locale, value)` pairs where the default configuration has an empty locale
+    pub fn resolve_strings(&self, id: u32) -> Vec<(String, String)> {
+        let package = (id >> 24) as u8;
+        let ty = (id >> 16) as u8;
+        let entry = (id & 0xffff) as usize;
+
+        let mut res = vec![];
+        for (_, chunks) in self.packages.iter().filter(|(p, _)| *p == package) {
+            for chunk in chunks {
+                if let Chunk::TableType(header, _, entries) = chunk {
+                    if header.id != ty {
+                        continue;
+                    }
+                    let Some(Some(e)) = entries.get(entry) else {
+                        continue;
+                    };
+                    // only plain string values, other types are not useful as text
+                    if let ResTableValue::Simple(v) = &e.value {
+                        if v.data_type != 3 {
+                            continue;
+                        }
+                        if let Some(s) = self.strings.get(v.data as usize) {
+                            res.push((locale_name(header.config.locale), s.clone()));
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Parse a resource reference attribute value (`@0x7f0e001b`) into its id
+pub fn resource_id(value: &str) -> Option<u32> {
+    u32::from_str_radix(value.strip_prefix("@0x")?, 16).ok()
+}
+
+/// Format a packed resource config locale as a language tag (`en`, `pt-BR`)
+fn locale_name(locale: u32) -> String {
+    let [l0, l1, c0, c1] = locale.to_le_bytes();
+    // packed 3 letter codes are not supported
+    if l0 == 0 || l0 & 0x80 != 0 {
+        return String::new();
+    }
+    let mut res = String::from_utf8_lossy(&[l0, l1]).to_string();
+    if c0 != 0 && c0 & 0x80 == 0 {
+        res.push('-');
+        res.push_str(&String::from_utf8_lossy(&[c0, c1]));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apk::res::{
+        ResTableConfig, ResTableEntry, ResTableHeader, ResTablePackageHeader, ResTableTypeHeader,
+        ResValue, ScreenType,
+    };
+
+    fn string_type(locale: u32, value: u32) -> Chunk {
+        Chunk::TableType(
+            ResTableTypeHeader {
+                id: 2,
+                res0: 0,
+                res1: 0,
+                entry_count: 2,
+                entries_start: 0,
+                config: ResTableConfig {
+                    size: 28,
+                    imsi: 0,
+                    locale,
+                    screen_type: ScreenType {
+                        orientation: 0,
+                        touchscreen: 0,
+                        density: 0,
+                    },
+                    input: 0,
+                    screen_size: 0,
+                    version: 0,
+                    unknown: vec![],
+                },
+            },
+            vec![0xffff_ffff, 0],
+            vec![
+                None,
+                Some(ResTableEntry {
+                    size: 8,
+                    flags: 0,
+                    key: 0,
+                    value: ResTableValue::Simple(ResValue {
+                        size: 8,
+                        res0: 0,
+                        data_type: 3,
+                        data: value,
+                    }),
+                }),
+            ],
+        )
+    }
+
+    #[test]
+    fn resolve_localized_strings() {
+        let table = ResourceTable {
+            strings: vec!["Example".into(), "Beispiel".into(), "Exemplo".into()],
+            packages: vec![(
+                0x7f,
+                vec![
+                    string_type(0, 0),
+                    string_type(u32::from_le_bytes(*b"de\0\0"), 1),
+                    string_type(u32::from_le_bytes(*b"ptBR"), 2),
+                ],
+            )],
+        };
+        assert_eq!(
+            table.resolve_strings(0x7f020001),
+            [
+                ("".to_string(), "Example".to_string()),
+                ("de".to_string(), "Beispiel".to_string()),
+                ("pt-BR".to_string(), "Exemplo".to_string()),
+            ]
+        );
+        assert!(table.resolve_strings(0x7f020000).is_empty());
+        assert!(table.resolve_strings(0x7f030001).is_empty());
+    }
+
+    #[test]
+    fn parse_table() -> Result<()> {
+        let mut out = Cursor::new(vec![]);
+        Chunk::Table(
+            ResTableHeader { package_count: 1 },
+            vec![
+                Chunk::StringPool(vec!["Example".into()], vec![]),
+                Chunk::TablePackage(
+                    ResTablePackageHeader {
+                        id: 0x7f,
+                        name: "com.example.app".into(),
+                        type_strings: 0,
+                        last_public_type: 0,
+                        key_strings: 0,
+                        last_public_key: 0,
+                        type_id_offset: 0,
+                    },
+                    vec![
+                        Chunk::StringPool(vec!["attr".into(), "string".into()], vec![]),
+                        Chunk::StringPool(vec!["app_name".into()], vec![]),
+                        string_type(0, 0),
+                    ],
+                ),
+            ],
+        )
+        .write(&mut out)?;
+        let table = ResourceTable::parse(&out.into_inner())?;
+        assert_eq!(
+            table.resolve_strings(0x7f020001),
+            [("".to_string(), "Example".to_string())]
+        );
+        assert_eq!(resource_id("@0x7f020001"), Some(0x7f020001));
+        assert_eq!(resource_id("Example"), None);
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::{bail, ensure, Result};
 use byteorder::{LittleEndian, ReadBytesExt};
 use log::{debug, warn};
+use sha2::{Digest, Sha256, Sha512};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::{Display, Formatter};
@@ -12,6 +13,12 @@ use std::path::Path;
 #[derive(Debug, Clone)]
 pub struct ApkSigningBlock {
     pub data: Vec<(u32, Vec<u8>)>,
+
+    /// Offset of the signing block from the start of the file
+    pub offset: u64,
+
+    /// Total size of the signing block in bytes
+    pub length: u64,
 }
 
 impl ApkSigningBlock {
@@ -30,6 +37,8 @@ impl ApkSigningBlock {
     pub fn get_signatures(&self) -> Result<Vec<ApkSignatureBlock>> {
         const V2_SIG_BLOCK_ID: u32 = 0x7109871a;
         const V3_SIG_BLOCK_ID: u32 = 0xf05368c0;
+        const SOURCE_STAMP_V1_BLOCK_ID: u32 = 0x2b09ab1d;
+        const SOURCE_STAMP_V2_BLOCK_ID: u32 = 0x6dff800d;
 
         let mut sigs = vec![];
         for (k, v) in &self.data {
@@ -103,11 +112,236 @@ impl ApkSigningBlock {
                         public_key: public_key.to_vec(),
                     });
                 }
+                SOURCE_STAMP_V1_BLOCK_ID | SOURCE_STAMP_V2_BLOCK_ID => {
+                    // both versions start with the stamp certificate, followed by the
+                    // signed digests (and attributes in v2)
+                    let mut v = v.as_slice();
+                    let mut stamp_block = take_lv_u32(&mut v)?;
+                    let certificate = take_lv_u32(&mut stamp_block)?;
+                    sigs.push(ApkSignatureBlock::SourceStamp {
+                        certificate: certificate.to_vec(),
+                    });
+                }
                 v => debug!("Unknown block id {}", v),
             }
         }
         Ok(sigs)
     }
+
+    /// Recompute the v2/v3 content digests and compare them with the digests in the signing block
+    ///
+    /// https://source.android.com/docs/security/features/apksigning/v2#integrity-protected-contents
+    pub fn verify_digests<R: Read + Seek>(&self, reader: &mut R) -> Result<()> {
+        let sigs = self.get_signatures()?;
+        let cd = CentralDirectory::from_reader(reader)?;
+        // apksig rejects these too, the scheme only defines the digest of the 32-bit EOCD
+        ensure!(
+            cd.zip64_offset.is_none(),
+            "Content digests of ZIP64 APKs can't be verified"
+        );
+        let cd_offset = self.offset + self.length;
+        ensure!(
+            cd.offset == cd_offset,
+            "Central directory does not follow the signing block"
+        );
+        // the EOCD is digested as if the central directory started at the signing block
+        let mut eocd = cd.eocd;
+        eocd[16..20].copy_from_slice(&(self.offset as u32).to_le_bytes());
+
+        let sections = [(0, self.offset), (cd_offset, cd.eocd_offset - cd_offset)];
+        let mut verified = 0;
+        for sig in sigs.iter().flat_map(|s| match s {
+            ApkSignatureBlock::V2 { signatures, .. } => signatures.as_slice(),
+            ApkSignatureBlock::V3 { signatures, .. } => signatures.as_slice(),
+            ApkSignatureBlock::Unknown { .. } | ApkSignatureBlock::SourceStamp { .. } => &[],
+        }) {
+            let actual = match sig.algo.content_digest() {
+                ContentDigestAlgo::Sha256 => {
+                    compute_content_digest::<Sha256, _>(reader, &sections, &eocd)?
+                }
+                ContentDigestAlgo::Sha512 => {
+                    compute_content_digest::<Sha512, _>(reader, &sections, &eocd)?
+                }
+            };
+            if actual != sig.digest {
+                return Err(DigestMismatch {
+                    algo: sig.algo.clone(),
+                    expected: sig.digest.clone(),
+                    actual,
+                }
+                .into());
+            }
+            verified += 1;
+        }
+        reader.seek(SeekFrom::Start(0))?;
+        ensure!(verified > 0, "No signatures found to verify");
+        Ok(())
+    }
+}
+
+/// Content digest from the signing block does not match the APK contents
+#[derive(Debug, Clone)]
+pub struct DigestMismatch {
+    pub algo: ApkSignatureAlgo,
+    pub expected: Vec<u8>,
+    pub actual: Vec<u8>,
+}
+
+impl Display for DigestMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} content digest mismatch, expected={}, actual={}",
+            self.algo,
+            hex::encode(&self.expected),
+            hex::encode(&self.actual)
+        )
+    }
+}
+
+impl std::error::Error for DigestMismatch {}
+
+/// Location of the ZIP central directory, from the end of central directory records
+#[derive(Debug, Clone)]
+pub struct CentralDirectory {
+    /// Offset of the central directory from the start of the file
+    pub offset: u64,
+
+    /// Size of the central directory in bytes
+    pub size: u64,
+
+    /// Offset of the End of Central Directory record
+    pub eocd_offset: u64,
+
+    /// End of Central Directory record, including the comment
+    pub eocd: Vec<u8>,
+
+    /// Offset of the ZIP64 End of Central Directory record, for archives over 4GB or with
+    /// more than 65535 entries
+    pub zip64_offset: Option<u64>,
+}
+
+impl CentralDirectory {
+    /// Read the (ZIP64) End of Central Directory records
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        const ZIP64_EOCD_MAGIC: &[u8] = &[0x50, 0x4b, 0x06, 0x06];
+        const ZIP64_LOCATOR_MAGIC: &[u8] = &[0x50, 0x4b, 0x06, 0x07];
+
+        let (eocd_offset, eocd) = read_eocd(reader)?;
+        let mut size = u32::from_le_bytes(eocd[12..16].try_into()?) as u64;
+        let mut offset = u32::from_le_bytes(eocd[16..20].try_into()?) as u64;
+
+        // the ZIP64 locator sits right before the EOCD and points at the ZIP64 record
+        let mut zip64_offset = None;
+        if eocd_offset >= 20 {
+            let mut locator = [0u8; 20];
+            reader.seek(SeekFrom::Start(eocd_offset - 20))?;
+            reader.read_exact(&mut locator)?;
+            if &locator[..4] == ZIP64_LOCATOR_MAGIC {
+                let record_offset = u64::from_le_bytes(locator[8..16].try_into()?);
+                let mut record = [0u8; 56];
+                reader.seek(SeekFrom::Start(record_offset))?;
+                reader.read_exact(&mut record)?;
+                ensure!(
+                    &record[..4] == ZIP64_EOCD_MAGIC,
+                    "Invalid ZIP64 end of central directory"
+                );
+                size = u64::from_le_bytes(record[40..48].try_into()?);
+                offset = u64::from_le_bytes(record[48..56].try_into()?);
+                zip64_offset = Some(record_offset);
+            }
+        }
+        ensure!(
+            offset
+                .checked_add(size)
+                .is_some_and(|end| end <= zip64_offset.unwrap_or(eocd_offset)),
+            "Invalid central directory location"
+        );
+        reader.seek(SeekFrom::Start(0))?;
+        Ok(CentralDirectory {
+            offset,
+            size,
+            eocd_offset,
+            eocd,
+            zip64_offset,
+        })
+    }
+}
+
+/// Locate the End of Central Directory record, returns its offset and contents
+fn read_eocd<R: Read + Seek>(reader: &mut R) -> Result<(u64, Vec<u8>)> {
+    const EOCD_MAGIC: &[u8] = &[0x50, 0x4b, 0x05, 0x06];
+    const EOCD_MIN_SIZE: u64 = 22;
+
+    let flen = reader.seek(SeekFrom::End(0))?;
+    ensure!(flen >= EOCD_MIN_SIZE, "File too small to be a zip");
+    // most archives have no comment, check the last record first to avoid reading 64KB
+    let mut buf = vec![0; EOCD_MIN_SIZE as usize];
+    reader.seek(SeekFrom::Start(flen - EOCD_MIN_SIZE))?;
+    reader.read_exact(&mut buf)?;
+    if &buf[..4] == EOCD_MAGIC && buf[20..22] == [0, 0] {
+        return Ok((flen - EOCD_MIN_SIZE, buf));
+    }
+    // EOCD can be followed by a comment up to 65535 bytes
+    let scan_len = flen.min(EOCD_MIN_SIZE + u16::MAX as u64);
+    reader.seek(SeekFrom::Start(flen - scan_len))?;
+    let mut buf = vec![0; scan_len as usize];
+    reader.read_exact(&mut buf)?;
+
+    for i in (0..=buf.len() - EOCD_MIN_SIZE as usize).rev() {
+        if &buf[i..i + 4] == EOCD_MAGIC {
+            return Ok((flen - scan_len + i as u64, buf[i..].to_vec()));
+        }
+    }
+    bail!("Failed to find end of central directory")
+}
+
+/// Chunked digest over the given file sections followed by the (modified) EOCD
+fn compute_content_digest<D, R>(
+    reader: &mut R,
+    sections: &[(u64, u64)],
+    eocd: &[u8],
+) -> Result<Vec<u8>>
+where
+    D: Digest,
+    R: Read + Seek,
+{
+    const CHUNK_SIZE: u64 = 1024 * 1024;
+
+    let chunk_count = sections
+        .iter()
+        .map(|(_, len)| len.div_ceil(CHUNK_SIZE))
+        .sum::<u64>()
+        + (eocd.len() as u64).div_ceil(CHUNK_SIZE);
+
+    let mut top = D::new();
+    top.update([0x5a]);
+    top.update((chunk_count as u32).to_le_bytes());
+
+    let mut buf = vec![0; CHUNK_SIZE as usize];
+    for (offset, len) in sections {
+        reader.seek(SeekFrom::Start(*offset))?;
+        let mut remaining = *len;
+        while remaining > 0 {
+            let n = remaining.min(CHUNK_SIZE) as usize;
+            reader.read_exact(&mut buf[..n])?;
+            top.update(digest_chunk::<D>(&buf[..n]));
+            remaining -= n as u64;
+        }
+    }
+    for chunk in eocd.chunks(CHUNK_SIZE as usize) {
+        top.update(digest_chunk::<D>(chunk));
+    }
+    Ok(top.finalize().to_vec())
+}
+
+#[inline]
+fn digest_chunk<D: Digest>(chunk: &[u8]) -> Vec<u8> {
+    let mut d = D::new();
+    d.update([0xa5]);
+    d.update((chunk.len() as u32).to_le_bytes());
+    d.update(chunk);
+    d.finalize().to_vec()
 }
 
 fn parse_sigs(signatures: &Vec<(u32, &[u8])>, digests: &HashMap<u32, &[u8]>) -> Vec<ApkSignature> {
@@ -158,12 +392,36 @@ pub enum ApkSignatureBlock {
         min_sdk: u32,
         max_sdk: u32,
     },
+
+    /// Source stamp identifying where the APK was built (eg. Play App Signing)
+    ///
+    /// https://source.android.com/docs/security/features/apksigning#source-stamp
+    SourceStamp {
+        /// DER encoded stamp certificate
+        certificate: Vec<u8>,
+    },
+}
+
+impl ApkSignatureBlock {
+    /// DER encoded signing certificates in this block, the source stamp is not a signer
+    pub fn certificates(&self) -> &[Vec<u8>] {
+        match self {
+            ApkSignatureBlock::Unknown { .. } | ApkSignatureBlock::SourceStamp { .. } => &[],
+            ApkSignatureBlock::V2 { certificates, .. } => certificates,
+            ApkSignatureBlock::V3 { certificates, .. } => certificates,
+        }
+    }
 }
 
 impl Display for ApkSignatureBlock {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             ApkSignatureBlock::Unknown { .. } => write!(f, "unknown"),
+            ApkSignatureBlock::SourceStamp { certificate } => write!(
+                f,
+                "stamp: cert={}",
+                hex::encode(Sha256::digest(certificate))
+            ),
             ApkSignatureBlock::V2 { signatures, .. } => {
                 write!(f, "v2: ")?;
                 for sig in signatures {
@@ -226,6 +484,24 @@ impl Display for ApkSignatureAlgo {
     }
 }
 
+/// Hash function used for the chunked content digest
+pub enum ContentDigestAlgo {
+    Sha256,
+    Sha512,
+}
+
+impl ApkSignatureAlgo {
+    /// Content digest hash function used by this signature algorithm
+    pub fn content_digest(&self) -> ContentDigestAlgo {
+        match self {
+            ApkSignatureAlgo::RsaSsaPssSha512
+            | ApkSignatureAlgo::RsaSsaPkcs1Sha512
+            | ApkSignatureAlgo::EcdsaSha512 => ContentDigestAlgo::Sha512,
+            _ => ContentDigestAlgo::Sha256,
+        }
+    }
+}
+
 impl TryFrom<u32> for ApkSignatureAlgo {
     type Error = anyhow::Error;
 
@@ -249,45 +525,50 @@ where
 {
     const SIG_BLOCK_MAGIC: &[u8] = b"APK Sig Block 42";
 
-    // scan backwards until we find the singing block
-    let flen = zip.seek(SeekFrom::End(0))?;
+    // the signing block ends with its size and magic right before the central directory,
+    // using the 64-bit offsets of ZIP64 archives
+    let cd = CentralDirectory::from_reader(zip)?;
+    if cd.offset < 32 {
+        bail!("Failed to find signing block");
+    }
     let mut magic_buf = [0u8; 16];
-    loop {
-        let magic_pos = zip.seek(SeekFrom::Current(-17))?;
-        if magic_pos <= 16 {
-            bail!("Failed to find signing block");
-        }
+    zip.seek(SeekFrom::Start(cd.offset - 24))?;
+    let size1 = zip.read_u64::<LittleEndian>()?;
+    zip.read_exact(&mut magic_buf)?;
+    if magic_buf != SIG_BLOCK_MAGIC {
+        bail!("Failed to find signing block");
+    }
+    ensure!(
+        size1 <= cd.offset - 8,
+        "Signing block is larger than entire file"
+    );
 
-        zip.read_exact(&mut magic_buf)?;
-        if magic_buf == SIG_BLOCK_MAGIC {
-            zip.seek(SeekFrom::Current(-(16 + 8)))?;
-            let size1 = zip.read_u64::<LittleEndian>()?;
-            ensure!(size1 <= flen, "Signing block is larger than entire file");
-
-            zip.seek(SeekFrom::Current(-(size1 as i64 - 8)))?;
-            let size2 = zip.read_u64::<LittleEndian>()?;
-            ensure!(
-                size2 == size1,
-                "Invalid block sizes, {} != {}",
-                size1,
-                size2
-            );
-
-            let mut data_bytes = size2 - 16 - 8;
-            let mut blocks = Vec::new();
-            loop {
-                let (k, v) = read_u64_length_prefixed_kv(zip)?;
-                data_bytes -= (v.len() + 4 + 8) as u64;
-                blocks.push((k, v));
-                if data_bytes == 0 {
-                    break;
-                }
-            }
+    let offset = zip.seek(SeekFrom::Start(cd.offset - 8 - size1))?;
+    let size2 = zip.read_u64::<LittleEndian>()?;
+    ensure!(
+        size2 == size1,
+        "Invalid block sizes, {} != {}",
+        size1,
+        size2
+    );
 
-            zip.seek(SeekFrom::Start(0))?;
-            return Ok(ApkSigningBlock { data: blocks });
+    let mut data_bytes = size2 - 16 - 8;
+    let mut blocks = Vec::new();
+    loop {
+        let (k, v) = read_u64_length_prefixed_kv(zip)?;
+        data_bytes -= (v.len() + 4 + 8) as u64;
+        blocks.push((k, v));
+        if data_bytes == 0 {
+            break;
         }
     }
+
+    zip.seek(SeekFrom::Start(0))?;
+    Ok(ApkSigningBlock {
+        data: blocks,
+        offset,
+        length: size1 + 8,
+    })
 }
 
 #[inline]
@@ -323,7 +604,7 @@ fn get_lv_u32(slice: &[u8]) -> Result<&[u8]> {
 }
 
 #[inline]
-fn take_lv_u32<'a>(slice: &mut &'a[u8]) -> Result<&'a [u8]> {
+fn take_lv_u32<'a>(slice: &mut &'a [u8]) -> Result<&'a [u8]> {
     let len = u32::from_le_bytes(slice[..4].try_into()?);
     ensure!(
         len <= (slice.len() - 4) as u32,
@@ -382,3 +663,35 @@ fn get_sequence_kv(slice: &[u8]) -> Result<Vec<(u32, &[u8])>> {
         })
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lv(data: &[u8]) -> Vec<u8> {
+        let mut ret = (data.len() as u32).to_le_bytes().to_vec();
+        ret.extend_from_slice(data);
+        ret
+    }
+
+    #[test]
+    fn parse_source_stamp() -> Result<()> {
+        let mut stamp = lv(b"stamp-cert");
+        stamp.extend(lv(&lv(b"signed-digests")));
+        let block = ApkSigningBlock {
+            data: vec![(0x6dff800d, lv(&stamp))],
+            offset: 0,
+            length: 0,
+        };
+        let sigs = block.get_signatures()?;
+        assert_eq!(sigs.len(), 1);
+        match &sigs[0] {
+            ApkSignatureBlock::SourceStamp { certificate } => {
+                assert_eq!(certificate, b"stamp-cert")
+            }
+            s => panic!("unexpected block {}", s),
+        }
+        assert!(sigs[0].certificates().is_empty());
+        Ok(())
+    }
+}
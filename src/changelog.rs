@@ -0,0 +1,216 @@
+use semver::Version;
+
+/// Release notes for a version from a markdown changelog
+///
+/// Sections start at a heading containing the version (`## [1.2.3] - 2024-01-01`, `# v1.2.3`)
+/// and end at the next heading of the same or a higher level. A file without any version
+/// headings is taken as the notes for this release as a whole.
+pub fn release_notes(changelog: &str, version: &Version) -> Option<String> {
+    let headings: Vec<(usize, usize, Option<Version>)> = changelog
+        .lines()
+        .enumerate()
+        .filter_map(|(i, l)| {
+            let level = l.chars().take_while(|c| *c == '#').count();
+            (level > 0).then(|| (i, level, heading_version(&l[level..])))
+        })
+        .collect();
+    if headings.iter().all(|(_, _, v)| v.is_none()) {
+        let notes = changelog.trim();
+        return (!notes.is_empty()).then(|| notes.to_string());
+    }
+
+    let (start, level, _) = headings
+        .iter()
+        .find(|(_, _, v)| v.as_ref() == Some(version))?;
+    let end = headings
+        .iter()
+        .find(|(i, l, _)| i > start && l <= level)
+        .map(|(i, _, _)| *i)
+        .unwrap_or(usize::MAX);
+    let notes = changelog
+        .lines()
+        .skip(start + 1)
+        .take(end.saturating_sub(start + 1))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    Some(notes.trim().to_string())
+}
+
+/// Release notes with `\r\n` line endings replaced and surrounding whitespace trimmed
+pub fn normalize_notes(notes: &str) -> String {
+    notes
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .trim()
+        .to_string()
+}
+
+/// Plain text version of markdown release notes for clients which don't render markdown
+///
+/// Headings, quotes, emphasis, code spans and HTML comments are stripped, links become
+/// `text (url)` and images their alt text. Code blocks are kept as-is.
+pub fn markdown_to_plain(markdown: &str) -> String {
+    let mut text = normalize_notes(markdown);
+    // release templates often carry hidden instructions
+    while let Some(start) = text.find("<!--") {
+        let end = text[start..]
+            .find("-->")
+            .map(|e| start + e + 3)
+            .unwrap_or(text.len());
+        text.replace_range(start..end, "");
+    }
+
+    let mut lines: Vec<String> = vec![];
+    let mut in_code = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            lines.push(line.to_string());
+            continue;
+        }
+        let rule: Vec<char> = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+        if rule.len() >= 3 && "-*_".contains(rule[0]) && rule.iter().all(|c| *c == rule[0]) {
+            // horizontal rule
+            continue;
+        }
+        let mut content = trimmed;
+        while let Some(l) = content.strip_prefix('>') {
+            content = l.trim_start();
+        }
+        let heading = content.chars().take_while(|c| *c == '#').count();
+        if heading > 0 && content[heading..].starts_with(' ') {
+            content = content[heading..].trim_start();
+        }
+        // keep the indent of nested list items
+        let indent = &line[..line.len() - trimmed.len()];
+        let item = match content.strip_prefix("* ").or(content.strip_prefix("+ ")) {
+            Some(rest) => format!("{}- {}", indent, plain_inline(rest)),
+            None => format!("{}{}", indent, plain_inline(content)),
+        };
+        if item.is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(item);
+    }
+    lines.join("\n").trim().to_string()
+}
+
+/// Strip inline markdown from one line
+fn plain_inline(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let image = rest.starts_with("![");
+        if let Some((text, url, len)) = inline_link(&rest[image as usize..]) {
+            let text = plain_inline(text);
+            match image {
+                true => out.push_str(&text),
+                false if text == url || text.is_empty() => out.push_str(url),
+                false => out.push_str(&format!("{} ({})", text, url)),
+            }
+            rest = &rest[image as usize + len..];
+        } else if let Some(r) = ["**", "__", "~~"].iter().find_map(|m| rest.strip_prefix(m)) {
+            rest = r;
+        } else {
+            if c != '`' {
+                out.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+/// `[text](url)` at the start of `s` as `(text, url, length)`
+fn inline_link(s: &str) -> Option<(&str, &str, usize)> {
+    let inner = s.strip_prefix('[')?;
+    let close = inner.find("](")?;
+    let text = &inner[..close];
+    let url_start = close + 2;
+    let url_len = inner[url_start..].find(')')?;
+    if text.contains('[') || text.contains(']') {
+        return None;
+    }
+    Some((
+        text,
+        &inner[url_start..url_start + url_len],
+        1 + url_start + url_len + 1,
+    ))
+}
+
+/// First word of a heading which is a version, ignoring brackets and a `v` prefix
+fn heading_version(heading: &str) -> Option<Version> {
+    heading.split_whitespace().find_map(|w| {
+        let w = w.trim_matches(|c| c == '[' || c == ']');
+        Version::parse(w.strip_prefix('v').unwrap_or(w)).ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGELOG: &str = r#"# Changelog
+
+## [Unreleased]
+
+## [1.2.0] - 2024-05-01
+### Added
+- Dark mode
+
+## v1.1.0
+- Fixed crash on start
+"#;
+
+    #[test]
+    fn version_sections() {
+        assert_eq!(
+            release_notes(CHANGELOG, &Version::new(1, 2, 0)).as_deref(),
+            Some("### Added\n- Dark mode")
+        );
+        assert_eq!(
+            release_notes(CHANGELOG, &Version::new(1, 1, 0)).as_deref(),
+            Some("- Fixed crash on start")
+        );
+        assert_eq!(release_notes(CHANGELOG, &Version::new(2, 0, 0)), None);
+    }
+
+    #[test]
+    fn whole_file() {
+        assert_eq!(
+            release_notes("### Fixes\n- Faster sync\n", &Version::new(1, 0, 0)).as_deref(),
+            Some("### Fixes\n- Faster sync")
+        );
+    }
+
+    #[test]
+    fn plain_notes() {
+        assert_eq!(
+            normalize_notes("\r\n## Fixes\r\n- Faster sync\r\n\r\n"),
+            "## Fixes\n- Faster sync"
+        );
+
+        let notes = "<!-- Release notes template -->\r\n## What's Changed\r\n\r\n\r\n\
+            * **Dark mode** by @alice in [#12](https://example.com/pull/12)\r\n\
+            * Fixed `sync` on ~~old~~ devices\r\n\
+            \x20 + Android 7\r\n\
+            ---\r\n\
+            > Full changelog: <https://example.com/compare>\r\n\
+            ![screenshot](https://example.com/shot.png)\r\n\
+            ```\r\n**not bold**\r\n```";
+        assert_eq!(
+            markdown_to_plain(notes),
+            "What's Changed\n\n\
+            - Dark mode by @alice in #12 (https://example.com/pull/12)\n\
+            - Fixed sync on old devices\n\
+            \x20 - Android 7\n\
+            Full changelog: <https://example.com/compare>\n\
+            screenshot\n\
+            **not bold**"
+        );
+    }
+}
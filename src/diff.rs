@@ -0,0 +1,356 @@
+use crate::repo::RepoRelease;
+use anyhow::Result;
+use indicatif::HumanBytes;
+use nostr_sdk::prelude::{hex, Coordinate};
+use nostr_sdk::{Alphabet, Client, Event, EventId, Filter, Kind, PublicKey, SingleLetterTag};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+/// Release already published on the relays
+pub struct PublishedRelease {
+    pub version: String,
+    /// Artifacts keyed by platform (`f` tag)
+    pub artifacts: BTreeMap<String, PublishedArtifact>,
+}
+
+/// File metadata of a published artifact
+pub struct PublishedArtifact {
+    pub size: Option<u64>,
+    /// Hex SHA-256 from the `x` tag
+    pub hash: Option<String>,
+}
+
+/// Version from a release `d` tag (`<app_id>@<version>`)
+pub fn release_version(ev: &Event) -> Option<&str> {
+    ev.tags
+        .identifier()
+        .and_then(|d| d.rsplit_once('@'))
+        .map(|(_, v)| v)
+}
+
+/// Value of the first tag with the given name
+fn tag_value<'a>(ev: &'a Event, name: &str) -> Option<&'a str> {
+    ev.tags.iter().find_map(|t| match t.as_slice() {
+        [n, v, ..] if n == name => Some(v.as_str()),
+        _ => None,
+    })
+}
+
+impl PublishedRelease {
+    /// Build from a release event and its file metadata events
+    pub fn from_events(release: &Event, files: &[Event]) -> PublishedRelease {
+        let artifacts = files
+            .iter()
+            .filter_map(|f| {
+                Some((
+                    tag_value(f, "f")?.to_string(),
+                    PublishedArtifact {
+                        size: tag_value(f, "size").and_then(|s| s.parse().ok()),
+                        hash: tag_value(f, "x").map(|x| x.to_string()),
+                    },
+                ))
+            })
+            .collect();
+        PublishedRelease {
+            version: release_version(release).unwrap_or("unknown").to_string(),
+            artifacts,
+        }
+    }
+
+    /// Check if the release has exactly the same files (platform and hash)
+    pub fn same_artifacts(&self, release: &RepoRelease) -> bool {
+        self.artifacts.len() == release.artifacts.len()
+            && release.artifacts.iter().all(|a| {
+                self.artifacts
+                    .get(&a.platform.to_string())
+                    .and_then(|p| p.hash.as_deref())
+                    == Some(hex::encode(&a.hash).as_str())
+            })
+    }
+}
+
+/// Fetch the latest release published by `author` for an app, or the release
+/// with the given `d` tag (`<app_id>@<version>`)
+pub async fn fetch_published_release(
+    client: &Client,
+    author: PublicKey,
+    app_coord: &Coordinate,
+    release_tag: Option<&str>,
+    timeout: Duration,
+) -> Result<Option<PublishedRelease>> {
+    let mut filter = Filter::new()
+        .kind(Kind::Custom(30_063))
+        .author(author)
+        .coordinate(app_coord);
+    if let Some(d) = release_tag {
+        filter = filter.identifier(d);
+    }
+    let releases = client.fetch_events(filter, timeout).await?;
+    let Some(release) = releases.iter().max_by_key(|e| e.created_at) else {
+        return Ok(None);
+    };
+    let ids: Vec<_> = release.tags.event_ids().copied().collect();
+    let files = if ids.is_empty() {
+        vec![]
+    } else {
+        client
+            .fetch_events(Filter::new().kind(Kind::FileMetadata).ids(ids), timeout)
+            .await?
+            .to_vec()
+    };
+    Ok(Some(PublishedRelease::from_events(release, &files)))
+}
+
+/// Find file metadata events `author` already published for the artifacts of a release, as event
+/// ids keyed by the hex SHA-256 (`x` tag)
+pub async fn fetch_file_events(
+    client: &Client,
+    author: PublicKey,
+    release: &RepoRelease,
+    timeout: Duration,
+) -> Result<HashMap<String, EventId>> {
+    let hashes: Vec<String> = release
+        .artifacts
+        .iter()
+        .map(|a| hex::encode(&a.hash))
+        .collect();
+    let filter = Filter::new()
+        .kind(Kind::FileMetadata)
+        .author(author)
+        .custom_tags(SingleLetterTag::lowercase(Alphabet::X), hashes);
+    Ok(file_events_by_hash(
+        client.fetch_events(filter, timeout).await?.iter(),
+    ))
+}
+
+/// Newest file metadata event id for each `x` hash
+fn file_events_by_hash<'a>(events: impl Iterator<Item = &'a Event>) -> HashMap<String, EventId> {
+    let mut newest: HashMap<String, &Event> = HashMap::new();
+    for ev in events {
+        let Some(x) = tag_value(ev, "x") else {
+            continue;
+        };
+        if newest.get(x).is_none_or(|e| e.created_at < ev.created_at) {
+            newest.insert(x.to_string(), ev);
+        }
+    }
+    newest.into_iter().map(|(x, ev)| (x, ev.id)).collect()
+}
+
+/// Fetch the file metadata events of the releases to retract which no other release of `author`
+/// still references, newer releases reuse the file events of unchanged artifacts
+pub async fn fetch_unshared_file_events(
+    client: &Client,
+    author: PublicKey,
+    retracted: &[Event],
+    timeout: Duration,
+) -> Result<Vec<EventId>> {
+    let releases = client
+        .fetch_events(
+            Filter::new().kind(Kind::Custom(30_063)).author(author),
+            timeout,
+        )
+        .await?;
+    Ok(unshared_file_events(retracted, releases.iter()))
+}
+
+/// File event ids referenced by the `retracted` releases and by none of the other `releases`
+fn unshared_file_events<'a>(
+    retracted: &[Event],
+    releases: impl Iterator<Item = &'a Event>,
+) -> Vec<EventId> {
+    let retracted_tags: HashSet<&str> = retracted
+        .iter()
+        .filter_map(|r| r.tags.identifier())
+        .collect();
+    let shared: HashSet<EventId> = releases
+        .filter(|r| {
+            !r.tags
+                .identifier()
+                .is_some_and(|d| retracted_tags.contains(d))
+        })
+        .flat_map(|r| r.tags.event_ids().copied())
+        .collect();
+    let mut ret = vec![];
+    for id in retracted.iter().flat_map(|r| r.tags.event_ids()) {
+        if !shared.contains(id) && !ret.contains(id) {
+            ret.push(*id);
+        }
+    }
+    ret
+}
+
+/// Describe the changes of a release against the published one, one line per change
+pub fn release_diff(published: &PublishedRelease, release: &RepoRelease) -> Vec<String> {
+    let mut ret = vec![];
+    let version = release.version.to_string();
+    if published.version == version {
+        ret.push(format!("version: {} (already published)", version));
+    } else {
+        ret.push(format!("version: {} -> {}", published.version, version));
+    }
+
+    let current: BTreeMap<String, u64> = release
+        .artifacts
+        .iter()
+        .map(|a| (a.platform.to_string(), a.size))
+        .collect();
+    for (platform, size) in &current {
+        match published.artifacts.get(platform) {
+            None => ret.push(format!("+ {} ({})", platform, HumanBytes(*size))),
+            Some(PublishedArtifact {
+                size: Some(old), ..
+            }) if old != size => ret.push(format!(
+                "~ {}: {} -> {} ({}{})",
+                platform,
+                HumanBytes(*old),
+                HumanBytes(*size),
+                if size > old { "+" } else { "-" },
+                HumanBytes(size.abs_diff(*old))
+            )),
+            _ => {}
+        }
+    }
+    for platform in published.artifacts.keys() {
+        if !current.contains_key(platform) {
+            ret.push(format!("- {}", platform));
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{Architecture, ArtifactMetadata, Platform, RepoArtifact, RepoResource};
+    use apk_parser::AndroidManifest;
+    use nostr_sdk::{EventBuilder, Keys, Tag, Timestamp};
+    use semver::Version;
+
+    fn file_event(keys: &Keys, platform: &str, size: u64) -> Result<Event> {
+        Ok(EventBuilder::new(Kind::FileMetadata, "")
+            .tags([
+                Tag::parse(["f", platform])?,
+                Tag::parse(["size", &size.to_string()])?,
+                Tag::parse(["x", &hex::encode([0; 32])])?,
+            ])
+            .sign_with_keys(keys)?)
+    }
+
+    fn artifact(arch: Architecture, size: u64) -> RepoArtifact {
+        RepoArtifact {
+            name: "app.apk".to_string(),
+            size,
+            location: RepoResource::Remote("https://example.com/app.apk".to_string()),
+            content_type: "application/vnd.android.package-archive".to_string(),
+            platform: Platform::Android { arch },
+            metadata: ArtifactMetadata::APK {
+                manifest: AndroidManifest::default(),
+                signature_blocks: vec![],
+                labels: vec![],
+                extras: Default::default(),
+                page_size_16kb: None,
+            },
+            hash: vec![0; 32],
+            download_path: None,
+            downloads: None,
+        }
+    }
+
+    #[test]
+    fn diff_against_published() -> Result<()> {
+        let keys = Keys::generate();
+        let release_ev = EventBuilder::new(Kind::Custom(30_063), "")
+            .tag(Tag::parse(["d", "com.example.app@1.0.0"])?)
+            .sign_with_keys(&keys)?;
+        let published = PublishedRelease::from_events(
+            &release_ev,
+            &[
+                file_event(&keys, "android-arm64-v8a", 1000)?,
+                file_event(&keys, "android-x86", 1000)?,
+            ],
+        );
+        let release = RepoRelease {
+            version: Version::new(1, 1, 0),
+            description: None,
+            url: None,
+            artifacts: vec![
+                artifact(Architecture::ARM64, 1500),
+                artifact(Architecture::X86_64, 2000),
+            ],
+            manifest_id: None,
+            published_at: None,
+        };
+        assert_eq!(
+            release_diff(&published, &release),
+            [
+                "version: 1.0.0 -> 1.1.0",
+                "~ android-arm64-v8a: 1000 B -> 1.46 KiB (+500 B)",
+                "+ android-x86_64 (1.95 KiB)",
+                "- android-x86",
+            ]
+        );
+        assert!(!published.same_artifacts(&release));
+
+        let published = PublishedRelease::from_events(
+            &release_ev,
+            &[
+                file_event(&keys, "android-arm64-v8a", 1500)?,
+                file_event(&keys, "android-x86_64", 2000)?,
+            ],
+        );
+        assert!(published.same_artifacts(&release));
+        Ok(())
+    }
+
+    #[test]
+    fn newest_file_event_by_hash() -> Result<()> {
+        let keys = Keys::generate();
+        let file = |x: &str, created_at: u64| -> Result<Event> {
+            Ok(EventBuilder::new(Kind::FileMetadata, "")
+                .tag(Tag::parse(["x", x])?)
+                .custom_created_at(Timestamp::from(created_at))
+                .sign_with_keys(&keys)?)
+        };
+        let old = file("aa", 1)?;
+        let new = file("aa", 2)?;
+        let other = file("bb", 1)?;
+        let found = file_events_by_hash([&new, &other, &old].into_iter());
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["aa"], new.id);
+        assert_eq!(found["bb"], other.id);
+        Ok(())
+    }
+
+    #[test]
+    fn keep_shared_file_events() -> Result<()> {
+        let keys = Keys::generate();
+        let file = |platform: &str| file_event(&keys, platform, 1000);
+        let (arm64, x86, x86_64) = (
+            file("android-arm64-v8a")?,
+            file("android-x86")?,
+            file("android-x86_64")?,
+        );
+        let release = |version: &str, files: &[&Event]| -> Result<Event> {
+            Ok(EventBuilder::new(Kind::Custom(30_063), "")
+                .tag(Tag::parse(["d", &format!("com.example.app@{version}")])?)
+                .tags(files.iter().map(|f| Tag::event(f.id)))
+                .sign_with_keys(&keys)?)
+        };
+        // 1.1.0 reuses the unchanged arm64 file event of 1.0.0
+        let old = release("1.0.0", &[&arm64, &x86])?;
+        let new = release("1.1.0", &[&arm64, &x86_64])?;
+
+        let retracted = [old.clone()];
+        assert_eq!(
+            unshared_file_events(&retracted, [&old, &new].into_iter()),
+            [x86.id]
+        );
+        let retracted = [new.clone()];
+        assert_eq!(
+            unshared_file_events(&retracted, [&old, &new].into_iter()),
+            [x86_64.id]
+        );
+        Ok(())
+    }
+}
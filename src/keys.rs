@@ -0,0 +1,128 @@
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
+use nostr_sdk::prelude::{EncryptedSecretKey, FromBech32, ToBech32};
+use nostr_sdk::{Keys, PublicKey};
+use std::path::Path;
+
+/// Environment variable holding the passphrase for an encrypted key file
+pub const KEY_PASSPHRASE_ENV: &str = "NAP_KEY_PASSPHRASE";
+
+/// Environment variable holding the passphrase for the release key file
+pub const RELEASE_KEY_PASSPHRASE_ENV: &str = "NAP_RELEASE_KEY_PASSPHRASE";
+
+/// OS keychain service and account the key is stored under by `nap login`
+const KEYRING_SERVICE: &str = "nap";
+const KEYRING_USER: &str = "nsec";
+
+/// Load the signing keys from a NIP-49 key file, the OS keychain, or prompt for the nsec
+pub fn load_keys(key_file: Option<&Path>) -> Result<Keys> {
+    match key_file {
+        Some(path) => load_key_file(path, KEY_PASSPHRASE_ENV),
+        None => match stored_keys() {
+            Some(keys) => {
+                info!("Using key {} from the OS keychain", keys.public_key);
+                Ok(keys)
+            }
+            None => prompt_keys(),
+        },
+    }
+}
+
+/// Load the separate key release events are signed with from a NIP-49 key file
+pub fn load_release_keys(path: &Path) -> Result<Keys> {
+    load_key_file(path, RELEASE_KEY_PASSPHRASE_ENV)
+}
+
+/// Prompt for the nsec and store it in the OS keychain
+pub fn login() -> Result<PublicKey> {
+    let keys = prompt_keys()?;
+    keyring_entry()?.set_password(&keys.secret_key().to_bech32()?)?;
+    Ok(keys.public_key)
+}
+
+/// Remove the stored key from the OS keychain, false if there was none
+pub fn logout() -> Result<bool> {
+    match keyring_entry()?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn keyring_entry() -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?)
+}
+
+/// Key saved with `nap login`, if any
+fn stored_keys() -> Option<Keys> {
+    let secret = match keyring_entry().and_then(|e| Ok(e.get_password()?)) {
+        Ok(s) => s,
+        Err(e) => {
+            if !matches!(e.downcast_ref(), Some(keyring::Error::NoEntry)) {
+                // no keychain available is normal in CI
+                debug!("Failed to read key from the OS keychain: {}", e);
+            }
+            return None;
+        }
+    };
+    match Keys::parse(&secret) {
+        Ok(keys) => Some(keys),
+        Err(e) => {
+            warn!("Invalid key in the OS keychain: {}", e);
+            None
+        }
+    }
+}
+
+/// Prompt for the nsec used to sign events
+fn prompt_keys() -> Result<Keys> {
+    let key = dialoguer::Password::new()
+        .with_prompt("Enter nsec:")
+        .interact()?;
+
+    if let Ok(nsec) = Keys::parse(&key) {
+        Ok(nsec)
+    } else {
+        bail!("Invalid private key")
+    }
+}
+
+/// Decrypt an `ncryptsec` (NIP-49) key file, with the passphrase from `passphrase_env` or prompted
+fn load_key_file(path: &Path, passphrase_env: &str) -> Result<Keys> {
+    let data = std::fs::read_to_string(path)?;
+    let encrypted = match EncryptedSecretKey::from_bech32(data.trim()) {
+        Ok(k) => k,
+        Err(e) => bail!("Invalid ncryptsec in {}: {}", path.display(), e),
+    };
+    let passphrase = match std::env::var(passphrase_env) {
+        Ok(p) => p,
+        Err(_) => dialoguer::Password::new()
+            .with_prompt(format!("Enter passphrase for {}:", path.display()))
+            .interact()?,
+    };
+    match encrypted.to_secret_key(passphrase) {
+        Ok(sk) => Ok(Keys::new(sk)),
+        Err(_) => bail!("Failed to decrypt key, wrong passphrase?"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::KeySecurity;
+
+    #[test]
+    fn decrypt_key_file() -> Result<()> {
+        let keys = Keys::generate();
+        let encrypted =
+            EncryptedSecretKey::new(keys.secret_key(), "hunter2", 4, KeySecurity::Unknown)?;
+        let path = std::env::temp_dir().join(format!("nap-test-{}.ncryptsec", keys.public_key));
+        std::fs::write(&path, encrypted.to_bech32()?)?;
+
+        std::env::set_var(KEY_PASSPHRASE_ENV, "hunter2");
+        let loaded = load_key_file(&path, KEY_PASSPHRASE_ENV);
+        std::fs::remove_file(&path)?;
+        assert_eq!(loaded?.public_key, keys.public_key);
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+//! Nostr App Publisher, publishes app releases as NIP-82 / NIP-94 events
+//!
+//! The `nap` binary is a CLI around this crate, other release tools can load a [Manifest], fetch
+//! releases with [repo::repo_from_manifest] and publish them with [build_release_events] and
+//! [publish_events].
+
+pub mod changelog;
+pub mod diff;
+pub mod keys;
+pub mod manifest;
+pub mod relay;
+pub mod repo;
+pub mod timings;
+pub mod validate;
+
+use crate::manifest::{render_template, Manifest};
+use crate::repo::{EventOptions, RepoRelease};
+use crate::timings::Timings;
+use crate::validate::validate_events;
+use anyhow::{bail, Result};
+use log::{error, warn};
+use nostr_sdk::prelude::{hex, Coordinate, ToBech32};
+use nostr_sdk::{Client, Event, EventId, Kind, NostrSigner};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Sign the app event, the release event and the file events of a release
+pub async fn build_release_events<T: NostrSigner>(
+    manifest: &Manifest,
+    release: &RepoRelease,
+    signer: &T,
+) -> Result<Vec<Event>> {
+    sign_release_events(
+        manifest,
+        release,
+        signer,
+        signer,
+        &EventOptions::from_manifest(manifest),
+    )
+    .await
+}
+
+/// Sign the app event with `app_signer` and the release and file events with `release_signer`,
+/// the app event comes first
+pub async fn sign_release_events<A: NostrSigner, R: NostrSigner>(
+    manifest: &Manifest,
+    release: &RepoRelease,
+    app_signer: &A,
+    release_signer: &R,
+    opts: &EventOptions,
+) -> Result<Vec<Event>> {
+    let app_coord = Coordinate::new(Kind::Custom(32_267), app_signer.get_public_key().await?)
+        .identifier(release.app_id()?);
+    let mut rendered = release.clone();
+    if let Some(notes) = &release.description {
+        rendered.description = Some(render_template(
+            notes,
+            &manifest.template_vars(Some(release)),
+        ));
+    }
+    let release_list = rendered
+        .into_release_list_event(release_signer, app_coord, opts)
+        .await?;
+    let release_coord =
+        Coordinate::new(Kind::Custom(30_063), release_signer.get_public_key().await?)
+            .identifier(release.release_tag()?);
+    let app_ev = manifest
+        .app_event(release, release_coord)
+        .sign(app_signer)
+        .await?;
+
+    let mut events = vec![app_ev];
+    events.extend(release_list);
+    Ok(events)
+}
+
+/// Relays which accepted or rejected a published event
+#[derive(Debug, Clone, Serialize)]
+pub struct PublishStatus {
+    pub id: EventId,
+    pub kind: Kind,
+    pub accepted: Vec<String>,
+    /// Error message by relay URL
+    pub rejected: BTreeMap<String, String>,
+}
+
+impl PublishStatus {
+    /// Status of an event which was not sent yet
+    pub fn new(event: &Event) -> Self {
+        PublishStatus {
+            id: event.id,
+            kind: event.kind,
+            accepted: vec![],
+            rejected: BTreeMap::new(),
+        }
+    }
+}
+
+/// Validate the events and send them to the relays of the client, nothing is sent if any event
+/// fails validation
+///
+/// Events are sent one at a time in [publish_order], each waiting for the relays to acknowledge
+/// it, so the ids and coordinates an event references were stored before it arrives.
+///
+/// Every event is attempted even when sending an earlier one failed. Events no relay accepted
+/// are logged, it is an error only if that was the app or release event, a missing file event
+/// leaves the rest of the release usable.
+///
+/// With `timings` enabled the events are sent to one relay at a time, so the time spent on each
+/// relay is recorded on its own.
+pub async fn publish_events(
+    client: &Client,
+    events: Vec<Event>,
+    timings: &Timings,
+) -> Result<Vec<PublishStatus>> {
+    validate_events(&events)?;
+    let mut events = events;
+    events.sort_by_key(|e| publish_order(e.kind));
+    let mut ret = vec![];
+    for ev in events {
+        let mut status = PublishStatus::new(&ev);
+        if !timings.is_enabled() {
+            match client.send_event(ev).await {
+                Ok(output) => {
+                    status.accepted = output.success.iter().map(|u| u.to_string()).collect();
+                    status.accepted.sort();
+                    status.rejected = output
+                        .failed
+                        .iter()
+                        .map(|(u, e)| (u.to_string(), e.clone()))
+                        .collect();
+                }
+                // no relay accepted it, the per relay errors are not returned
+                Err(e) => {
+                    for url in client.relays().await.keys() {
+                        status.rejected.insert(url.to_string(), e.to_string());
+                    }
+                }
+            }
+        } else {
+            for (url, relay) in client.relays().await {
+                match timings
+                    .time(format!("publish {}", url), relay.send_event(ev.clone()))
+                    .await
+                {
+                    Ok(_) => status.accepted.push(url.to_string()),
+                    Err(e) => {
+                        status.rejected.insert(url.to_string(), e.to_string());
+                    }
+                }
+            }
+        }
+        for (url, e) in &status.rejected {
+            warn!("Failed to publish {} to {}: {}", status.id, url, e);
+        }
+        ret.push(status);
+    }
+
+    let failed: Vec<&PublishStatus> = ret.iter().filter(|s| s.accepted.is_empty()).collect();
+    for status in &failed {
+        error!(
+            "No relay accepted the kind {} event {}",
+            status.kind.as_u16(),
+            status.id
+        );
+    }
+    if let Some(status) = failed.iter().find(|s| is_critical_event(s.kind)) {
+        bail!(
+            "Failed to publish the kind {} event {}, {} of {} event(s) failed",
+            status.kind.as_u16(),
+            status.id,
+            failed.len(),
+            ret.len()
+        );
+    }
+    Ok(ret)
+}
+
+/// Position of an event kind when publishing: the file metadata events first, then the release
+/// event listing them by id, then the app event pointing at the release, anything else last
+pub fn publish_order(kind: Kind) -> u8 {
+    match kind {
+        Kind::FileMetadata => 0,
+        Kind::Custom(30_063) => 1,
+        Kind::Custom(32_267) => 2,
+        _ => 3,
+    }
+}
+
+/// The app and release events, without them the published file events can't be found
+fn is_critical_event(kind: Kind) -> bool {
+    kind == Kind::Custom(32_267) || kind == Kind::Custom(30_063)
+}
+
+/// Machine readable result of publishing a release, for `--json-summary`
+///
+/// `status` is `published`, `signed` (written to a file) or `unchanged` (already published).
+pub fn release_summary(
+    release: &RepoRelease,
+    app_coord: &Coordinate,
+    release_coord: &Coordinate,
+    status: &str,
+    events: &[PublishStatus],
+) -> Result<serde_json::Value> {
+    let artifacts: Vec<serde_json::Value> = release
+        .artifacts
+        .iter()
+        .map(|a| {
+            serde_json::json!({
+                "name": a.name,
+                "platform": a.platform.to_string(),
+                "size": a.size,
+                "sha256": hex::encode(&a.hash),
+            })
+        })
+        .collect();
+    Ok(serde_json::json!({
+        "app": release.app_id()?,
+        "status": status,
+        "version": release.version.to_string(),
+        "coordinate": app_coord.to_string(),
+        "naddr": app_coord.to_bech32()?,
+        "release_coordinate": release_coord.to_string(),
+        "release_naddr": release_coord.to_bech32()?,
+        "events": events,
+        "artifacts": artifacts,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{Platform, RepoArtifact, RepoResource};
+    use config::{Config, File, FileFormat};
+    use nostr_sdk::Keys;
+    use semver::Version;
+
+    #[tokio::test]
+    async fn release_events() -> Result<()> {
+        let manifest: Manifest = Config::builder()
+            .add_source(File::from_str(
+                "id: \"com.example.app\"\nname: \"Example\"\ntags: []",
+                FileFormat::Yaml,
+            ))
+            .build()?
+            .try_deserialize()?;
+        let release = RepoRelease {
+            version: Version::new(1, 0, 0),
+            description: None,
+            url: None,
+            artifacts: vec![RepoArtifact {
+                name: "app.zip".to_string(),
+                size: 1,
+                location: RepoResource::Remote("https://example.com/app.zip".to_string()),
+                content_type: "application/zip".to_string(),
+                platform: Platform::Web,
+                metadata: repo::ArtifactMetadata::Web {
+                    manifest: serde_json::from_str("{}")?,
+                },
+                hash: vec![0; 32],
+                download_path: None,
+                downloads: None,
+            }],
+            manifest_id: Some("com.example.app".to_string()),
+            published_at: None,
+        };
+        let keys = Keys::generate();
+        let events = build_release_events(&manifest, &release, &keys).await?;
+        let kinds: Vec<Kind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                Kind::Custom(32_267),
+                Kind::FileMetadata,
+                Kind::Custom(30_063)
+            ]
+        );
+        validate_events(&events)?;
+
+        let app_coord =
+            Coordinate::new(Kind::Custom(32_267), keys.public_key).identifier("com.example.app");
+        let release_coord = Coordinate::new(Kind::Custom(30_063), keys.public_key)
+            .identifier(release.release_tag()?);
+        let mut status = PublishStatus::new(&events[2]);
+        status.accepted.push("wss://relay.example.com".to_string());
+        status.rejected.insert(
+            "wss://relay.other.com".to_string(),
+            "blocked: not allowed".to_string(),
+        );
+        let summary =
+            release_summary(&release, &app_coord, &release_coord, "published", &[status])?;
+        assert_eq!(summary["app"], "com.example.app");
+        assert_eq!(summary["version"], "1.0.0");
+        assert!(summary["naddr"].as_str().unwrap().starts_with("naddr1"));
+        assert_eq!(
+            summary["release_coordinate"],
+            format!("30063:{}:com.example.app@1.0.0", keys.public_key)
+        );
+        assert_eq!(
+            summary["events"],
+            serde_json::json!([{
+                "id": events[2].id.to_hex(),
+                "kind": 30_063,
+                "accepted": ["wss://relay.example.com"],
+                "rejected": {"wss://relay.other.com": "blocked: not allowed"},
+            }])
+        );
+        assert_eq!(summary["artifacts"][0]["sha256"], hex::encode([0; 32]));
+
+        let mut ordered = events.clone();
+        ordered.sort_by_key(|e| publish_order(e.kind));
+        let kinds: Vec<Kind> = ordered.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                Kind::FileMetadata,
+                Kind::Custom(30_063),
+                Kind::Custom(32_267)
+            ]
+        );
+
+        // nothing listens there, every event is attempted and the release event failure is fatal
+        let client = Client::default();
+        client.add_relay("ws://127.0.0.1:1").await?;
+        let err = publish_events(&client, events.clone(), &Timings::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Failed to publish the kind 30063 event {}, 3 of 3 event(s) failed",
+                events[2].id
+            )
+        );
+        assert!(!is_critical_event(Kind::FileMetadata));
+        Ok(())
+    }
+}
@@ -1,26 +1,248 @@
-mod manifest;
-mod repo;
-
-use crate::manifest::Manifest;
-use crate::repo::Repo;
 use anyhow::{anyhow, bail, Result};
-use clap::Parser;
-use config::{Config, File};
-use log::info;
-use nostr_sdk::prelude::Coordinate;
-use nostr_sdk::{Client, EventBuilder, Keys, Kind, Tag};
-use std::path::PathBuf;
+use clap::{CommandFactory, Parser};
+use config::{Config, ConfigError, File, FileFormat};
+use log::{error, info, warn};
+use nap::changelog::release_notes;
+use nap::diff::{
+    fetch_file_events, fetch_published_release, fetch_unshared_file_events, release_diff,
+    release_version,
+};
+use nap::keys::{load_keys, load_release_keys, login, logout};
+use nap::manifest::{check_lud16, interpolate_env, Manifest};
+use nap::relay::{
+    connect_authenticated_client, connect_client, merge_relays, nip65_write_relays, DEFAULT_RELAY,
+};
+use nap::repo::{
+    http_client, repo_from_manifest, AssetFilter, EventOptions, LoadOptions, Platform, RepoRelease,
+    ARTIFACT_FORMATS, REQUIRED_FILE_TAGS,
+};
+use nap::timings::Timings;
+use nap::validate::validate_events;
+use nap::{publish_events, release_summary, sign_release_events, PublishStatus};
+use nostr_sdk::prelude::{Coordinate, EventIdOrCoordinate, JsonUtil, ToBech32};
+use nostr_sdk::{Client, Event, EventBuilder, Filter, Keys, Kind, PublicKey};
+use semver::Version;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long to wait for relays to return stored events
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(clap::Parser)]
 #[command(version, about)]
 struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// User specified config path
-    #[arg(long, short)]
+    #[arg(long, short, global = true)]
     pub config: Option<PathBuf>,
 
     /// Relay to publish events to
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub relay: Vec<String>,
+
+    /// Seconds to wait for a relay connection before giving up
+    #[arg(long, global = true, default_value_t = 10)]
+    pub relay_timeout: u64,
+
+    /// Also publish to the write relays from the signer's NIP-65 relay list
+    #[arg(long, global = true)]
+    pub use_nip65: bool,
+
+    /// NIP-49 encrypted key (ncryptsec) file used for signing, the passphrase is read from
+    /// NAP_KEY_PASSPHRASE or prompted
+    #[arg(long, global = true)]
+    pub key_file: Option<PathBuf>,
+
+    /// NIP-49 encrypted key file to sign the release and file events with, overrides the
+    /// manifest, the passphrase is read from NAP_RELEASE_KEY_PASSPHRASE or prompted
+    #[arg(long, global = true)]
+    pub release_key_file: Option<PathBuf>,
+
+    /// Only operate on the app with this id from a multi-app config
+    #[arg(long, global = true)]
+    pub app: Option<String>,
+
+    /// Verify APK signature digests against the file contents before publishing
+    #[arg(long)]
+    pub verify_signatures: bool,
+
+    /// GitHub API base URL for GitHub Enterprise, overrides the manifest
+    #[arg(long)]
+    pub github_api: Option<String>,
+
+    /// Only publish releases with tags starting with this prefix, overrides the manifest
+    #[arg(long)]
+    pub tag_prefix: Option<String>,
+
+    /// Seconds an HTTP request may go without receiving data before it is aborted
+    #[arg(long, default_value_t = 60)]
+    pub http_timeout: u64,
+
+    /// Proxy URL for all HTTP requests, overrides HTTPS_PROXY / HTTP_PROXY
+    #[arg(long)]
+    pub proxy: Option<String>,
+
+    /// Directory to download artifacts into
+    #[arg(long)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Keep downloaded artifacts after publishing
+    #[arg(long)]
+    pub keep_downloads: bool,
+
+    /// Fail when manifest tags are not a known category
+    #[arg(long)]
+    pub strict_tags: bool,
+
+    /// Fail instead of warning when an APK is debuggable
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Publish new file metadata events for artifacts which were published before, instead of
+    /// referencing the existing events with the same hash
+    #[arg(long)]
+    pub republish_files: bool,
+
+    /// Write the signed events to this file as a JSON array instead of publishing them
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Write the parsed APK manifests of the release artifacts to this file as JSON
+    #[arg(long)]
+    pub emit_manifest_json: Option<PathBuf>,
+
+    /// Write a JSON summary of the published releases to this file: the app and release
+    /// coordinates (naddr), event ids with the relays that accepted or rejected them and the
+    /// artifact hashes
+    #[arg(long)]
+    pub json_summary: Option<PathBuf>,
+
+    /// Pick which of the release artifacts to publish
+    #[arg(long)]
+    pub select_artifacts: bool,
+
+    /// Stop downloading assets of a release after this many artifacts, applied after the
+    /// include/exclude filters
+    #[arg(long, alias = "limit")]
+    pub max_artifacts_per_release: Option<usize>,
+
+    /// Read APKs with HTTP range requests, fetching only the signing block, manifest and
+    /// resources instead of downloading the whole file (it is still streamed once for the hash,
+    /// without being stored)
+    #[arg(long)]
+    pub range_requests: bool,
+
+    /// Only download release assets matching this glob (`*.apk`), on top of the manifest
+    /// include/exclude lists
+    #[arg(long)]
+    pub asset_glob: Option<String>,
+
+    /// Don't prompt, publish all artifacts without confirmation
+    #[arg(long, short, global = true)]
+    pub yes: bool,
+
+    /// Allow publishing APKs signed with the Android debug certificate
+    #[arg(long)]
+    pub allow_debug_signature: bool,
+
+    /// Skip artifacts whose package name does not match the app id instead of failing
+    #[arg(long)]
+    pub skip_id_mismatch: bool,
+
+    /// Publish even if the same release with the same files is already published
+    #[arg(long)]
+    pub force: bool,
+
+    /// Publish every release of the repo, oldest first, instead of only the latest one
+    #[arg(long)]
+    pub all: bool,
+
+    /// Date the release events with the release publish date instead of now,
+    /// for backfilling old releases
+    #[arg(long)]
+    pub preserve_dates: bool,
+
+    /// Leave an optional NIP-94 tag out of the file events, added to `omit_tags` from the manifest
+    #[arg(long)]
+    pub omit_tag: Vec<String>,
+
+    /// Print the time spent fetching, downloading, parsing, signing and publishing (per relay)
+    /// when done
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Log output format
+    #[arg(long, env = "NAP_LOG_FORMAT", default_value = "text")]
+    pub log_format: LogFormat,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Retract a published release with NIP-09 deletion requests
+    Retract {
+        /// Version of the release to retract
+        #[arg(long)]
+        release_version: Version,
+    },
+
+    /// List the releases already published for this app
+    Status {
+        /// Publisher public key (npub/hex), prompts for the nsec when not set
+        #[arg(long)]
+        pubkey: Option<String>,
+    },
+
+    /// Store the signing key in the OS keychain, used instead of prompting for the nsec
+    Login,
+
+    /// Remove the signing key from the OS keychain
+    Logout,
+
+    /// Check the manifest for problems without publishing anything
+    Validate,
+
+    /// List the artifact formats nap can read and the platforms it can tag
+    Formats,
+
+    /// Send previously signed events (from --output) to the relays
+    Broadcast {
+        /// JSON file containing an array of signed events
+        events: PathBuf,
+    },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+enum LogFormat {
+    /// Human-readable log lines
+    Text,
+    /// Newline-delimited JSON records
+    Json,
+}
+
+fn init_logger(format: LogFormat) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "timestamp": buf.timestamp().to_string(),
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
 }
 
 #[tokio::main]
@@ -29,90 +251,768 @@ async fn main() -> Result<()> {
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", "info");
     }
-    env_logger::init();
-
     let args = Args::parse();
+    init_logger(args.log_format);
+
+    match &args.command {
+        None => publish(&args, &load_manifests(&args)?).await,
+        Some(Command::Retract { release_version }) => {
+            retract(&args, &single_app(load_manifests(&args)?)?, release_version).await
+        }
+        Some(Command::Status { pubkey }) => {
+            status(&args, &load_manifests(&args)?, pubkey.as_deref()).await
+        }
+        Some(Command::Broadcast { events }) => broadcast(&args, events).await,
+        Some(Command::Validate) => validate(&args),
+        Some(Command::Formats) => {
+            formats();
+            Ok(())
+        }
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(*shell, &mut Args::command(), "nap", &mut std::io::stdout());
+            Ok(())
+        }
+        Some(Command::Login) => {
+            let pubkey = login()?;
+            info!("Stored key {} in the OS keychain", pubkey.to_bech32()?);
+            Ok(())
+        }
+        Some(Command::Logout) => {
+            if logout()? {
+                info!("Removed key from the OS keychain");
+            } else {
+                info!("No key stored in the OS keychain");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Manifest files searched for when no config path is given, in order
+const DEFAULT_CONFIGS: &[&str] = &["nap.yaml", "nap.yml", "nap.toml", "nap.json"];
+
+fn config_path(args: &Args) -> PathBuf {
+    args.config.clone().unwrap_or_else(|| {
+        DEFAULT_CONFIGS
+            .iter()
+            .map(PathBuf::from)
+            .find(|p| p.exists())
+            .unwrap_or(PathBuf::from(DEFAULT_CONFIGS[0]))
+    })
+}
+
+/// Detect the manifest format from the file extension
+fn config_format(path: &Path) -> Result<FileFormat> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => Ok(FileFormat::Yaml),
+        Some("toml") => Ok(FileFormat::Toml),
+        Some("json") => Ok(FileFormat::Json),
+        _ => bail!(
+            "Unknown config format {}, expected .yaml, .yml, .toml or .json",
+            path.display()
+        ),
+    }
+}
 
-    let manifest: Manifest = Config::builder()
-        .add_source(File::from(args.config.unwrap_or(PathBuf::from("nap.yaml"))))
+/// Load the app manifests from the config, either a single app or a list under `apps`
+fn load_manifests(args: &Args) -> Result<Vec<Manifest>> {
+    let path = config_path(args);
+    let format = config_format(&path)?;
+    let source = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow!("Failed to load config {}: {}", path.display(), e))?;
+    let config = Config::builder()
+        .add_source(File::from_str(&interpolate_env(&source)?, format))
         .build()
-        .map_err(|e| anyhow!("Failed to load config: {}", e))?
-        .try_deserialize()?;
+        .map_err(|e| anyhow!("Failed to load config: {}", e))?;
+    let mut apps = match config.get::<Vec<Manifest>>("apps") {
+        Err(ConfigError::NotFound(_)) => vec![config
+            .try_deserialize()
+            .map_err(|e| anyhow!("Failed to parse config: {}", e))?],
+        r => r.map_err(|e| anyhow!("Failed to parse config: {}", e))?,
+    };
+    if let Some(id) = &args.app {
+        apps.retain(|m| m.id == *id);
+        if apps.is_empty() {
+            bail!("App {} not found in {}", id, path.display());
+        }
+    }
+    Ok(apps)
+}
 
-    let repo: Box<dyn Repo> = (&manifest).try_into()?;
+/// Commands which only work on one app need it selected with --app in multi-app configs
+fn single_app(mut manifests: Vec<Manifest>) -> Result<Manifest> {
+    if manifests.len() != 1 {
+        bail!(
+            "The config contains {} apps, select one with --app",
+            manifests.len()
+        );
+    }
+    Ok(manifests.remove(0))
+}
 
-    let releases = repo.get_releases().await?;
+/// Keys and relay connection shared by all apps published in one run
+#[derive(Default)]
+struct Session {
+    keys: Option<Keys>,
+    /// Release signing keys by key file
+    release_keys: HashMap<PathBuf, Keys>,
+    client: Option<Client>,
+    /// Signed events collected for --output
+    events: Vec<Event>,
+    /// HTTP client for repo requests and downloads
+    http: reqwest::Client,
+    /// Parsed artifact manifests collected for --emit-manifest-json
+    manifests: Vec<serde_json::Value>,
+    /// Phase durations for --timings
+    timings: Timings,
+    /// Published releases collected for --json-summary
+    summary: Vec<serde_json::Value>,
+}
+
+impl Session {
+    /// Signing keys, only prompted for once
+    fn keys(&mut self, args: &Args) -> Result<Keys> {
+        if let Some(keys) = &self.keys {
+            return Ok(keys.clone());
+        }
+        Ok(self
+            .keys
+            .insert(load_keys(args.key_file.as_deref())?)
+            .clone())
+    }
+
+    /// Keys for the release events, the main keys unless a release key file is set
+    fn release_keys(&mut self, args: &Args, manifest: &Manifest) -> Result<Keys> {
+        let Some(path) = args
+            .release_key_file
+            .as_ref()
+            .or(manifest.release_key_file.as_ref())
+        else {
+            return self.keys(args);
+        };
+        if let Some(keys) = self.release_keys.get(path) {
+            return Ok(keys.clone());
+        }
+        let keys = load_release_keys(path)?;
+        info!("Signing releases with {}", keys.public_key);
+        self.release_keys.insert(path.clone(), keys.clone());
+        Ok(keys)
+    }
+
+    /// Relay connection, connected on first use
+    async fn client(&mut self, args: &Args) -> Result<&Client> {
+        if self.client.is_none() {
+            let keys = self.keys(args)?;
+            let relays = publish_relays(args, keys.public_key).await?;
+            let client = connect_authenticated_client(&relays, relay_timeout(args), keys).await?;
+            self.client = Some(client);
+        }
+        Ok(self.client.as_ref().unwrap())
+    }
+
+    /// Send events to the relays, or collect them when writing to --output
+    async fn send(&mut self, args: &Args, events: Vec<Event>) -> Result<Vec<PublishStatus>> {
+        if args.output.is_some() {
+            // catch events relays would reject before writing them
+            validate_events(&events)?;
+            let status = events.iter().map(PublishStatus::new).collect();
+            self.events.extend(events);
+            return Ok(status);
+        }
+        info!("Publishing events..");
+        let timings = self.timings.clone();
+        let client = self.client(args).await?;
+        publish_events(client, events, &timings).await
+    }
+}
+
+async fn publish(args: &Args, manifests: &[Manifest]) -> Result<()> {
+    let mut session = Session {
+        http: http_client(
+            Duration::from_secs(args.http_timeout),
+            args.proxy.as_deref(),
+        )?,
+        timings: if args.timings {
+            Timings::enabled()
+        } else {
+            Timings::default()
+        },
+        ..Default::default()
+    };
+    for manifest in manifests {
+        publish_app(args, manifest, &mut session).await?;
+    }
+
+    if let Some(path) = &args.emit_manifest_json {
+        std::fs::write(path, serde_json::to_string_pretty(&session.manifests)?)?;
+        info!(
+            "Wrote {} artifact manifest(s) to {}",
+            session.manifests.len(),
+            path.display()
+        );
+    }
+    if let Some(path) = &args.json_summary {
+        let summary = serde_json::json!({ "releases": session.summary });
+        std::fs::write(path, serde_json::to_string_pretty(&summary)?)?;
+        info!("Wrote the publish summary to {}", path.display());
+    }
+    if let Some(path) = &args.output {
+        if !session.events.is_empty() {
+            std::fs::write(path, serde_json::to_string_pretty(&session.events)?)?;
+            info!(
+                "Wrote {} signed event(s) to {}",
+                session.events.len(),
+                path.display()
+            );
+        }
+    }
+    if session.timings.is_enabled() {
+        info!("Timings:");
+        for line in session.timings.report() {
+            info!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+async fn publish_app(args: &Args, manifest: &Manifest, session: &mut Session) -> Result<()> {
+    manifest.validate_tags(args.strict_tags)?;
+    if let Some(lud16) = &manifest.lud16 {
+        check_lud16(lud16).map_err(|e| anyhow!("lud16: {}", e))?;
+    }
+    let repo = repo_from_manifest(
+        manifest,
+        LoadOptions {
+            verify_signatures: args.verify_signatures,
+            filter: AssetFilter::new(&manifest.include, &manifest.exclude)?
+                .with_glob(args.asset_glob.as_deref())?,
+            max_artifacts: args.max_artifacts_per_release,
+            version_source: manifest.version_source,
+            cache_dir: args.cache_dir.clone(),
+            tag_prefix: args.tag_prefix.clone().or(manifest.tag_prefix.clone()),
+            github_api: args.github_api.clone().or(manifest.github_api.clone()),
+            client: session.http.clone(),
+            verify_with: manifest.verify_with.clone(),
+            content_types: manifest.content_types.clone(),
+            progress: Default::default(),
+            timings: session.timings.clone(),
+            range_requests: args.range_requests,
+            all_releases: args.all,
+        },
+    )?;
+
+    let mut releases = repo.get_releases().await?;
 
     info!("Found {} release(s)", releases.len());
 
-    if let Some(release) = releases.first() {
-        info!("Starting publish of release {}", release.version);
-        info!("Artifacts: ");
-        for a in &release.artifacts {
-            info!(" - {}", a);
+    // oldest first, so the app event published last points at the latest release
+    releases.reverse();
+    for release in releases {
+        publish_release(args, manifest, session, release).await?;
+    }
+    Ok(())
+}
+
+/// Check, sign and publish a single release of an app
+async fn publish_release(
+    args: &Args,
+    manifest: &Manifest,
+    session: &mut Session,
+    mut release: RepoRelease,
+) -> Result<()> {
+    if let Some(path) = &manifest.changelog {
+        let changelog = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read changelog {}: {}", path.display(), e))?;
+        match release_notes(&changelog, &release.version) {
+            Some(notes) => release.description = Some(notes),
+            None => warn!(
+                "No section for v{} in {}, using the release description",
+                release.version,
+                path.display()
+            ),
         }
-        if !dialoguer::Confirm::new()
-            .default(false)
-            .with_prompt(format!("Publish v{}?", release.version))
-            .interact()?
+    }
+    if !manifest.platforms.is_empty() {
+        release.retain_platforms(&manifest.platforms);
+        if release.artifacts.is_empty() {
+            bail!("No artifacts left to publish after platform filtering");
+        }
+    }
+    let ids: Vec<&str> = [manifest.id.as_str()]
+        .into_iter()
+        .chain(manifest.allowed_ids.iter().map(|s| s.as_str()))
+        .collect();
+    let mismatched = release.package_mismatches(&ids);
+    if !mismatched.is_empty() {
+        let names: Vec<String> = mismatched
+            .iter()
+            .map(|i| {
+                let a = &release.artifacts[*i];
+                format!("{} ({})", a.name, a.metadata.package().unwrap_or_default())
+            })
+            .collect();
+        if !args.skip_id_mismatch {
+            bail!(
+                "Package name does not match app id {}: {}, use --skip-id-mismatch to skip them",
+                manifest.id,
+                names.join(", ")
+            );
+        }
+        let keep: Vec<usize> = (0..release.artifacts.len())
+            .filter(|i| !mismatched.contains(i))
+            .collect();
+        for a in release.select_artifacts(&keep) {
+            warn!(
+                "Skipping {}, package {} does not match app id {}",
+                a.name,
+                a.metadata.package().unwrap_or_default(),
+                manifest.id
+            );
+            if !args.keep_downloads {
+                a.remove_download();
+            }
+        }
+        if release.artifacts.is_empty() {
+            bail!("No artifacts left to publish after package id check");
+        }
+    }
+    if args.select_artifacts && !args.yes && std::io::stdin().is_terminal() {
+        let items: Vec<String> = release.artifacts.iter().map(|a| a.to_string()).collect();
+        let chosen = dialoguer::MultiSelect::new()
+            .with_prompt("Select artifacts to publish")
+            .items(&items)
+            .defaults(&vec![true; items.len()])
+            .interact()?;
+        for a in release.select_artifacts(&chosen) {
+            info!("Skipping deselected artifact {}", a.name);
+            if !args.keep_downloads {
+                a.remove_download();
+            }
+        }
+        if release.artifacts.is_empty() {
+            bail!("No artifacts selected");
+        }
+    }
+    info!("Starting publish of release {}", release.version);
+    info!("Artifacts: ");
+    for a in &release.artifacts {
+        info!(" - {}", a);
+    }
+    if args.emit_manifest_json.is_some() {
+        session.manifests.extend(release.artifacts.iter().map(|a| {
+            serde_json::json!({
+                "app": manifest.id,
+                "artifact": a.name,
+                "platform": a.platform.to_string(),
+                "manifest": a.metadata.manifest_info(),
+            })
+        }));
+    }
+    for a in release
+        .artifacts
+        .iter()
+        .filter(|a| a.metadata.is_debug_signed())
+    {
+        if args.allow_debug_signature {
+            warn!("{} is signed with the Android debug certificate", a.name);
+        } else {
+            bail!(
+                "{} is signed with the Android debug certificate, use --allow-debug-signature to publish anyway",
+                a.name
+            );
+        }
+    }
+    for a in release
+        .artifacts
+        .iter()
+        .filter(|a| a.metadata.is_debuggable())
+    {
+        if args.strict {
+            bail!("{} is a debuggable build (android:debuggable=true)", a.name);
+        }
+        warn!(
+            "{} is a debuggable build (android:debuggable=true), release builds should not be",
+            a.name
+        );
+    }
+    let key = session.keys(args)?;
+    let release_key = session.release_keys(args, manifest)?;
+
+    let app_id = release.app_id()?;
+    let app_coord = Coordinate::new(Kind::Custom(32_267), key.public_key).identifier(&app_id);
+    let release_coord = Coordinate::new(Kind::Custom(30_063), release_key.public_key)
+        .identifier(release.release_tag()?);
+
+    // nothing to compare against when writing events to a file
+    if args.output.is_none() {
+        let client = session.client(args).await?;
+        let release_tag = release.release_tag()?;
+        match fetch_published_release(
+            client,
+            release_key.public_key,
+            &app_coord,
+            Some(&release_tag),
+            FETCH_TIMEOUT,
+        )
+        .await
+        {
+            Ok(Some(published)) if published.same_artifacts(&release) && !args.force => {
+                info!(
+                    "{} is already published with the same files, skipping (use --force to publish again)",
+                    release_tag
+                );
+                if args.json_summary.is_some() {
+                    session.summary.push(release_summary(
+                        &release,
+                        &app_coord,
+                        &release_coord,
+                        "unchanged",
+                        &[],
+                    )?);
+                }
+                if !args.keep_downloads {
+                    release.remove_downloads();
+                }
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to look up release {}: {}", release_tag, e),
+        }
+        match fetch_published_release(
+            client,
+            release_key.public_key,
+            &app_coord,
+            None,
+            FETCH_TIMEOUT,
+        )
+        .await
         {
-            return Ok(());
+            Ok(Some(published)) => {
+                info!("Changes since the published release:");
+                for line in release_diff(&published, &release) {
+                    info!("  {}", line);
+                }
+            }
+            Ok(None) => info!("No published release found for {}", app_id),
+            Err(e) => warn!("Failed to fetch the published release: {}", e),
         }
+    }
 
-        let key = dialoguer::Password::new()
-            .with_prompt("Enter nsec:")
-            .interact()?;
+    if !args.yes
+        && !dialoguer::Confirm::new()
+            .default(false)
+            .with_prompt(format!("Publish v{}?", release.version))
+            .interact()?
+    {
+        return Ok(());
+    }
 
-        let key = if let Ok(nsec) = Keys::parse(&key) {
-            nsec
+    // create release
+    let created_at = if args.preserve_dates {
+        if release.published_at.is_none() {
+            warn!("The release has no publish date, dating events now");
+        }
+        Some(release.published_timestamp())
+    } else {
+        None
+    };
+    let mut opts = EventOptions::from_manifest(manifest);
+    opts.created_at = created_at;
+    opts.omit_tags.extend(args.omit_tag.iter().cloned());
+    if args.output.is_none() && !args.republish_files {
+        let client = session.client(args).await?;
+        match fetch_file_events(client, release_key.public_key, &release, FETCH_TIMEOUT).await {
+            Ok(files) => opts.existing_files = files,
+            Err(e) => warn!("Failed to look up published file events: {}", e),
+        }
+    }
+    if let Some(t) = opts
+        .omit_tags
+        .iter()
+        .find(|t| REQUIRED_FILE_TAGS.contains(&t.as_str()))
+    {
+        bail!("The '{}' tag is required and can't be omitted", t);
+    }
+    let events = session
+        .timings
+        .time(
+            format!("sign {}", release.release_tag()?),
+            sign_release_events(manifest, &release, &key, &release_key, &opts),
+        )
+        .await?;
+    let published = session.send(args, events).await?;
+    if args.json_summary.is_some() {
+        let status = if args.output.is_some() {
+            "signed"
         } else {
-            bail!("Invalid private key")
+            "published"
         };
+        session.summary.push(release_summary(
+            &release,
+            &app_coord,
+            &release_coord,
+            status,
+            &published,
+        )?);
+    }
+
+    if !args.keep_downloads {
+        release.remove_downloads();
+    }
+    info!("Done.");
+    Ok(())
+}
+
+fn relay_timeout(args: &Args) -> Duration {
+    Duration::from_secs(args.relay_timeout)
+}
 
-        let ev: EventBuilder = (&manifest).into();
+/// Relays to publish to, merged with the NIP-65 write relays when enabled
+async fn publish_relays(args: &Args, pubkey: PublicKey) -> Result<Vec<String>> {
+    if !args.use_nip65 {
+        return Ok(args.relay.clone());
+    }
+    // the NIP-65 relays are used in addition to the default relay, not instead of it
+    let configured = if args.relay.is_empty() {
+        vec![DEFAULT_RELAY.to_string()]
+    } else {
+        args.relay.clone()
+    };
+    match nip65_write_relays(&configured, pubkey, relay_timeout(args), FETCH_TIMEOUT).await {
+        Ok(write) => Ok(merge_relays(&configured, &write)),
+        Err(e) => {
+            warn!(
+                "Failed to look up the NIP-65 relay list, publishing to the configured relays: {}",
+                e
+            );
+            Ok(configured)
+        }
+    }
+}
+
+/// Publish NIP-09 deletion requests for a release and its file metadata events
+async fn retract(args: &Args, manifest: &Manifest, version: &Version) -> Result<()> {
+    // releases are authored by the release key when one is configured
+    let key = match args
+        .release_key_file
+        .as_ref()
+        .or(manifest.release_key_file.as_ref())
+    {
+        Some(path) => load_release_keys(path)?,
+        None => load_keys(args.key_file.as_deref())?,
+    };
+    let client = connect_authenticated_client(
+        &publish_relays(args, key.public_key).await?,
+        relay_timeout(args),
+        key.clone(),
+    )
+    .await?;
 
-        let app_id = release.app_id()?;
-        let app_coord = Coordinate::new(Kind::Custom(32_267), key.public_key).identifier(app_id);
+    let release_tag = format!("{}@{}", manifest.id, version);
+    info!("Looking up release {}", release_tag);
+    let releases = client
+        .fetch_events(
+            Filter::new()
+                .kind(Kind::Custom(30_063))
+                .author(key.public_key)
+                .identifier(&release_tag),
+            FETCH_TIMEOUT,
+        )
+        .await?;
+    if releases.is_empty() {
+        bail!("No published release found for {}", release_tag);
+    }
 
-        // create release
-        let release_list = release
-            .clone()
-            .into_release_list_event(&key, app_coord)
+    let release_coord =
+        Coordinate::new(Kind::Custom(30_063), key.public_key).identifier(&release_tag);
+    let mut targets: Vec<EventIdOrCoordinate> = vec![release_coord.into()];
+    targets.extend(releases.iter().map(|ev| EventIdOrCoordinate::from(ev.id)));
+    // file events reused by other releases are kept
+    let files =
+        fetch_unshared_file_events(&client, key.public_key, &releases.to_vec(), FETCH_TIMEOUT)
             .await?;
-        let release_coord = Coordinate::new(Kind::Custom(30_063), key.public_key)
-            .identifier(release.release_tag()?);
-
-        // publish application
-        let app_ev = ev
-            .tag(Tag::coordinate(release_coord))
-            .tags(
-                release
-                    .artifacts
-                    .iter()
-                    .filter_map(|a| Tag::parse(["f", a.platform.to_string().as_str()]).ok()),
-            )
-            .sign_with_keys(&key)?;
+    targets.extend(files.into_iter().map(EventIdOrCoordinate::from));
 
-        info!("Publishing events..");
-        let client = Client::builder().build();
-        for r in &args.relay {
-            info!("Connecting to {}", r);
-            client.add_relay(r).await?;
+    info!("Retracting {} event(s):", targets.len());
+    for t in &targets {
+        match t {
+            EventIdOrCoordinate::Id(id) => info!(" - {}", id),
+            EventIdOrCoordinate::Coordinate(c) => info!(" - {}", c),
         }
-        if args.relay.is_empty() {
-            const DEFAULT_RELAY: &str = "wss://relay.zapstore.dev";
-            info!("Connecting to default relay {DEFAULT_RELAY}");
-            client.add_relay(DEFAULT_RELAY).await?;
+    }
+    if !args.yes
+        && !dialoguer::Confirm::new()
+            .default(false)
+            .with_prompt(format!("Retract v{}?", version))
+            .interact()?
+    {
+        return Ok(());
+    }
+
+    let ev = EventBuilder::delete(targets).sign_with_keys(&key)?;
+    client.send_event(ev).await?;
+
+    info!("Done.");
+    Ok(())
+}
+
+/// Print the releases already published for the apps
+async fn status(args: &Args, manifests: &[Manifest], pubkey: Option<&str>) -> Result<()> {
+    let pubkey = match pubkey {
+        Some(pk) => PublicKey::parse(pk)?,
+        None => load_keys(args.key_file.as_deref())?.public_key,
+    };
+    let client = connect_client(&args.relay, relay_timeout(args)).await?;
+    for manifest in manifests {
+        app_status(&client, manifest, pubkey).await?;
+    }
+    Ok(())
+}
+
+async fn app_status(client: &Client, manifest: &Manifest, pubkey: PublicKey) -> Result<()> {
+    let app_coord = Coordinate::new(Kind::Custom(32_267), pubkey).identifier(&manifest.id);
+    let mut releases = client
+        .fetch_events(
+            Filter::new()
+                .kind(Kind::Custom(30_063))
+                .author(pubkey)
+                .coordinate(&app_coord),
+            FETCH_TIMEOUT,
+        )
+        .await?
+        .to_vec();
+    releases.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+
+    info!(
+        "Found {} published release(s) for {}",
+        releases.len(),
+        app_coord
+    );
+    for ev in releases {
+        let version = release_version(&ev).unwrap_or("unknown");
+        info!(
+            " - v{} published={} artifacts={}",
+            version,
+            ev.created_at.to_human_datetime(),
+            ev.tags.event_ids().count()
+        );
+    }
+    Ok(())
+}
+
+/// Print the supported artifact extensions and platform tags, grouped by OS
+fn formats() {
+    println!("Artifact formats:");
+    for format in ARTIFACT_FORMATS {
+        let extensions: Vec<String> = format.extensions.iter().map(|e| format!(".{e}")).collect();
+        println!("  {:16} {}", extensions.join(" "), format.description);
+    }
+
+    let mut platforms: Vec<(&str, Vec<&str>)> = vec![];
+    for platform in Platform::all().into_iter().filter(|p| p.is_supported()) {
+        // web has no architecture
+        let archs = Vec::from_iter(platform.arch_name());
+        match platforms.iter_mut().find(|(os, _)| *os == platform.os()) {
+            Some((_, a)) => a.extend(archs),
+            None => platforms.push((platform.os(), archs)),
+        }
+    }
+    println!("Platforms:");
+    for (os, archs) in platforms {
+        println!("  {:16} {}", os, archs.join(", "));
+    }
+}
+
+/// Report all manifest problems at once, failing if there are any
+fn validate(args: &Args) -> Result<()> {
+    let path = config_path(args);
+    let manifests = load_manifests(args)?;
+    let source = std::fs::read_to_string(&path).unwrap_or_default();
+
+    let mut count = 0;
+    for manifest in &manifests {
+        let problems = validate_app(args, manifest);
+        for (field, msg) in &problems {
+            // field lines are ambiguous when the config has multiple apps
+            match field_line(&source, field).filter(|_| manifests.len() == 1) {
+                Some(line) => error!("{}:{}: {}: {}", path.display(), line, field, msg),
+                None => error!("{}: {}: {}: {}", path.display(), manifest.id, field, msg),
+            }
         }
-        client.connect().await;
+        count += problems.len();
+    }
+    if count > 0 {
+        bail!("{} problem(s) found in {}", count, path.display());
+    }
+    info!("{} is valid", path.display());
+    Ok(())
+}
 
-        client.send_event(app_ev).await?;
-        for ev in release_list {
-            client.send_event(ev).await?;
+/// All problems with one app manifest as `(field, message)`
+fn validate_app(args: &Args, manifest: &Manifest) -> Vec<(&'static str, String)> {
+    let mut problems = manifest.validate();
+    if let Err(e) = manifest.validate_tags(args.strict_tags) {
+        problems.push(("tags", e.to_string()));
+    }
+    match AssetFilter::new(&manifest.include, &manifest.exclude) {
+        Ok(filter) => {
+            let opts = LoadOptions {
+                filter,
+                github_api: args.github_api.clone().or(manifest.github_api.clone()),
+                ..Default::default()
+            };
+            if let Err(e) = repo_from_manifest(manifest, opts) {
+                problems.push(("repository", e.to_string()));
+            }
         }
+        Err(e) => problems.push(("include", e.to_string())),
+    }
+    problems
+}
+
+/// Line number of a top level field in the config source
+fn field_line(source: &str, field: &str) -> Option<usize> {
+    source
+        .lines()
+        .position(|l| {
+            l.strip_prefix(field)
+                .is_some_and(|r| r.trim_start().starts_with(':'))
+        })
+        .map(|i| i + 1)
+}
 
-        info!("Done.");
+/// Send signed events from a file without re-signing them
+async fn broadcast(args: &Args, path: &Path) -> Result<()> {
+    let data: Vec<serde_json::Value> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let mut events = Vec::with_capacity(data.len());
+    for v in data {
+        let ev = Event::from_json(v.to_string())?;
+        ev.verify()
+            .map_err(|e| anyhow!("Invalid event {}: {}", ev.id, e))?;
+        events.push(ev);
+    }
+    info!(
+        "Loaded {} signed event(s) from {}",
+        events.len(),
+        path.display()
+    );
+
+    let client = connect_client(&args.relay, relay_timeout(args)).await?;
+    let published = publish_events(&client, events, &Timings::default()).await?;
+    for status in published.iter().filter(|s| !s.accepted.is_empty()) {
+        info!(
+            "Published kind {} event {} to {} relay(s)",
+            status.kind.as_u16(),
+            status.id,
+            status.accepted.len()
+        );
+    }
+    if let Some(path) = &args.json_summary {
+        let summary = serde_json::json!({ "events": published });
+        std::fs::write(path, serde_json::to_string_pretty(&summary)?)?;
+        info!("Wrote the publish summary to {}", path.display());
     }
 
+    info!("Done.");
     Ok(())
 }
@@ -1,7 +1,14 @@
-use nostr_sdk::{EventBuilder, Kind, Tag};
+use crate::repo::{parse_lenient_version, Platform, RepoRelease, REQUIRED_FILE_TAGS};
+use anyhow::{bail, Result};
+use log::warn;
+use nostr_sdk::prelude::Coordinate;
+use nostr_sdk::{EventBuilder, Kind, Tag, TagStandard, Url};
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Manifest {
     /// App ID, must be unique
     pub id: String,
@@ -9,60 +16,635 @@ pub struct Manifest {
     /// Application display name
     pub name: String,
 
-    /// App description
+    /// Long form app description (with markdown), published as the app event content
     pub description: Option<String>,
 
-    /// Long form app description (with markdown)
+    /// Short one-line summary, published as the `summary` tag
     pub summary: Option<String>,
 
     /// Repo URL
     pub repository: Option<String>,
 
+    /// Direct download URLs of self-hosted artifacts, published as a single release instead of
+    /// reading releases from `repository`
+    #[serde(default)]
+    pub artifacts: Vec<String>,
+
+    /// Release version of `artifacts`, the artifact version is used when not set
+    pub version: Option<String>,
+
     /// Public project website
     pub url: Option<String>,
 
     /// SPDX license code
     pub license: Option<String>,
 
-    /// App icon
-    pub icon: Option<String>,
+    /// Lightning address (LUD-16) for donations, published as the `lud16` tag
+    pub lud16: Option<String>,
+
+    /// App store listings of the app by store (`play: com.example.app`, `appstore: "123456"`),
+    /// published as `i` tags
+    #[serde(default)]
+    pub external_ids: BTreeMap<String, String>,
 
-    /// App preview images
-    pub images: Vec<String>,
+    /// App icon, optionally keyed by platform
+    pub icon: Option<PlatformValues<String>>,
+
+    /// App preview images, optionally keyed by platform
+    #[serde(default)]
+    pub images: PlatformValues<Vec<ImageSource>>,
 
     /// Tags (category / purpose)
     pub tags: Vec<String>,
+
+    /// Minimum supported OS version keyed by platform tag or OS (`ios: "15.0"`,
+    /// `linux: "glibc 2.31"`), for platforms whose artifacts don't declare it
+    #[serde(default)]
+    pub min_os: HashMap<String, String>,
+
+    /// Platforms to publish, full `f` tag values (`android-arm64-v8a`) or just the OS
+    /// (`android`), all platforms when empty
+    #[serde(default)]
+    pub platforms: Vec<String>,
+
+    /// Markdown changelog, the section for the published version is used as the release notes
+    pub changelog: Option<PathBuf>,
+
+    /// Public key to verify detached signatures of the release assets with
+    pub verify_with: Option<VerifyWith>,
+
+    /// GitHub API base URL for GitHub Enterprise, defaults to `https://<host>/api/v3`
+    pub github_api: Option<String>,
+
+    /// Release tag prefix for repos which publish releases for multiple apps (`myapp-v1.2.3`)
+    pub tag_prefix: Option<String>,
+
+    /// Glob patterns of release asset filenames to publish, all assets when empty
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Glob patterns of release asset filenames to skip
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Other APK package names allowed in the release besides `id`
+    #[serde(default)]
+    pub allowed_ids: Vec<String>,
+
+    /// NIP-49 key file to sign the release events with, the app event keeps the main key
+    pub release_key_file: Option<PathBuf>,
+
+    /// Optional NIP-94 tags to leave out of the file events (`apk_signature_hash`)
+    #[serde(default)]
+    pub omit_tags: Vec<String>,
+
+    /// MIME types by file extension (`apk`), overriding the detected `m` tag
+    #[serde(default)]
+    pub content_types: HashMap<String, String>,
+
+    /// Take the release version from the tag (default) or the artifacts
+    #[serde(default)]
+    pub version_source: VersionSource,
+
+    /// Publish the release notes as markdown (default) or as plain text with the markdown in a
+    /// `markdown` tag
+    #[serde(default)]
+    pub release_notes_format: NotesFormat,
+
+    /// Extra tags appended to the app event as-is, eg. `["p", "<hex pubkey>"]`
+    #[serde(default)]
+    pub extra_tags: Vec<Vec<String>>,
+
+    /// Extra tags appended to the release event
+    #[serde(default)]
+    pub release_extra_tags: Vec<Vec<String>>,
 }
 
 impl From<&Manifest> for EventBuilder {
     fn from(val: &Manifest) -> Self {
-        let mut b = EventBuilder::new(Kind::Custom(32_267), val.description.as_str_or_empty())
-            .tags([
-                Tag::parse(["d", &val.id]).unwrap(),
-                Tag::parse(["name", &val.name]).unwrap(),
-                Tag::parse(["url", val.url.as_str_or_empty()]).unwrap(),
-            ]);
-        if let Some(s) = &val.summary {
-            b = b.tag(Tag::parse(["summary", s]).unwrap());
-        }
-        if let Some(icon) = &val.icon {
-            b = b.tag(Tag::parse(["icon", icon]).unwrap());
-        }
-        if let Some(repository) = &val.repository {
+        val.event_builder(&val.template_vars(None))
+    }
+}
+
+/// Listing URL of an app in a known store, published as the `i` tag URL hint
+fn store_url(store: &str, id: &str) -> Option<String> {
+    match store {
+        "play" => Some(format!(
+            "https://play.google.com/store/apps/details?id={id}"
+        )),
+        "appstore" => Some(format!("https://apps.apple.com/app/id{id}")),
+        "fdroid" => Some(format!("https://f-droid.org/packages/{id}")),
+        "flathub" => Some(format!("https://flathub.org/apps/{id}")),
+        "snapcraft" => Some(format!("https://snapcraft.io/{id}")),
+        _ => None,
+    }
+}
+
+/// Git clone URL of a repository web URL
+fn clone_url(repository: &str) -> String {
+    let repository = repository.trim_end_matches('/');
+    if repository.ends_with(".git") {
+        repository.to_string()
+    } else {
+        format!("{repository}.git")
+    }
+}
+
+/// Known app categories
+pub const CATEGORIES: &[&str] = &[
+    "audio",
+    "books",
+    "business",
+    "communication",
+    "development",
+    "education",
+    "entertainment",
+    "finance",
+    "games",
+    "graphics",
+    "health",
+    "lifestyle",
+    "maps",
+    "music",
+    "news",
+    "nostr",
+    "photography",
+    "productivity",
+    "security",
+    "shopping",
+    "social",
+    "sports",
+    "tools",
+    "travel",
+    "video",
+    "weather",
+];
+
+/// Normalize a tag for publishing
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+impl Manifest {
+    /// App event with placeholders in the description and summary replaced by `vars`
+    fn event_builder(&self, vars: &[(&str, String)]) -> EventBuilder {
+        let description = render_template(self.description.as_str_or_empty(), vars);
+        let mut b = EventBuilder::new(Kind::Custom(32_267), description).tags([
+            Tag::parse(["d", &self.id]).unwrap(),
+            Tag::parse(["name", &self.name]).unwrap(),
+            Tag::parse(["url", self.url.as_str_or_empty()]).unwrap(),
+        ]);
+        if let Some(s) = &self.summary {
+            b = b.tag(Tag::parse(["summary", &render_template(s, vars)]).unwrap());
+        }
+        if let Some(lud16) = &self.lud16 {
+            b = b.tag(Tag::parse(["lud16", lud16]).unwrap());
+        }
+        for (store, id) in &self.external_ids {
+            let mut tag = vec!["i".to_string(), format!("{}:{}", store, id)];
+            tag.extend(store_url(store, id));
+            b = b.tag(Tag::parse(tag).unwrap());
+        }
+        if let Some(icon) = &self.icon {
+            for (platform, icon) in icon.iter() {
+                b = b.tag(Tag::parse(platform_tag("icon", icon, platform)).unwrap());
+            }
+        }
+        if let Some(repository) = &self.repository {
             b = b.tag(Tag::parse(["repository", repository]).unwrap());
+            b = b.tag(Tag::parse(["r", &clone_url(repository), "source"]).unwrap());
+        }
+        if let Some(url) = &self.url {
+            b = b.tag(Tag::parse(["r", url, "homepage"]).unwrap());
         }
-        if let Some(license) = &val.license {
+        if let Some(license) = &self.license {
             b = b.tag(Tag::parse(["license", license]).unwrap());
         }
-        for image in &val.images {
-            b = b.tag(Tag::parse(["image", image]).unwrap());
+        for (platform, images) in self.images.iter() {
+            for image in images {
+                b = b.tag(Tag::parse(platform_tag("image", image.url(), platform)).unwrap());
+                // NIP-92 style, the position of the platform in the image tag stays fixed
+                if let Some(dim) = image.dimensions() {
+                    let imeta = [
+                        "imeta".to_string(),
+                        format!("url {}", image.url()),
+                        format!("dim {}", dim),
+                    ];
+                    b = b.tag(Tag::parse(imeta).unwrap());
+                }
+            }
         }
-        for tag in &val.tags {
-            b = b.tag(Tag::parse(["t", tag]).unwrap());
+        for tag in &self.tags {
+            b = b.tag(Tag::parse(["t", &normalize_tag(tag)]).unwrap());
         }
+        b = b.tags(parse_extra_tags(&self.extra_tags));
 
         b
     }
+
+    /// App event for a release, linking the release and listing the platforms it supports
+    pub fn app_event(&self, release: &RepoRelease, release_coord: Coordinate) -> EventBuilder {
+        self.event_builder(&self.template_vars(Some(release)))
+            .tag(Tag::coordinate(release_coord))
+            .tags(
+                release
+                    .artifacts
+                    .iter()
+                    .filter_map(|a| Tag::parse(["f", a.platform.to_string().as_str()]).ok()),
+            )
+            .tags(
+                release
+                    .artifacts
+                    .iter()
+                    .map(|a| a.metadata.labels())
+                    .find(|l| !l.is_empty())
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|(locale, name)| Tag::parse(["name", name, locale]).ok()),
+            )
+    }
+
+    /// Values for the content placeholders: `{name}`, `{app_id}` and with a release `{version}`
+    /// and `{date}` (publish date as `YYYY-MM-DD`)
+    pub fn template_vars(&self, release: Option<&RepoRelease>) -> Vec<(&'static str, String)> {
+        let mut vars = vec![("name", self.name.clone()), ("app_id", self.id.clone())];
+        if let Some(release) = release {
+            vars.push(("version", release.version.to_string()));
+            if let Some(date) = release.published_at {
+                vars.push(("date", date.format("%Y-%m-%d").to_string()));
+            }
+        }
+        vars
+    }
+
+    /// Warn about tags which are not a known category, or error when `strict`
+    pub fn validate_tags(&self, strict: bool) -> Result<()> {
+        let unknown: Vec<String> = self
+            .tags
+            .iter()
+            .map(|t| normalize_tag(t))
+            .filter(|t| !CATEGORIES.contains(&t.as_str()))
+            .collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        if strict {
+            bail!("Unknown tags: {}", unknown.join(", "));
+        }
+        warn!(
+            "Tags are not a known category and may be hard to discover: {}",
+            unknown.join(", ")
+        );
+        Ok(())
+    }
+
+    /// Check the manifest fields, returning all problems as `(field, message)`
+    pub fn validate(&self) -> Vec<(&'static str, String)> {
+        let mut problems = vec![];
+        if !is_valid_app_id(&self.id) {
+            problems.push((
+                "id",
+                format!(
+                    "'{}' is not a valid app id, expected a reverse domain name like com.example.app",
+                    self.id
+                ),
+            ));
+        }
+        if self.name.trim().is_empty() {
+            problems.push(("name", "must not be empty".to_string()));
+        }
+        let urls = [("repository", &self.repository), ("url", &self.url)];
+        for (field, url) in urls {
+            if let Some(Err(e)) = url.as_deref().map(check_url) {
+                problems.push((field, e));
+            }
+        }
+        for url in &self.artifacts {
+            if let Err(e) = check_url(url) {
+                problems.push(("artifacts", e));
+            }
+        }
+        if let Some(v) = self.version.as_deref() {
+            if parse_lenient_version(v).is_none() {
+                problems.push(("version", format!("'{}' is not a version", v)));
+            }
+        }
+        if let Some(icon) = &self.icon {
+            for (_, url) in icon.iter() {
+                if let Err(e) = check_url(url) {
+                    problems.push(("icon", e));
+                }
+            }
+        }
+        for (_, images) in self.images.iter() {
+            for image in images {
+                if let Err(e) = check_url(image.url()) {
+                    problems.push(("images", e));
+                }
+            }
+        }
+        if let Some(license) = &self.license {
+            if let Err(e) = spdx::Expression::parse(license) {
+                problems.push((
+                    "license",
+                    format!("'{}' is not a valid SPDX expression: {}", license, e.reason),
+                ));
+            }
+        }
+        if let Some(Err(e)) = self.lud16.as_deref().map(check_lud16) {
+            problems.push(("lud16", e));
+        }
+        for (store, id) in &self.external_ids {
+            let valid = match store.as_str() {
+                "play" | "fdroid" => is_valid_app_id(id),
+                "appstore" => !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()),
+                _ => !id.trim().is_empty(),
+            };
+            if !valid {
+                problems.push((
+                    "external_ids",
+                    format!("'{}' is not a valid {} id", id, store),
+                ));
+            }
+            if store.is_empty() || store.contains(':') {
+                problems.push((
+                    "external_ids",
+                    format!("'{}' is not a valid store name", store),
+                ));
+            }
+        }
+        let known = Platform::all();
+        for platform in &self.platforms {
+            if !known
+                .iter()
+                .any(|p| *platform == p.to_string() || platform == p.os())
+            {
+                problems.push(("platforms", format!("unknown platform '{}'", platform)));
+            }
+        }
+        for platform in self.min_os.keys() {
+            if !known
+                .iter()
+                .any(|p| *platform == p.to_string() || platform == p.os())
+            {
+                problems.push(("min_os", format!("unknown platform '{}'", platform)));
+            }
+        }
+        for (ext, content_type) in &self.content_types {
+            if !is_valid_mime_type(content_type) {
+                problems.push((
+                    "content_types",
+                    format!("'{}' for .{} is not a valid MIME type", content_type, ext),
+                ));
+            }
+        }
+        let extra = [
+            ("extra_tags", &self.extra_tags),
+            ("release_extra_tags", &self.release_extra_tags),
+        ];
+        for (field, tags) in extra {
+            for tag in tags {
+                if let Err(e) = parse_tag(tag) {
+                    problems.push((field, format!("invalid tag {:?}: {}", tag, e)));
+                }
+            }
+        }
+        for tag in &self.omit_tags {
+            if REQUIRED_FILE_TAGS.contains(&tag.as_str()) {
+                problems.push((
+                    "omit_tags",
+                    format!("'{}' is required and can't be omitted", tag),
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// Parse a tag from the config, tags with a standard name must be valid for it (`p` needs a
+/// public key), others are taken as-is
+fn parse_tag(tag: &[String]) -> Result<Tag, nostr_sdk::event::tag::Error> {
+    let parsed = Tag::parse(tag)?;
+    match TagStandard::parse(tag) {
+        Ok(_) | Err(nostr_sdk::event::tag::Error::UnknownStandardizedTag) => Ok(parsed),
+        Err(e) => Err(e),
+    }
+}
+
+/// Parse the extra tags from the config, skipping malformed ones with a warning
+pub fn parse_extra_tags(tags: &[Vec<String>]) -> Vec<Tag> {
+    tags.iter()
+        .filter_map(|t| match parse_tag(t) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                warn!("Skipping invalid extra tag {:?}: {}", t, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Check for a `type/subtype` MIME type
+fn is_valid_mime_type(s: &str) -> bool {
+    let token = |t: &str| {
+        !t.is_empty()
+            && t.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    matches!(s.split_once('/'), Some((t, st)) if token(t) && token(st))
+}
+
+/// Replace `{name}` placeholders with their values, unknown placeholders are kept as-is
+pub fn render_template(source: &str, vars: &[(&str, String)]) -> String {
+    let mut res = source.to_string();
+    for (name, value) in vars {
+        res = res.replace(&format!("{{{}}}", name), value);
+    }
+    res
+}
+
+/// Substitute `${VAR}` references with environment variables, failing on unset variables
+pub fn interpolate_env(source: &str) -> Result<String> {
+    let mut res = String::with_capacity(source.len());
+    let mut missing = vec![];
+    let mut rest = source;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + len];
+        res.push_str(&rest[..start]);
+        match std::env::var(name) {
+            Ok(v) => res.push_str(&v),
+            Err(_) => missing.push(name.to_string()),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    res.push_str(rest);
+    if !missing.is_empty() {
+        bail!(
+            "Environment variable(s) referenced in the manifest are not set: {}",
+            missing.join(", ")
+        );
+    }
+    Ok(res)
+}
+
+/// App ids follow the Android package name rules: at least two dot separated segments
+/// each starting with a letter
+fn is_valid_app_id(id: &str) -> bool {
+    let segments: Vec<&str> = id.split('.').collect();
+    segments.len() >= 2
+        && segments.iter().all(|s| {
+            s.starts_with(|c: char| c.is_ascii_alphabetic())
+                && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+/// Check a manifest URL is an absolute http(s) URL
+fn check_url(url: &str) -> Result<(), String> {
+    match url.parse::<Url>() {
+        Ok(u) if u.scheme() == "https" || u.scheme() == "http" => Ok(()),
+        Ok(_) => Err(format!("'{}' is not an http(s) URL", url)),
+        Err(e) => Err(format!("'{}' is not a valid URL: {}", url, e)),
+    }
+}
+
+/// Check a lightning address is `name@domain` with the LUD-16 allowed name characters
+pub fn check_lud16(address: &str) -> Result<(), String> {
+    let valid = address.split_once('@').is_some_and(|(name, domain)| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '-' | '_' | '.'))
+            && domain.contains('.')
+            && Url::parse(&format!("https://{domain}")).is_ok_and(|u| u.host_str() == Some(domain))
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("'{}' is not a valid lightning address", address))
+    }
+}
+
+/// Where the release version comes from
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionSource {
+    /// The release tag, or the artifact version if the tag is not semver
+    #[default]
+    Tag,
+    /// The `versionName` (or `versionCode`) of the APK artifacts
+    Artifact,
+}
+
+/// Format of the release event content
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotesFormat {
+    /// The notes as written, eg. the GitHub release body
+    #[default]
+    Markdown,
+    /// Markdown stripped to plain text, for clients which show the content as-is
+    Plain,
+}
+
+/// Public key for detached release asset signatures
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyWith {
+    /// Minisign public key, signatures are `<asset>.minisig`
+    Minisign(String),
+    /// GPG keyring file, signatures are `<asset>.asc` or `<asset>.sig`
+    Gpg(PathBuf),
+}
+
+impl VerifyWith {
+    /// Filename suffixes of the signature sidecar files
+    pub fn signature_suffixes(&self) -> &'static [&'static str] {
+        match self {
+            VerifyWith::Minisign(_) => &[".minisig"],
+            VerifyWith::Gpg(_) => &[".asc", ".sig"],
+        }
+    }
+}
+
+/// App preview image, a URL or a URL with a local copy of the image
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ImageSource {
+    Url(String),
+    File {
+        url: String,
+        /// Local copy of the published image, used to read its dimensions
+        file: PathBuf,
+    },
+}
+
+impl ImageSource {
+    pub fn url(&self) -> &str {
+        match self {
+            ImageSource::Url(url) => url,
+            ImageSource::File { url, .. } => url,
+        }
+    }
+
+    /// Image size as `WxH` from the local copy, [None] for remote images or if it can't be read
+    pub fn dimensions(&self) -> Option<String> {
+        let ImageSource::File { file, .. } = self else {
+            return None;
+        };
+        match image::image_dimensions(file) {
+            Ok((w, h)) => Some(format!("{}x{}", w, h)),
+            Err(e) => {
+                warn!("Failed to read image size of {}: {}", file.display(), e);
+                None
+            }
+        }
+    }
+}
+
+/// A value which applies to all platforms or is keyed by platform
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum PlatformValues<T> {
+    All(T),
+    ByPlatform(BTreeMap<String, T>),
+}
+
+impl<T> PlatformValues<T> {
+    /// Iterate values with the platform they apply to, [None] for all platforms
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (Option<&str>, &T)> + '_> {
+        match self {
+            PlatformValues::All(v) => Box::new(std::iter::once((None, v))),
+            PlatformValues::ByPlatform(m) => Box::new(m.iter().map(|(k, v)| (Some(k.as_str()), v))),
+        }
+    }
+
+    /// Value for a platform, keys can be the full platform tag or just the OS
+    pub fn get(&self, platform: &Platform) -> Option<&T> {
+        match self {
+            PlatformValues::All(v) => Some(v),
+            PlatformValues::ByPlatform(m) => m
+                .get(&platform.to_string())
+                .or_else(|| m.get(platform.os())),
+        }
+    }
+}
+
+impl<T: Default> Default for PlatformValues<T> {
+    fn default() -> Self {
+        PlatformValues::All(T::default())
+    }
+}
+
+/// Tag with an optional platform annotation
+fn platform_tag<'a>(name: &'a str, value: &'a str, platform: Option<&'a str>) -> Vec<&'a str> {
+    let mut tag = vec![name, value];
+    if let Some(p) = platform {
+        tag.push(p);
+    }
+    tag
 }
 
 pub trait AsStrOrEmpty {
@@ -74,3 +656,525 @@ impl AsStrOrEmpty for Option<String> {
         self.as_ref().map(|s| s.as_str()).unwrap_or("")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{Architecture, ArtifactMetadata, RepoArtifact, RepoResource};
+    use config::{Config, File, FileFormat};
+    use nostr_sdk::{Alphabet, Keys, SingleLetterTag, TagKind};
+
+    fn parse_manifest(yaml: &str) -> Manifest {
+        Config::builder()
+            .add_source(File::from_str(yaml, FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn description_and_summary() {
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+description: "Long **markdown** description"
+summary: "Short summary"
+images: []
+tags: []
+"#,
+        );
+        let ev = EventBuilder::from(&manifest)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        assert_eq!(ev.content, "Long **markdown** description");
+        let summary = ev.tags.find(TagKind::custom("summary")).unwrap();
+        assert_eq!(summary.content(), Some("Short summary"));
+    }
+
+    #[test]
+    fn external_id_tags() {
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+tags: []
+external_ids:
+  play: "com.example.app"
+  appstore: "123456"
+  itch: "example/app"
+"#,
+        );
+        assert!(manifest.validate().is_empty());
+        let ev = EventBuilder::from(&manifest)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let tags: Vec<Vec<String>> = ev
+            .tags
+            .filter(TagKind::i())
+            .map(|t| t.as_slice().to_vec())
+            .collect();
+        assert_eq!(
+            tags,
+            [
+                vec![
+                    "i",
+                    "appstore:123456",
+                    "https://apps.apple.com/app/id123456"
+                ],
+                vec!["i", "itch:example/app"],
+                vec![
+                    "i",
+                    "play:com.example.app",
+                    "https://play.google.com/store/apps/details?id=com.example.app"
+                ],
+            ]
+        );
+
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+tags: []
+external_ids:
+  appstore: "id123456"
+"#,
+        );
+        assert_eq!(
+            manifest.validate(),
+            [(
+                "external_ids",
+                "'id123456' is not a valid appstore id".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn category_tags() {
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+tags:
+  - " Video "
+  - "tiktok"
+"#,
+        );
+        assert!(manifest.validate_tags(false).is_ok());
+        assert!(manifest.validate_tags(true).is_err());
+
+        let ev = EventBuilder::from(&manifest)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let tags: Vec<_> = ev.tags.hashtags().collect();
+        assert_eq!(tags, ["video", "tiktok"]);
+    }
+
+    #[test]
+    fn platform_images() {
+        let flat = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+icon: "https://example.com/icon.png"
+images:
+  - "https://example.com/1.png"
+tags: []
+"#,
+        );
+        let ev = EventBuilder::from(&flat)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let images: Vec<_> = ev.tags.filter(TagKind::custom("image")).collect();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].as_slice(), ["image", "https://example.com/1.png"]);
+
+        let by_platform = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+icon:
+  android: "https://example.com/android.png"
+images:
+  android:
+    - "https://example.com/phone.png"
+  web:
+    - "https://example.com/desktop.png"
+tags: []
+"#,
+        );
+        let ev = EventBuilder::from(&by_platform)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let images: Vec<_> = ev
+            .tags
+            .filter(TagKind::custom("image"))
+            .map(|t| t.as_slice().to_vec())
+            .collect();
+        assert_eq!(
+            images,
+            [
+                ["image", "https://example.com/phone.png", "android"],
+                ["image", "https://example.com/desktop.png", "web"],
+            ]
+        );
+        let icon = ev.tags.find(TagKind::custom("icon")).unwrap();
+        assert_eq!(
+            icon.as_slice(),
+            ["icon", "https://example.com/android.png", "android"]
+        );
+    }
+
+    #[test]
+    fn template_content() {
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+description: "{name} v{version} ({app_id}), released {date} {unknown}"
+summary: "{name} {version}"
+tags: []
+"#,
+        );
+        let release = RepoRelease {
+            version: semver::Version::new(1, 2, 3),
+            description: None,
+            url: None,
+            artifacts: vec![],
+            manifest_id: None,
+            published_at: Some("2021-03-04T05:06:07Z".parse().unwrap()),
+        };
+        let release_coord = Coordinate::new(Kind::Custom(30_063), Keys::generate().public_key);
+        let ev = manifest
+            .app_event(&release, release_coord)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        assert_eq!(
+            ev.content,
+            "Example v1.2.3 (com.example.app), released 2021-03-04 {unknown}"
+        );
+        let summary = ev.tags.find(TagKind::custom("summary")).unwrap();
+        assert_eq!(summary.content(), Some("Example 1.2.3"));
+
+        // no release, only the app placeholders are known
+        let ev = EventBuilder::from(&manifest)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        assert_eq!(
+            ev.content,
+            "Example v{version} (com.example.app), released {date} {unknown}"
+        );
+    }
+
+    #[test]
+    fn misspelled_key() {
+        let err = Config::builder()
+            .add_source(File::from_str(
+                r#"
+id: "com.example.app"
+name: "Example"
+tags: []
+changelgo: "CHANGELOG.md"
+"#,
+                FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<Manifest>()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("unknown field `changelgo`"));
+    }
+
+    #[test]
+    fn extra_tags() {
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+tags: []
+extra_tags:
+  - ["p", "13adc511de7e1cfcf1c6b7f6365fb5a03442d7bcacf565ea57fa7770912c023d"]
+  - ["label", "beta"]
+  - []
+  - ["p", "npub-ish"]
+"#,
+        );
+        let ev = EventBuilder::from(&manifest)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let tags: Vec<_> = ev.tags.iter().map(|t| t.as_slice().to_vec()).collect();
+        assert!(tags.ends_with(&[
+            vec![
+                "p".to_string(),
+                "13adc511de7e1cfcf1c6b7f6365fb5a03442d7bcacf565ea57fa7770912c023d".to_string()
+            ],
+            vec!["label".to_string(), "beta".to_string()],
+        ]));
+        assert_eq!(manifest.validate().len(), 2);
+    }
+
+    #[test]
+    fn image_dimensions() {
+        let path = std::env::temp_dir().join("nap-test-screenshot.png");
+        image::RgbImage::new(108, 192).save(&path).unwrap();
+        let jpeg = std::env::temp_dir().join("nap-test-screenshot.jpg");
+        image::RgbImage::new(1080, 1920).save(&jpeg).unwrap();
+        let manifest = parse_manifest(&format!(
+            r#"
+id: "com.example.app"
+name: "Example"
+images:
+  android:
+    - url: "https://example.com/local.png"
+      file: "{}"
+    - url: "https://example.com/local.jpg"
+      file: "{}"
+    - url: "https://example.com/missing.png"
+      file: "/nonexistent/missing.png"
+    - "https://example.com/remote.png"
+tags: []
+"#,
+            path.display(),
+            jpeg.display()
+        ));
+        let ev = EventBuilder::from(&manifest)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&jpeg).unwrap();
+        let tags = |name: &str| -> Vec<_> {
+            ev.tags
+                .filter(TagKind::custom(name))
+                .map(|t| t.as_slice().to_vec())
+                .collect()
+        };
+        assert_eq!(
+            tags("image"),
+            [
+                vec!["image", "https://example.com/local.png", "android"],
+                vec!["image", "https://example.com/local.jpg", "android"],
+                vec!["image", "https://example.com/missing.png", "android"],
+                vec!["image", "https://example.com/remote.png", "android"],
+            ]
+        );
+        assert_eq!(
+            tags("imeta"),
+            [
+                vec!["imeta", "url https://example.com/local.png", "dim 108x192"],
+                vec![
+                    "imeta",
+                    "url https://example.com/local.jpg",
+                    "dim 1080x1920"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_fields() {
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+repository: "https://github.com/example/app"
+license: "MIT OR Apache-2.0"
+icon: "https://example.com/icon.png"
+tags: []
+platforms:
+  - "android"
+  - "linux-x86_64"
+"#,
+        );
+        assert!(manifest.validate().is_empty());
+
+        let manifest = parse_manifest(
+            r#"
+id: "example app"
+name: ""
+repository: "github.com/example/app"
+url: "ftp://example.com"
+license: "MIT-ish"
+images:
+  - "https://example.com/ok.png"
+  - "not a url"
+tags: []
+platforms:
+  - "amiga"
+omit_tags:
+  - "apk_signature_hash"
+  - "x"
+release_extra_tags:
+  - ["p", "not a key"]
+content_types:
+  zip: "application zip"
+"#,
+        );
+        let fields: Vec<&str> = manifest.validate().iter().map(|(f, _)| *f).collect();
+        assert_eq!(
+            fields,
+            [
+                "id",
+                "name",
+                "repository",
+                "url",
+                "images",
+                "license",
+                "platforms",
+                "content_types",
+                "release_extra_tags",
+                "omit_tags"
+            ]
+        );
+    }
+
+    #[test]
+    fn env_interpolation() -> Result<()> {
+        std::env::set_var("NAP_TEST_REPO", "https://github.com/example/app");
+        assert_eq!(
+            interpolate_env("repository: \"${NAP_TEST_REPO}\"\nname: $HOME")?,
+            "repository: \"https://github.com/example/app\"\nname: $HOME"
+        );
+        let err = interpolate_env("url: ${NAP_TEST_UNSET_A}\nicon: ${NAP_TEST_UNSET_B}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.ends_with("NAP_TEST_UNSET_A, NAP_TEST_UNSET_B"));
+        Ok(())
+    }
+
+    #[test]
+    fn lightning_address() {
+        assert!(check_lud16("dev@getalby.com").is_ok());
+        assert!(check_lud16("my.name_1@example.co.uk").is_ok());
+        assert!(check_lud16("Dev@getalby.com").is_err());
+        assert!(check_lud16("dev@localhost").is_err());
+        assert!(check_lud16("dev@exa mple.com").is_err());
+        assert!(check_lud16("getalby.com").is_err());
+
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+lud16: "dev@getalby.com"
+tags: []
+"#,
+        );
+        let ev = EventBuilder::from(&manifest)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let lud16 = ev.tags.find(TagKind::custom("lud16")).unwrap();
+        assert_eq!(lud16.content(), Some("dev@getalby.com"));
+    }
+
+    #[test]
+    fn reference_tags() {
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+repository: "https://github.com/example/app/"
+url: "https://example.com"
+tags: []
+"#,
+        );
+        let ev = EventBuilder::from(&manifest)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let r: Vec<&[String]> = ev
+            .tags
+            .filter(TagKind::SingleLetter(SingleLetterTag::lowercase(
+                Alphabet::R,
+            )))
+            .map(|t| t.as_slice())
+            .collect();
+        assert_eq!(
+            r,
+            [
+                ["r", "https://github.com/example/app.git", "source"],
+                ["r", "https://example.com", "homepage"]
+            ]
+        );
+        let repository = ev.tags.find(TagKind::custom("repository")).unwrap();
+        assert_eq!(
+            repository.content(),
+            Some("https://github.com/example/app/")
+        );
+    }
+
+    #[test]
+    fn app_event_golden() {
+        let manifest = parse_manifest(
+            r#"
+id: "com.example.app"
+name: "Example"
+description: "An example app"
+summary: "Example summary"
+repository: "https://github.com/example/app"
+url: "https://example.com"
+license: "MIT"
+lud16: "dev@example.com"
+icon: "https://example.com/icon.png"
+images:
+  - "https://example.com/screen.png"
+tags:
+  - "Communication"
+"#,
+        );
+        let keys = Keys::generate();
+        let release = RepoRelease {
+            version: semver::Version::new(1, 2, 3),
+            description: None,
+            url: None,
+            artifacts: vec![RepoArtifact {
+                name: "app.apk".to_string(),
+                size: 1,
+                location: RepoResource::Remote("https://example.com/app.apk".to_string()),
+                content_type: "application/vnd.android.package-archive".to_string(),
+                platform: Platform::Android {
+                    arch: Architecture::ARM64,
+                },
+                metadata: ArtifactMetadata::APK {
+                    manifest: Default::default(),
+                    signature_blocks: vec![],
+                    labels: vec![("de".to_string(), "Beispiel".to_string())],
+                    extras: Default::default(),
+                    page_size_16kb: None,
+                },
+                hash: vec![0; 32],
+                download_path: None,
+                downloads: None,
+            }],
+            manifest_id: None,
+            published_at: None,
+        };
+        let release_coord = Coordinate::new(Kind::Custom(30_063), keys.public_key)
+            .identifier("com.example.app@1.2.3");
+        let ev = manifest
+            .app_event(&release, release_coord)
+            .sign_with_keys(&keys)
+            .unwrap();
+        assert_eq!(ev.kind, Kind::Custom(32_267));
+        assert_eq!(ev.content, "An example app");
+        let tags: Vec<&[String]> = ev.tags.iter().map(|t| t.as_slice()).collect();
+        let release_a = format!("30063:{}:com.example.app@1.2.3", keys.public_key);
+        let expected: Vec<Vec<&str>> = vec![
+            vec!["d", "com.example.app"],
+            vec!["name", "Example"],
+            vec!["url", "https://example.com"],
+            vec!["summary", "Example summary"],
+            vec!["lud16", "dev@example.com"],
+            vec!["icon", "https://example.com/icon.png"],
+            vec!["repository", "https://github.com/example/app"],
+            vec!["r", "https://github.com/example/app.git", "source"],
+            vec!["r", "https://example.com", "homepage"],
+            vec!["license", "MIT"],
+            vec!["image", "https://example.com/screen.png"],
+            vec!["t", "communication"],
+            vec!["a", &release_a],
+            vec!["f", "android-arm64-v8a"],
+            vec!["name", "Beispiel", "de"],
+        ];
+        assert_eq!(tags, expected);
+    }
+}
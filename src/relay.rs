@@ -0,0 +1,140 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use nostr_sdk::nips::nip65::{extract_relay_list, RelayMetadata};
+use nostr_sdk::pool::RelayNotification;
+use nostr_sdk::{Client, Filter, Keys, Kind, PublicKey, RelayMessage, RelayUrl};
+use std::time::Duration;
+use tokio::sync::broadcast::Receiver;
+
+/// Relay used when no relays are specified
+pub const DEFAULT_RELAY: &str = "wss://relay.zapstore.dev";
+
+/// Relay indexing NIP-65 relay lists, queried alongside the configured relays
+pub const BOOTSTRAP_RELAY: &str = "wss://purplepag.es";
+
+/// Create a nostr client connected to the given relays (or the default relay),
+/// failing if none of them connects within `timeout`
+pub async fn connect_client(relays: &[String], timeout: Duration) -> Result<Client> {
+    connect(Client::builder().build(), relays, timeout).await
+}
+
+/// Like [connect_client], answering NIP-42 AUTH challenges with `keys` so relays which only
+/// accept writes from authenticated users take the events
+pub async fn connect_authenticated_client(
+    relays: &[String],
+    timeout: Duration,
+    keys: Keys,
+) -> Result<Client> {
+    connect(Client::builder().signer(keys).build(), relays, timeout).await
+}
+
+async fn connect(client: Client, relays: &[String], timeout: Duration) -> Result<Client> {
+    for r in relays {
+        info!("Connecting to {}", r);
+        client.add_relay(r).await?;
+    }
+    if relays.is_empty() {
+        info!("Connecting to default relay {DEFAULT_RELAY}");
+        client.add_relay(DEFAULT_RELAY).await?;
+    }
+    for (url, relay) in client.relays().await {
+        tokio::spawn(log_authentication(url, relay.notifications()));
+    }
+    client.connect().await;
+    client.wait_for_connection(timeout).await;
+
+    let pool = client.relays().await;
+    if !pool.values().any(|r| r.is_connected()) {
+        bail!(
+            "No relay connected within {}s, check the relay URLs or use --relay-timeout",
+            timeout.as_secs()
+        );
+    }
+    for url in pool
+        .iter()
+        .filter(|(_, r)| !r.is_connected())
+        .map(|(u, _)| u)
+    {
+        warn!("Relay {} did not connect, continuing without it", url);
+    }
+    Ok(client)
+}
+
+/// Log the NIP-42 AUTH challenges of a relay and their outcome, the SDK answers them when the
+/// client has a signer
+async fn log_authentication(url: RelayUrl, mut notifications: Receiver<RelayNotification>) {
+    while let Ok(notification) = notifications.recv().await {
+        match notification {
+            RelayNotification::Message {
+                message: RelayMessage::Auth { .. },
+            } => info!("{} requested NIP-42 authentication", url),
+            RelayNotification::Authenticated => info!("Authenticated to {} (NIP-42)", url),
+            RelayNotification::AuthenticationFailed => {
+                warn!("NIP-42 authentication to {} failed", url)
+            }
+            RelayNotification::Shutdown => break,
+            _ => {}
+        }
+    }
+}
+
+/// Look up the write relays from the author's NIP-65 relay list
+pub async fn nip65_write_relays(
+    relays: &[String],
+    pubkey: PublicKey,
+    connect_timeout: Duration,
+    timeout: Duration,
+) -> Result<Vec<String>> {
+    let mut bootstrap = relays.to_vec();
+    bootstrap.push(BOOTSTRAP_RELAY.to_string());
+    let client = connect_client(&bootstrap, connect_timeout).await?;
+    let events = client
+        .fetch_events(
+            Filter::new().kind(Kind::RelayList).author(pubkey).limit(1),
+            timeout,
+        )
+        .await?;
+    client.disconnect().await;
+
+    let Some(list) = events.iter().max_by_key(|e| e.created_at) else {
+        warn!("No NIP-65 relay list found for {}", pubkey);
+        return Ok(vec![]);
+    };
+    let write: Vec<String> = extract_relay_list(list)
+        .filter(|(_, m)| matches!(m, None | Some(RelayMetadata::Write)))
+        .map(|(r, _)| r.to_string())
+        .collect();
+    info!("Found {} NIP-65 write relay(s)", write.len());
+    Ok(write)
+}
+
+/// Merge relay lists, skipping duplicates which only differ by a trailing slash
+pub fn merge_relays(a: &[String], b: &[String]) -> Vec<String> {
+    let mut res: Vec<String> = vec![];
+    for r in a.iter().chain(b) {
+        if !res
+            .iter()
+            .any(|x| x.trim_end_matches('/') == r.trim_end_matches('/'))
+        {
+            res.push(r.clone());
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_relay_lists() {
+        let merged = merge_relays(
+            &["wss://relay.example.com".to_string()],
+            &[
+                "wss://relay.example.com/".to_string(),
+                "wss://nos.lol/".to_string(),
+            ],
+        );
+        assert_eq!(merged, ["wss://relay.example.com", "wss://nos.lol/"]);
+    }
+}
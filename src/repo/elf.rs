@@ -0,0 +1,107 @@
+use anyhow::{anyhow, bail, ensure, Result};
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+const PT_LOAD: u64 = 1;
+
+/// How much of a shared library is read, the program headers follow the ELF header
+pub const HEADER_LEN: u64 = 4096;
+
+/// Smallest alignment of the `LOAD` segments of an ELF file, the largest page size it can be
+/// mapped with
+pub fn load_alignment(data: &[u8]) -> Result<u64> {
+    ensure!(data.starts_with(ELF_MAGIC), "Not an ELF file");
+    let class = *data.get(4).ok_or(anyhow!("ELF header is truncated"))?;
+    let big_endian = match data.get(5) {
+        Some(&ELFDATA2LSB) => false,
+        Some(&ELFDATA2MSB) => true,
+        _ => bail!("unknown ELF byte order"),
+    };
+    let read = |at: u64, len: usize| -> Result<u64> {
+        let bytes = data.get(at as usize..at as usize + len).ok_or(anyhow!(
+            "ELF program headers are not in the first {HEADER_LEN} bytes"
+        ))?;
+        let mut buf = [0u8; 8];
+        if big_endian {
+            buf[8 - len..].copy_from_slice(bytes);
+            Ok(u64::from_be_bytes(buf))
+        } else {
+            buf[..len].copy_from_slice(bytes);
+            Ok(u64::from_le_bytes(buf))
+        }
+    };
+    // (e_phoff, e_phentsize, e_phnum, p_align) offsets and the size of the address fields
+    let (phoff, phentsize, phnum, align, word) = match class {
+        ELFCLASS32 => (0x1c, 0x2a, 0x2c, 0x1c, 4),
+        ELFCLASS64 => (0x20, 0x36, 0x38, 0x30, 8),
+        c => bail!("unknown ELF class {}", c),
+    };
+    let phoff = read(phoff, word)?;
+    let phentsize = read(phentsize, 2)?;
+    let mut ret = None;
+    for i in 0..read(phnum, 2)? {
+        let header = phoff + i * phentsize;
+        if read(header, 4)? == PT_LOAD {
+            let a = read(header + align, word)?;
+            ret = Some(ret.map_or(a, |r: u64| r.min(a)));
+        }
+    }
+    ret.ok_or(anyhow!("No LOAD segments in ELF file"))
+}
+
+/// 64-bit little endian ELF with `LOAD` segments of the given alignments
+#[cfg(test)]
+pub fn elf64(aligns: &[u64]) -> Vec<u8> {
+    let mut data = vec![0; 64];
+    data[..4].copy_from_slice(ELF_MAGIC);
+    data[4] = ELFCLASS64;
+    data[5] = ELFDATA2LSB;
+    data[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+    data[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+    data[0x38..0x3a].copy_from_slice(&(aligns.len() as u16 + 1).to_le_bytes());
+    // PT_DYNAMIC, not mapped with its own alignment
+    let mut dynamic = vec![0; 56];
+    dynamic[..4].copy_from_slice(&2u32.to_le_bytes());
+    dynamic[0x30..0x38].copy_from_slice(&8u64.to_le_bytes());
+    data.extend(dynamic);
+    for align in aligns {
+        let mut header = vec![0; 56];
+        header[..4].copy_from_slice(&(PT_LOAD as u32).to_le_bytes());
+        header[0x30..0x38].copy_from_slice(&align.to_le_bytes());
+        data.extend(header);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_alignment() -> Result<()> {
+        assert_eq!(load_alignment(&elf64(&[0x4000, 0x4000]))?, 0x4000);
+        assert_eq!(load_alignment(&elf64(&[0x4000, 0x1000]))?, 0x1000);
+
+        // 32-bit big endian with one segment
+        let mut elf32 = vec![0; 52];
+        elf32[..4].copy_from_slice(ELF_MAGIC);
+        elf32[4] = ELFCLASS32;
+        elf32[5] = ELFDATA2MSB;
+        elf32[0x1c..0x20].copy_from_slice(&52u32.to_be_bytes());
+        elf32[0x2a..0x2c].copy_from_slice(&32u16.to_be_bytes());
+        elf32[0x2c..0x2e].copy_from_slice(&1u16.to_be_bytes());
+        let mut header = vec![0; 32];
+        header[..4].copy_from_slice(&(PT_LOAD as u32).to_be_bytes());
+        header[0x1c..0x20].copy_from_slice(&0x10000u32.to_be_bytes());
+        elf32.extend(header);
+        assert_eq!(load_alignment(&elf32)?, 0x10000);
+
+        assert!(load_alignment(&elf64(&[])).is_err());
+        assert!(load_alignment(&elf64(&[0x4000])[..100]).is_err());
+        assert!(load_alignment(b"\x7fELF fixture").is_err());
+        Ok(())
+    }
+}
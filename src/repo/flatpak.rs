@@ -0,0 +1,210 @@
+use anyhow::{anyhow, bail, Result};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Metadata from a single-file Flatpak bundle (`flatpak build-bundle`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatpakBundle {
+    /// Application id, from the bundle ref
+    pub app_id: String,
+    /// Flatpak architecture name (`x86_64`, `aarch64`)
+    pub arch: String,
+    /// Branch the app was exported to
+    pub branch: String,
+    /// Runtime ref the app needs (`org.freedesktop.Platform/x86_64/23.08`)
+    pub runtime: Option<String>,
+}
+
+impl FlatpakBundle {
+    /// Read the bundle metadata
+    ///
+    /// A bundle is an ostree static delta superblock, a GVariant tuple whose first
+    /// member is the `a{sv}` metadata dictionary. Only that dictionary is read, the
+    /// rest of the file (the commit and its objects) is never loaded.
+    pub fn from_reader<R: Read + Seek>(r: &mut R) -> Result<FlatpakBundle> {
+        let len = r.seek(SeekFrom::End(0))?;
+        let size = offset_size(len);
+        if len < size as u64 {
+            bail!("Flatpak bundle is too small");
+        }
+        // framing offsets are stored in reverse, the last one is the end of the first member
+        r.seek(SeekFrom::End(-(size as i64)))?;
+        let mut buf = vec![0; size];
+        r.read_exact(&mut buf)?;
+        let end = read_offset(&buf);
+        if end > len {
+            bail!("Invalid Flatpak bundle header");
+        }
+        let mut header = vec![0; end as usize];
+        r.seek(SeekFrom::Start(0))?;
+        r.read_exact(&mut header)?;
+
+        let mut bundle_ref = None;
+        let mut metadata = None;
+        for (key, value) in parse_dict(&header)? {
+            match (key, variant_string(value)) {
+                ("ref", Some(v)) => bundle_ref = Some(v),
+                ("metadata", Some(v)) => metadata = Some(v),
+                _ => {}
+            }
+        }
+        let bundle_ref = bundle_ref.ok_or(anyhow!("Flatpak bundle has no ref"))?;
+        let (app_id, arch, branch) = match bundle_ref.split('/').collect::<Vec<_>>()[..] {
+            ["app", id, arch, branch] => (id, arch, branch),
+            _ => bail!("Not a Flatpak application ref: {}", bundle_ref),
+        };
+        Ok(FlatpakBundle {
+            app_id: app_id.to_string(),
+            arch: arch.to_string(),
+            branch: branch.to_string(),
+            runtime: metadata.and_then(|m| keyfile_value(m, "Application", "runtime")),
+        })
+    }
+}
+
+/// Look up a value in a GLib key file (the Flatpak `metadata` file)
+fn keyfile_value(data: &str, group: &str, key: &str) -> Option<String> {
+    let mut in_group = false;
+    for line in data.lines().map(str::trim) {
+        if let Some(g) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_group = g == group;
+        } else if in_group {
+            if let Some((k, v)) = line.split_once('=') {
+                if k.trim() == key {
+                    return Some(v.trim().to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Size of the framing offsets in a GVariant container of `len` bytes
+fn offset_size(len: u64) -> usize {
+    match len {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x10000..=0xffff_ffff => 4,
+        _ => 8,
+    }
+}
+
+/// Little-endian framing offset
+fn read_offset(data: &[u8]) -> u64 {
+    data.iter()
+        .rev()
+        .fold(0u64, |acc, b| (acc << 8) | *b as u64)
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+/// Split a serialized `a{sv}` into its keys and variant values
+fn parse_dict(data: &[u8]) -> Result<Vec<(&str, &[u8])>> {
+    if data.is_empty() {
+        return Ok(vec![]);
+    }
+    let size = offset_size(data.len() as u64);
+    let table = read_offset(&data[data.len() - size..]) as usize;
+    if table > data.len() || !(data.len() - table).is_multiple_of(size) {
+        bail!("Invalid Flatpak metadata");
+    }
+
+    let mut ret = vec![];
+    let mut start = 0;
+    for framing in data[table..].chunks(size) {
+        let end = read_offset(framing) as usize;
+        if start > end || end > table {
+            bail!("Invalid Flatpak metadata");
+        }
+        ret.push(parse_entry(&data[start..end])?);
+        // {sv} entries are 8 byte aligned because of the variant
+        start = align(end, 8);
+    }
+    Ok(ret)
+}
+
+/// Split a serialized `{sv}` dictionary entry
+fn parse_entry(data: &[u8]) -> Result<(&str, &[u8])> {
+    let size = offset_size(data.len() as u64);
+    if data.len() < size {
+        bail!("Invalid Flatpak metadata entry");
+    }
+    let value_end = data.len() - size;
+    let key_end = read_offset(&data[value_end..]) as usize;
+    if key_end == 0 || key_end > value_end {
+        bail!("Invalid Flatpak metadata entry");
+    }
+    let key = std::str::from_utf8(&data[..key_end - 1])?;
+    let value_start = align(key_end, 8).min(value_end);
+    Ok((key, &data[value_start..value_end]))
+}
+
+/// Value of a variant holding a string (`s`), [None] for other types
+fn variant_string(data: &[u8]) -> Option<&str> {
+    // the type signature follows the value after a zero byte
+    let sep = data.iter().rposition(|b| *b == 0)?;
+    if &data[sep + 1..] != b"s" {
+        return None;
+    }
+    std::str::from_utf8(data[..sep].strip_suffix(&[0])?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Serialize `a{sv}` with string values, only valid for containers under 256 bytes
+    fn build_dict(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut ret = vec![];
+        let mut ends = vec![];
+        for (k, v) in entries {
+            ret.resize(align(ret.len(), 8), 0);
+            let mut entry = format!("{k}\0").into_bytes();
+            let key_end = entry.len() as u8;
+            entry.resize(align(entry.len(), 8), 0);
+            entry.extend_from_slice(format!("{v}\0\0s").as_bytes());
+            entry.push(key_end);
+            ret.extend(entry);
+            ends.push(ret.len() as u8);
+        }
+        ret.extend(ends);
+        ret
+    }
+
+    #[test]
+    fn parse_bundle() -> Result<()> {
+        let mut data = build_dict(&[
+            ("ref", "app/org.example.App/x86_64/stable"),
+            (
+                "metadata",
+                "[Application]\nname=org.example.App\nruntime=org.freedesktop.Platform/x86_64/23.08\n",
+            ),
+        ]);
+        let dict_end = data.len() as u8;
+        // stand-in for the rest of the superblock and its other framing offsets
+        data.extend_from_slice(&[0xaa; 16]);
+        data.extend_from_slice(&[0x10, 0x08, dict_end]);
+
+        let bundle = FlatpakBundle::from_reader(&mut Cursor::new(data))?;
+        assert_eq!(
+            bundle,
+            FlatpakBundle {
+                app_id: "org.example.App".to_string(),
+                arch: "x86_64".to_string(),
+                branch: "stable".to_string(),
+                runtime: Some("org.freedesktop.Platform/x86_64/23.08".to_string()),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_runtime_ref() {
+        let mut data = build_dict(&[("ref", "runtime/org.example.Sdk/x86_64/1")]);
+        let dict_end = data.len() as u8;
+        data.push(dict_end);
+        assert!(FlatpakBundle::from_reader(&mut Cursor::new(data)).is_err());
+    }
+}
@@ -1,47 +1,208 @@
-use crate::repo::{load_artifact_url, Repo, RepoRelease};
-use anyhow::{anyhow, Result};
-use log::{info, warn};
+use crate::manifest::{VerifyWith, VersionSource};
+use crate::repo::{
+    is_sidecar, load_artifact_request, load_checksum_request, load_signature_request,
+    verify_detached, LoadOptions, Repo, RepoArtifact, RepoRelease, TotalProgress,
+};
+use anyhow::{anyhow, bail, Result};
+use apk_parser::DigestMismatch;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
 use nostr_sdk::Url;
-use reqwest::header::{HeaderMap, ACCEPT, USER_AGENT};
-use reqwest::Client;
+use reqwest::header::{HeaderMap, ACCEPT, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{RequestBuilder, StatusCode};
 use semver::Version;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use std::time::Duration;
+
+/// Attempts at an API request before giving up
+const API_ATTEMPTS: u32 = 4;
+
+/// Releases fetched per API request when loading all releases, the maximum GitHub allows
+const RELEASES_PER_PAGE: usize = 100;
 
 pub struct GithubRepo {
-    client: Client,
+    /// REST API base URL
+    api: String,
     owner: String,
     repo: String,
+    app_id: String,
+    opts: LoadOptions,
+    /// Token for authenticated access, needed for private repos
+    token: Option<String>,
 }
 
 impl GithubRepo {
-    pub fn new(owner: String, repo: String) -> GithubRepo {
-        let mut headers = HeaderMap::new();
-        headers.insert(ACCEPT, "application/vnd.github+json".parse().unwrap());
-        headers.insert(
-            USER_AGENT,
-            "nap/1.0 (https://github.com/v0l/nap)".parse().unwrap(),
-        );
-
-        let client = Client::builder().default_headers(headers).build().unwrap();
-
+    pub fn new(
+        api: String,
+        owner: String,
+        repo: String,
+        app_id: String,
+        opts: LoadOptions,
+    ) -> GithubRepo {
+        let token = std::env::var("GITHUB_TOKEN").ok().filter(|t| !t.is_empty());
         GithubRepo {
+            api,
             owner,
             repo,
-            client,
+            app_id,
+            opts,
+            token,
+        }
+    }
+
+    /// API request with the GitHub headers on the shared client
+    fn request(&self, url: &str, accept: &str) -> RequestBuilder {
+        let req = self.opts.client.get(url).header(ACCEPT, accept);
+        match &self.token {
+            Some(token) => req.header(AUTHORIZATION, format!("Bearer {token}")),
+            None => req,
+        }
+    }
+
+    /// Download request of a release asset, through the API when a token is set because the
+    /// browser URLs of private assets need auth
+    fn asset_request(&self, asset: &GithubReleaseArtifact) -> RequestBuilder {
+        match &self.token {
+            Some(_) => self.request(&asset.url, "application/octet-stream"),
+            None => self.opts.client.get(&asset.browser_download_url),
+        }
+    }
+
+    /// Releases of the repo, newest first, following the pages when loading all releases
+    async fn list_releases(&self) -> Result<Vec<GithubRelease>> {
+        let url = format!("{}/repos/{}/{}/releases", self.api, self.owner, self.repo);
+        if !self.opts.all_releases {
+            return self
+                .opts
+                .timings
+                .time("fetch release list", self.get_json(&url))
+                .await;
+        }
+        let mut ret = vec![];
+        for page in 1.. {
+            let releases: Vec<GithubRelease> = self
+                .opts
+                .timings
+                .time(
+                    "fetch release list",
+                    self.get_json(&format!("{url}?per_page={RELEASES_PER_PAGE}&page={page}")),
+                )
+                .await?;
+            let last = releases.len() < RELEASES_PER_PAGE;
+            ret.extend(releases);
+            if last {
+                break;
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Number of assets of a release which are downloaded
+    fn wanted_assets(&self, release: &GithubRelease) -> usize {
+        let wanted = release
+            .assets
+            .iter()
+            .filter(|a| !is_sidecar(&a.name) && self.opts.filter.matches(&a.name))
+            .count();
+        self.opts.max_artifacts.map_or(wanted, |m| wanted.min(m))
+    }
+
+    /// JSON API request, retried with backoff on server errors, rate limits and network errors
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            let (reason, wait) = match self
+                .request(url, "application/vnd.github+json")
+                .send()
+                .await
+            {
+                Ok(rsp) if rsp.status().is_success() => {
+                    let content_type = header_str(rsp.headers(), CONTENT_TYPE).to_string();
+                    return decode_json(url, &content_type, &rsp.text().await?);
+                }
+                Ok(rsp) => match retry_delay(rsp.status(), rsp.headers(), attempt) {
+                    Some(wait) if attempt < API_ATTEMPTS => (rsp.status().to_string(), wait),
+                    _ => {
+                        let status = rsp.status();
+                        let body = rsp.text().await.unwrap_or_default();
+                        bail!(
+                            "GitHub API request {} failed: {}{}",
+                            url,
+                            status,
+                            api_message(&body)
+                                .map(|m| format!(" ({})", m))
+                                .unwrap_or_default()
+                        )
+                    }
+                },
+                Err(e) if attempt < API_ATTEMPTS && (e.is_connect() || e.is_timeout()) => {
+                    (e.to_string(), backoff(attempt))
+                }
+                Err(e) => return Err(e.into()),
+            };
+            warn!(
+                "GitHub API request failed ({}), retrying in {}s",
+                reason,
+                wait.as_secs()
+            );
+            tokio::time::sleep(wait).await;
+            attempt += 1;
         }
     }
 
-    pub fn from_url(url: &str) -> Result<GithubRepo> {
+    pub fn from_url(url: &str, app_id: &str, opts: LoadOptions) -> Result<GithubRepo> {
         let u: Url = url.parse()?;
         let mut segs = u.path_segments().ok_or(anyhow::anyhow!("Invalid URL"))?;
+        let api = match &opts.github_api {
+            Some(api) => api.trim_end_matches('/').to_string(),
+            None => api_url(u.host_str().ok_or(anyhow!("Invalid URL"))?),
+        };
         Ok(GithubRepo::new(
+            api,
             segs.next().ok_or(anyhow!("Invalid URL"))?.to_string(),
             segs.next().ok_or(anyhow!("Invalid URL"))?.to_string(),
+            app_id.to_string(),
+            opts,
         ))
     }
 }
 
+impl GithubRepo {
+    /// Check the detached signature asset of a downloaded artifact, missing signatures fail
+    async fn verify_signature(
+        &self,
+        key: &VerifyWith,
+        assets: &[GithubReleaseArtifact],
+        asset: &GithubReleaseArtifact,
+        artifact: &RepoArtifact,
+    ) -> Result<()> {
+        let sig = key
+            .signature_suffixes()
+            .iter()
+            .find_map(|s| {
+                assets
+                    .iter()
+                    .find(|a| a.name == format!("{}{}", asset.name, s))
+            })
+            .ok_or(anyhow!(
+                "No signature found for {}, refusing to publish",
+                asset.name
+            ))?;
+        let path = artifact
+            .download_path
+            .as_ref()
+            .ok_or(anyhow!("{} was not downloaded", asset.name))?;
+        let data = load_signature_request(self.asset_request(sig)).await?;
+        verify_detached(key, path, &data)
+            .map_err(|e| anyhow!("Invalid signature for {}: {}", asset.name, e))?;
+        info!("Verified signature of {}", asset.name);
+        Ok(())
+    }
+}
+
 #[derive(Deserialize)]
+#[allow(dead_code)]
 struct GithubRelease {
     pub tag_name: String,
     #[serde(rename = "html_url")]
@@ -51,40 +212,118 @@ struct GithubRelease {
     #[serde(rename = "prerelease")]
     pub pre_release: bool,
     pub body: String,
+    pub published_at: Option<DateTime<Utc>>,
     pub assets: Vec<GithubReleaseArtifact>,
 }
 
 #[derive(Deserialize)]
+#[allow(dead_code)]
 struct GithubReleaseArtifact {
     pub name: String,
     pub size: u64,
     pub content_type: String,
     pub browser_download_url: String,
+    /// API endpoint of the asset
+    pub url: String,
+    #[serde(default)]
+    pub download_count: Option<u64>,
 }
 
 #[async_trait::async_trait]
 impl Repo for GithubRepo {
     async fn get_releases(&self) -> Result<Vec<RepoRelease>> {
         info!(
-            "Fetching release from: github.com/{}/{}",
-            self.owner, self.repo
+            "Fetching release from: {}/repos/{}/{}",
+            self.api, self.owner, self.repo
         );
-        let req = self
-            .client
-            .get(format!(
-                "https://api.github.com/repos/{}/{}/releases",
-                self.owner, self.repo
-            ))
-            .build()?;
-
-        let gh_release: Vec<GithubRelease> = self.client.execute(req).await?.json().await?;
+        let gh_release: Vec<GithubRelease> = self
+            .list_releases()
+            .await?
+            .into_iter()
+            .filter(|r| match &self.opts.tag_prefix {
+                Some(prefix) if !r.tag_name.starts_with(prefix.as_str()) => {
+                    debug!("Skipping release {}, tag prefix mismatch", r.tag_name);
+                    false
+                }
+                _ => true,
+            })
+            .collect();
 
+        // without `all_releases` only the latest release with artifacts is loaded
+        let (count, assets) = if self.opts.all_releases {
+            let assets = gh_release.iter().map(|r| self.wanted_assets(r)).sum();
+            (gh_release.len(), assets)
+        } else {
+            (1, gh_release.first().map_or(0, |r| self.wanted_assets(r)))
+        };
         let mut releases = vec![];
+        let mut progress = TotalProgress::new(&self.opts.progress, count, assets as u64);
         for release in gh_release {
+            let tag = match &self.opts.tag_prefix {
+                Some(prefix) => release
+                    .tag_name
+                    .strip_prefix(prefix.as_str())
+                    .unwrap_or(&release.tag_name),
+                None => release.tag_name.as_str(),
+            };
+            let tag_version = parse_tag_version(tag);
+            progress.start_release(&release.tag_name, self.wanted_assets(&release) as u64);
             let mut artifacts = vec![];
-            for gh_artifact in release.assets {
-                match load_artifact_url(&gh_artifact.browser_download_url).await {
-                    Ok(a) => artifacts.push(a),
+            for gh_artifact in &release.assets {
+                if is_sidecar(&gh_artifact.name) {
+                    debug!("Skipping sidecar file {}", gh_artifact.name);
+                    continue;
+                }
+                if !self.opts.filter.matches(&gh_artifact.name) {
+                    info!("Skipping excluded asset {}", gh_artifact.name);
+                    continue;
+                }
+                if let Some(max) = self.opts.max_artifacts.filter(|m| artifacts.len() >= *m) {
+                    info!(
+                        "Loaded {} artifacts for {}, skipping the remaining assets",
+                        max, release.tag_name
+                    );
+                    break;
+                }
+                let checksum = match release.assets.iter().find(|a| {
+                    a.name == format!("{}.sha256", gh_artifact.name)
+                        || a.name == format!("{}.sha512", gh_artifact.name)
+                }) {
+                    Some(sidecar) => {
+                        match load_checksum_request(
+                            self.asset_request(sidecar),
+                            &sidecar.browser_download_url,
+                        )
+                        .await
+                        {
+                            Ok(c) => Some(c),
+                            Err(e) => {
+                                warn!("Failed to load checksum {}: {}", sidecar.name, e);
+                                None
+                            }
+                        }
+                    }
+                    None => None,
+                };
+                let loaded = load_artifact_request(
+                    self.asset_request(gh_artifact),
+                    &gh_artifact.browser_download_url,
+                    &self.opts,
+                    checksum.as_ref(),
+                )
+                .await;
+                progress.inc();
+                match loaded {
+                    Ok(mut a) => {
+                        a.downloads = gh_artifact.download_count;
+                        if let Some(key) = &self.opts.verify_with {
+                            self.verify_signature(key, &release.assets, gh_artifact, &a)
+                                .await?;
+                        }
+                        artifacts.push(a)
+                    }
+                    // never skip over a tampered artifact
+                    Err(e) if e.is::<DigestMismatch>() => return Err(e),
                     Err(e) => warn!(
                         "Failed to load artifact {}: {}",
                         gh_artifact.browser_download_url, e
@@ -95,20 +334,448 @@ impl Repo for GithubRepo {
                 warn!("No artifacts found for {}", release.tag_name);
                 continue;
             }
+            let version = match tag_version {
+                Ok(v) if self.opts.version_source == VersionSource::Tag => v,
+                tag_version => match RepoRelease::artifact_version(&artifacts) {
+                    Some(v) => {
+                        info!(
+                            "Using version {} of the artifacts for {}",
+                            v, release.tag_name
+                        );
+                        v
+                    }
+                    None => match tag_version {
+                        Ok(v) => {
+                            warn!(
+                                "No artifact version found for {}, using the tag",
+                                release.tag_name
+                            );
+                            v
+                        }
+                        Err(e) => bail!(
+                            "Tag {} is not a version ({}) and the artifacts have none",
+                            release.tag_name,
+                            e
+                        ),
+                    },
+                },
+            };
             releases.push(RepoRelease {
-                version: Version::parse(if release.tag_name.starts_with("v") {
-                    &release.tag_name[1..]
-                } else {
-                    &release.tag_name
-                })?,
+                version,
                 description: Some(release.body),
                 url: Some(release.url),
                 artifacts,
+                manifest_id: Some(self.app_id.clone()),
+                published_at: release.published_at,
             });
-
-            //TODO: handle more than one release
-            break;
+            if !self.opts.all_releases {
+                break;
+            }
         }
         Ok(releases)
     }
 }
+
+/// REST API base URL for a GitHub host, GitHub Enterprise serves it under `/api/v3`
+fn api_url(host: &str) -> String {
+    if host == "github.com" {
+        "https://api.github.com".to_string()
+    } else {
+        format!("https://{host}/api/v3")
+    }
+}
+
+/// Exponential backoff, 2s, 4s, 8s..
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << attempt)
+}
+
+/// How long to wait before retrying a failed API response, [None] if it should not be retried
+///
+/// Rate limits (403/429) honor the `Retry-After` header, a 403 without it is a permission error.
+fn retry_delay(status: StatusCode, headers: &HeaderMap, attempt: u32) -> Option<Duration> {
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    match status {
+        StatusCode::FORBIDDEN => retry_after,
+        StatusCode::TOO_MANY_REQUESTS => Some(retry_after.unwrap_or(backoff(attempt))),
+        s if s.is_server_error() => Some(backoff(attempt)),
+        _ => None,
+    }
+}
+
+/// Header value as text, empty when missing or not visible ASCII
+fn header_str(headers: &HeaderMap, name: reqwest::header::HeaderName) -> &str {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+}
+
+/// Parse an API response body, with a readable error for HTML error pages and other bodies
+/// which aren't JSON, eg. from a proxy or captive portal
+fn decode_json<T: DeserializeOwned>(url: &str, content_type: &str, body: &str) -> Result<T> {
+    serde_json::from_str(body).map_err(|e| {
+        let trimmed = body.trim_start();
+        if content_type.contains("json") || trimmed.starts_with(['{', '[']) {
+            anyhow!("Invalid JSON from GitHub API request {}: {}", url, e)
+        } else {
+            let snippet: String = trimmed
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .chars()
+                .take(120)
+                .collect();
+            anyhow!(
+                "GitHub API request {} returned {} instead of JSON: {}",
+                url,
+                if content_type.is_empty() {
+                    "no content type"
+                } else {
+                    content_type
+                },
+                snippet
+            )
+        }
+    })
+}
+
+/// `message` of a GitHub API error body, eg. the rate limit explanation
+fn api_message(body: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct ApiError {
+        message: String,
+    }
+    serde_json::from_str::<ApiError>(body)
+        .ok()
+        .map(|e| e.message)
+}
+
+/// Parse a semver version from a git tag, with or without the `v` prefix
+fn parse_tag_version(tag: &str) -> Result<Version> {
+    Ok(Version::parse(tag.strip_prefix('v').unwrap_or(tag))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_version() -> Result<()> {
+        assert_eq!(parse_tag_version("v1.2.3")?, Version::new(1, 2, 3));
+        assert_eq!(parse_tag_version("1.2.3")?, Version::new(1, 2, 3));
+        assert_eq!(
+            parse_tag_version("myapp-v1.2.3".strip_prefix("myapp-").unwrap())?,
+            Version::new(1, 2, 3)
+        );
+        assert!(parse_tag_version("myapp-v1.2.3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn api_retry_delay() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            retry_delay(StatusCode::BAD_GATEWAY, &headers, 1),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(retry_delay(StatusCode::FORBIDDEN, &headers, 1), None);
+        assert_eq!(retry_delay(StatusCode::NOT_FOUND, &headers, 1), None);
+
+        headers.insert(RETRY_AFTER, "30".parse().unwrap());
+        assert_eq!(
+            retry_delay(StatusCode::FORBIDDEN, &headers, 1),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_delay(StatusCode::TOO_MANY_REQUESTS, &headers, 2),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[tokio::test]
+    async fn api_response_bodies() -> Result<()> {
+        use flate2::write::GzEncoder;
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let mut gz = GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(br#"[{"tag_name": "v1.0.0"}]"#)?;
+        let responses = [
+            (
+                "content-type: application/json\r\ncontent-encoding: gzip",
+                gz.finish()?,
+            ),
+            (
+                "content-type: text/html",
+                b"<html>\n<body>Service unavailable</body></html>".to_vec(),
+            ),
+        ];
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let api = format!("http://{}", server.local_addr()?);
+        std::thread::spawn(move || -> Result<()> {
+            for (headers, body) in responses {
+                let (mut conn, _) = server.accept()?;
+                let _request = conn.read(&mut [0; 1024])?;
+                conn.write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\n{}\r\ncontent-length: {}\r\n\r\n",
+                        headers,
+                        body.len()
+                    )
+                    .as_bytes(),
+                )?;
+                conn.write_all(&body)?;
+            }
+            Ok(())
+        });
+
+        let repo = GithubRepo::from_url(
+            "https://github.com/example/app",
+            "com.example.app",
+            LoadOptions {
+                client: crate::repo::http_client(Duration::from_secs(10), None)?,
+                github_api: Some(api.clone()),
+                ..Default::default()
+            },
+        )?;
+        let url = format!("{}/releases", api);
+        let releases: Vec<serde_json::Value> = repo.get_json(&url).await?;
+        assert_eq!(releases[0]["tag_name"], "v1.0.0");
+        let err = repo
+            .get_json::<Vec<serde_json::Value>>(&url)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "GitHub API request {} returned text/html instead of JSON: \
+                <html> <body>Service unavailable</body></html>",
+                url
+            )
+        );
+
+        assert!(decode_json::<Vec<u8>>(&url, "application/json", "[1,")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid JSON from GitHub API request"));
+        assert_eq!(
+            api_message(r#"{"message": "API rate limit exceeded", "documentation_url": ""}"#)
+                .as_deref(),
+            Some("API rate limit exceeded")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn private_asset_requests() -> Result<()> {
+        let asset: GithubReleaseArtifact = serde_json::from_str(
+            r#"{
+                "name": "app.apk.sha256",
+                "size": 64,
+                "content_type": "text/plain",
+                "browser_download_url": "https://github.com/v0l/nap/releases/download/v1.0.0/app.apk.sha256",
+                "url": "https://api.github.com/repos/v0l/nap/releases/assets/1"
+            }"#,
+        )?;
+        let mut repo = GithubRepo::from_url(
+            "https://github.com/v0l/nap",
+            "io.v0l.nap",
+            LoadOptions::default(),
+        )?;
+        repo.token = None;
+        let public = repo.asset_request(&asset).build()?;
+        assert_eq!(public.url().as_str(), asset.browser_download_url);
+        assert!(public.headers().get(AUTHORIZATION).is_none());
+
+        repo.token = Some("secret".to_string());
+        let private = repo.asset_request(&asset).build()?;
+        assert_eq!(private.url().as_str(), asset.url);
+        assert_eq!(private.headers()[AUTHORIZATION], "Bearer secret");
+        assert_eq!(private.headers()[ACCEPT], "application/octet-stream");
+        Ok(())
+    }
+
+    #[test]
+    fn enterprise_api_url() -> Result<()> {
+        let repo = GithubRepo::from_url(
+            "https://github.com/v0l/nap",
+            "io.v0l.nap",
+            LoadOptions::default(),
+        )?;
+        assert_eq!(repo.api, "https://api.github.com");
+        let repo = GithubRepo::from_url(
+            "https://github.mycorp.com/team/app",
+            "com.mycorp.app",
+            LoadOptions::default(),
+        )?;
+        assert_eq!(repo.api, "https://github.mycorp.com/api/v3");
+        assert_eq!((repo.owner.as_str(), repo.repo.as_str()), ("team", "app"));
+        let repo = GithubRepo::from_url(
+            "https://git.mycorp.com/team/app",
+            "com.mycorp.app",
+            LoadOptions {
+                github_api: Some("https://git.mycorp.com/api/v3/".to_string()),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(repo.api, "https://git.mycorp.com/api/v3");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_all_releases() -> Result<()> {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let release = |i: usize| {
+            serde_json::json!({
+                "tag_name": format!("v1.0.{i}"),
+                "html_url": "",
+                "name": "",
+                "draft": false,
+                "prerelease": false,
+                "body": "",
+                "assets": [],
+            })
+        };
+        let pages = [
+            (0..RELEASES_PER_PAGE).map(release).collect::<Vec<_>>(),
+            vec![release(RELEASES_PER_PAGE)],
+        ];
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let api = format!("http://{}", server.local_addr()?);
+        let paths = std::thread::spawn(move || -> Result<Vec<String>> {
+            let mut paths = vec![];
+            for page in pages {
+                let (mut conn, _) = server.accept()?;
+                let mut buf = [0; 1024];
+                let len = conn.read(&mut buf)?;
+                let request = String::from_utf8_lossy(&buf[..len]).to_string();
+                paths.extend(request.split_whitespace().nth(1).map(|p| p.to_string()));
+                let body = serde_json::to_vec(&page)?;
+                conn.write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )?;
+                conn.write_all(&body)?;
+            }
+            Ok(paths)
+        });
+
+        let repo = GithubRepo::from_url(
+            "https://github.com/example/app",
+            "com.example.app",
+            LoadOptions {
+                github_api: Some(api),
+                all_releases: true,
+                ..Default::default()
+            },
+        )?;
+        let releases = repo.list_releases().await?;
+        assert_eq!(releases.len(), RELEASES_PER_PAGE + 1);
+        assert_eq!(releases[RELEASES_PER_PAGE].tag_name, "v1.0.100");
+        assert_eq!(
+            paths.join().unwrap()?,
+            [
+                "/repos/example/app/releases?per_page=100&page=1",
+                "/repos/example/app/releases?per_page=100&page=2",
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn load_all_releases() -> Result<()> {
+        use apk_parser::zip::write::{FileOptions, ZipWriter};
+        use std::io::{Cursor, Read, Write};
+        use std::net::TcpListener;
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("manifest.webmanifest", FileOptions::default())?;
+        zip.write_all(br#"{"name": "Example"}"#)?;
+        let bundle = zip.finish()?.into_inner();
+
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let api = format!("http://{}", server.local_addr()?);
+        let release = |tag: &str, published_at: &str| {
+            let url = format!("{api}/download/{tag}/app.zip");
+            serde_json::json!({
+                "tag_name": tag,
+                "html_url": "",
+                "name": "",
+                "draft": false,
+                "prerelease": false,
+                "body": "",
+                "published_at": published_at,
+                "assets": [{
+                    "name": "app.zip",
+                    "size": bundle.len(),
+                    "content_type": "application/zip",
+                    "browser_download_url": url,
+                    "url": url,
+                }],
+            })
+        };
+        let list = serde_json::to_vec(&[
+            release("v1.1.0", "2021-03-04T05:06:07Z"),
+            release("v1.0.0", "2020-01-02T03:04:05Z"),
+        ])?;
+        std::thread::spawn(move || -> Result<()> {
+            for conn in server.incoming() {
+                let mut conn = conn?;
+                let mut buf = [0; 1024];
+                let len = conn.read(&mut buf)?;
+                let (content_type, body) = if buf[..len].starts_with(b"GET /repos/") {
+                    ("application/json", &list)
+                } else {
+                    ("application/zip", &bundle)
+                };
+                conn.write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                        content_type,
+                        body.len()
+                    )
+                    .as_bytes(),
+                )?;
+                conn.write_all(body)?;
+            }
+            Ok(())
+        });
+
+        let cache_dir = std::env::temp_dir().join("nap-test-all-releases");
+        let repo = GithubRepo::from_url(
+            "https://github.com/example/app",
+            "com.example.app",
+            LoadOptions {
+                github_api: Some(api),
+                cache_dir: Some(cache_dir.clone()),
+                all_releases: true,
+                ..Default::default()
+            },
+        )?;
+        let releases = repo.get_releases().await;
+        std::fs::remove_dir_all(&cache_dir)?;
+        // every release is dated by its own publish date for --preserve-dates
+        let dates: Vec<(String, u64)> = releases?
+            .iter()
+            .map(|r| (r.version.to_string(), r.published_timestamp().as_u64()))
+            .collect();
+        assert_eq!(
+            dates,
+            [
+                ("1.1.0".to_string(), 1614834367),
+                ("1.0.0".to_string(), 1577934245)
+            ]
+        );
+        Ok(())
+    }
+}
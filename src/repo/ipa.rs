@@ -0,0 +1,216 @@
+use crate::repo::Architecture;
+use anyhow::{anyhow, bail, Result};
+use apk_parser::zip::ZipArchive;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// `FAT_MAGIC` and `FAT_MAGIC_64`, fat headers are always big endian
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+
+/// `MH_MAGIC` and `MH_MAGIC_64` of a thin Mach-O, read in the file byte order
+const MH_MAGIC: u32 = 0xfeed_face;
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+
+const CPU_ARCH_ABI64: i32 = 0x0100_0000;
+const CPU_TYPE_X86: i32 = 7;
+const CPU_TYPE_ARM: i32 = 12;
+const CPU_SUBTYPE_MASK: i32 = 0xff00_0000_u32 as i32;
+const CPU_SUBTYPE_ARM64E: i32 = 2;
+
+/// How much of the executable is read, enough for a fat header with hundreds of slices
+const HEADER_LEN: u64 = 4096;
+
+/// Bundle info of an iOS app archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpaInfo {
+    pub info: InfoPlist,
+    /// Architectures of the main executable, one per slice of a fat binary
+    pub slices: Vec<Architecture>,
+}
+
+/// Keys from the app bundle `Info.plist`
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct InfoPlist {
+    #[serde(rename = "CFBundleIdentifier")]
+    pub bundle_id: String,
+    #[serde(rename = "CFBundleName")]
+    pub name: Option<String>,
+    #[serde(rename = "CFBundleExecutable")]
+    pub executable: String,
+    /// Marketing version, eg. `1.2.0`
+    #[serde(rename = "CFBundleShortVersionString")]
+    pub version: Option<String>,
+    /// Build number
+    #[serde(rename = "CFBundleVersion")]
+    pub build: Option<String>,
+}
+
+impl IpaInfo {
+    /// Read the `Info.plist` and main executable architectures from an `.ipa`
+    pub fn from_ipa(path: &Path) -> Result<IpaInfo> {
+        let mut zip = ZipArchive::new(File::open(path)?)?;
+        let plist_name = zip
+            .file_names()
+            .find(|n| is_bundle_info(n))
+            .ok_or(anyhow!("No Payload/*.app/Info.plist in {}", path.display()))?
+            .to_string();
+        let mut data = vec![];
+        zip.by_name(&plist_name)?.read_to_end(&mut data)?;
+        let info: InfoPlist = plist::from_bytes(&data)?;
+
+        let bundle = plist_name.trim_end_matches("Info.plist");
+        let mut header = vec![];
+        zip.by_name(&format!("{}{}", bundle, info.executable))
+            .map_err(|e| anyhow!("Failed to open executable {}: {}", info.executable, e))?
+            .take(HEADER_LEN)
+            .read_to_end(&mut header)?;
+        Ok(IpaInfo {
+            slices: macho_architectures(&header)?,
+            info,
+        })
+    }
+}
+
+/// `Payload/<name>.app/Info.plist`, the bundle may contain more plists in frameworks
+fn is_bundle_info(name: &str) -> bool {
+    matches!(
+        name.split('/').collect::<Vec<_>>().as_slice(),
+        ["Payload", app, "Info.plist"] if app.ends_with(".app")
+    )
+}
+
+/// Architectures of a Mach-O executable from its header, every slice of a fat binary
+pub fn macho_architectures(data: &[u8]) -> Result<Vec<Architecture>> {
+    let be_u32 = |at: usize| -> Result<u32> {
+        Ok(u32::from_be_bytes(
+            data.get(at..at + 4)
+                .ok_or(anyhow!("Mach-O header is truncated"))?
+                .try_into()?,
+        ))
+    };
+    let magic = be_u32(0)?;
+    let entry_len = match magic {
+        FAT_MAGIC => 20,
+        FAT_MAGIC_64 => 32,
+        _ => {
+            // thin binary, the header uses the byte order of the target
+            let cpu = match (magic, magic.swap_bytes()) {
+                (MH_MAGIC | MH_MAGIC_64, _) => (be_u32(4)? as i32, be_u32(8)? as i32),
+                (_, MH_MAGIC | MH_MAGIC_64) => (
+                    be_u32(4)?.swap_bytes() as i32,
+                    be_u32(8)?.swap_bytes() as i32,
+                ),
+                _ => bail!("Not a Mach-O executable"),
+            };
+            return Ok(vec![cpu_architecture(cpu.0, cpu.1)?]);
+        }
+    };
+    let count = be_u32(4)? as usize;
+    (0..count)
+        .map(|i| {
+            let at = 8 + i * entry_len;
+            cpu_architecture(be_u32(at)? as i32, be_u32(at + 4)? as i32)
+        })
+        .collect()
+}
+
+fn cpu_architecture(cpu_type: i32, cpu_subtype: i32) -> Result<Architecture> {
+    Ok(match cpu_type {
+        CPU_TYPE_ARM => Architecture::ARMv7,
+        t if t == CPU_TYPE_ARM | CPU_ARCH_ABI64 => {
+            if cpu_subtype & !CPU_SUBTYPE_MASK == CPU_SUBTYPE_ARM64E {
+                Architecture::ARM64e
+            } else {
+                Architecture::ARM64
+            }
+        }
+        CPU_TYPE_X86 => Architecture::X86,
+        t if t == CPU_TYPE_X86 | CPU_ARCH_ABI64 => Architecture::X86_64,
+        t => bail!("unknown Mach-O cpu type: {:#x}", t),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apk_parser::zip::write::{FileOptions, ZipWriter};
+    use std::io::Write;
+
+    const ARM64: i32 = CPU_TYPE_ARM | CPU_ARCH_ABI64;
+
+    /// Fat header with `(cpu_type, cpu_subtype)` slices
+    fn fat_header(slices: &[(i32, i32)]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(FAT_MAGIC.to_be_bytes());
+        data.extend((slices.len() as u32).to_be_bytes());
+        for (cpu, sub) in slices {
+            data.extend(cpu.to_be_bytes());
+            data.extend(sub.to_be_bytes());
+            data.extend([0; 12]);
+        }
+        data
+    }
+
+    #[test]
+    fn macho_slices() -> Result<()> {
+        // arm64e with the pointer authentication ABI version in the capability bits
+        let slices = fat_header(&[
+            (ARM64, 0),
+            (ARM64, CPU_SUBTYPE_ARM64E | 0x8000_0000_u32 as i32),
+        ]);
+        assert_eq!(
+            macho_architectures(&slices)?,
+            [Architecture::ARM64, Architecture::ARM64e]
+        );
+
+        // thin little endian arm64 executable
+        let mut thin = vec![];
+        thin.extend(MH_MAGIC_64.to_le_bytes());
+        thin.extend(ARM64.to_le_bytes());
+        thin.extend(0_i32.to_le_bytes());
+        assert_eq!(macho_architectures(&thin)?, [Architecture::ARM64]);
+
+        assert!(macho_architectures(b"\x7fELF\0\0\0\0").is_err());
+        assert!(macho_architectures(&fat_header(&[(ARM64, 0)])[..12]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn read_ipa() -> Result<()> {
+        let path = std::env::temp_dir().join("nap-test-app.ipa");
+        let mut zip = ZipWriter::new(File::create(&path)?);
+        zip.start_file(
+            "Payload/Example.app/Frameworks/Lib.framework/Info.plist",
+            FileOptions::default(),
+        )?;
+        zip.write_all(b"not a plist")?;
+        zip.start_file("Payload/Example.app/Info.plist", FileOptions::default())?;
+        zip.write_all(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>CFBundleIdentifier</key><string>com.example.app</string>
+    <key>CFBundleName</key><string>Example</string>
+    <key>CFBundleExecutable</key><string>Example</string>
+    <key>CFBundleShortVersionString</key><string>1.2.0</string>
+    <key>CFBundleVersion</key><string>42</string>
+</dict>
+</plist>"#,
+        )?;
+        zip.start_file("Payload/Example.app/Example", FileOptions::default())?;
+        zip.write_all(&fat_header(&[(ARM64, 0), (ARM64, CPU_SUBTYPE_ARM64E)]))?;
+        zip.finish()?;
+
+        let ipa = IpaInfo::from_ipa(&path);
+        std::fs::remove_file(&path)?;
+        let ipa = ipa?;
+        assert_eq!(ipa.info.bundle_id, "com.example.app");
+        assert_eq!(ipa.info.version.as_deref(), Some("1.2.0"));
+        assert_eq!(ipa.info.build.as_deref(), Some("42"));
+        assert_eq!(ipa.slices, [Architecture::ARM64, Architecture::ARM64e]);
+        Ok(())
+    }
+}
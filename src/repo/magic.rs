@@ -0,0 +1,152 @@
+use anyhow::{bail, Result};
+use apk_parser::zip::ZipArchive;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Artifact type detected from the file content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Apk,
+    Ipa,
+    Zip,
+    Gzip,
+    Squashfs,
+    /// Usually an error or login page served instead of the file
+    Html,
+    Unknown,
+}
+
+impl FileType {
+    /// Type expected for a file extension, [None] if it can't be detected from the content
+    pub fn from_extension(ext: &str) -> Option<FileType> {
+        Some(match ext {
+            "apk" => FileType::Apk,
+            "ipa" => FileType::Ipa,
+            "zip" => FileType::Zip,
+            "gz" | "tgz" | "tar.gz" => FileType::Gzip,
+            "snap" => FileType::Squashfs,
+            _ => return None,
+        })
+    }
+
+    /// Detect the type from the magic bytes, ZIPs are told apart by their entries
+    pub fn detect(path: &Path) -> Result<FileType> {
+        let mut head = vec![];
+        File::open(path)?.take(512).read_to_end(&mut head)?;
+        Ok(match head.as_slice() {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => zip_type(path),
+            [0x1f, 0x8b, ..] => FileType::Gzip,
+            [b'h', b's', b'q', b's', ..] => FileType::Squashfs,
+            _ if is_html(&head) => FileType::Html,
+            _ => FileType::Unknown,
+        })
+    }
+}
+
+impl Display for FileType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileType::Apk => write!(f, "APK"),
+            FileType::Ipa => write!(f, "IPA"),
+            FileType::Zip => write!(f, "ZIP"),
+            FileType::Gzip => write!(f, "gzip"),
+            FileType::Squashfs => write!(f, "squashfs"),
+            FileType::Html => write!(f, "HTML"),
+            FileType::Unknown => write!(f, "unknown data"),
+        }
+    }
+}
+
+fn zip_type(path: &Path) -> FileType {
+    let Ok(Ok(zip)) = File::open(path).map(ZipArchive::new) else {
+        // corrupt archive, let the loader report it
+        return FileType::Zip;
+    };
+    if zip.file_names().any(|n| n == "AndroidManifest.xml") {
+        FileType::Apk
+    } else if zip.file_names().any(|n| n.starts_with("Payload/")) {
+        FileType::Ipa
+    } else {
+        FileType::Zip
+    }
+}
+
+fn is_html(head: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(head)
+        .trim_start()
+        .to_ascii_lowercase();
+    text.starts_with("<!doctype html") || text.starts_with("<html")
+}
+
+/// Check the content of an artifact matches its extension, so a mislabeled file fails with
+/// a clear error instead of deep inside the parser of another format
+pub fn check_file_type(path: &Path, ext: &str) -> Result<()> {
+    let Some(expected) = FileType::from_extension(ext) else {
+        return Ok(());
+    };
+    let found = FileType::detect(path)?;
+    if found != expected {
+        let hint = match (expected, found) {
+            (FileType::Apk, FileType::Zip) => " without AndroidManifest.xml",
+            (FileType::Ipa, FileType::Zip) => " without a Payload folder",
+            _ => "",
+        };
+        bail!("expected {}, got {}{}", expected, found, hint);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apk_parser::zip::write::{FileOptions, ZipWriter};
+    use std::io::Write;
+
+    fn write_zip(path: &Path, entries: &[&str]) -> Result<()> {
+        let mut zip = ZipWriter::new(File::create(path)?);
+        for name in entries {
+            zip.start_file(*name, FileOptions::default())?;
+            zip.write_all(b"data")?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn detect_mislabeled_files() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let apk = dir.join("nap-test-magic.apk");
+        write_zip(&apk, &["AndroidManifest.xml", "classes.dex"])?;
+        let web = dir.join("nap-test-magic-web.apk");
+        write_zip(&web, &["index.html"])?;
+        let html = dir.join("nap-test-magic-page.apk");
+        std::fs::write(
+            &html,
+            "\n<!DOCTYPE html><html><body>Not found</body></html>",
+        )?;
+
+        let results = [
+            check_file_type(&apk, "apk").map_err(|e| e.to_string()),
+            check_file_type(&apk, "zip").map_err(|e| e.to_string()),
+            check_file_type(&web, "apk").map_err(|e| e.to_string()),
+            check_file_type(&html, "apk").map_err(|e| e.to_string()),
+            check_file_type(&html, "flatpak").map_err(|e| e.to_string()),
+        ];
+        for p in [apk, web, html] {
+            std::fs::remove_file(p)?;
+        }
+        assert_eq!(
+            results,
+            [
+                Ok(()),
+                Err("expected ZIP, got APK".to_string()),
+                Err("expected APK, got ZIP without AndroidManifest.xml".to_string()),
+                Err("expected APK, got HTML".to_string()),
+                Ok(()),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -1,23 +1,53 @@
-use crate::manifest::Manifest;
+use crate::changelog::{markdown_to_plain, normalize_notes};
+use crate::manifest::{
+    parse_extra_tags, Manifest, NotesFormat, PlatformValues, VerifyWith, VersionSource,
+};
+use crate::repo::flatpak::FlatpakBundle;
 use crate::repo::github::GithubRepo;
-use anyhow::{anyhow, bail, ensure, Result};
-use apk_parser::zip::ZipArchive;
-use apk_parser::{parse_android_manifest, AndroidManifest, ApkSignatureBlock, ApkSigningBlock};
-use log::{info, warn};
+use crate::repo::ipa::IpaInfo;
+use crate::repo::magic::check_file_type;
+use crate::repo::range::load_apk_ranges;
+use crate::repo::snap::SnapYaml;
+use crate::repo::urls::UrlsRepo;
+use crate::repo::web::WebManifest;
+use crate::timings::Timings;
+use anyhow::{anyhow, bail, Result};
+use apk_parser::zip::{CompressionMethod, ZipArchive};
+use apk_parser::{
+    certificate_public_key_hash, is_debug_certificate, launcher_activity,
+    parse_android_manifest_extras, resource_id, AndroidManifest, ApkSignatureBlock,
+    ApkSigningBlock, ManifestExtras, ResourceTable,
+};
+use chrono::{DateTime, Utc};
+use glob::Pattern;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
 use nostr_sdk::prelude::{hex, Coordinate, StreamExt};
-use nostr_sdk::{Event, EventBuilder, Kind, NostrSigner, Tag};
-use reqwest::Url;
+use nostr_sdk::{Event, EventBuilder, EventId, Kind, NostrSigner, Tag, Timestamp};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Client, NoProxy, Proxy, RequestBuilder, Response, StatusCode, Url};
 use semver::Version;
-use sha2::{Digest, Sha256};
-use std::collections::HashSet;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env::temp_dir;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::{Read, Seek};
+use std::io::{IsTerminal, Read, Seek};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
 
+mod elf;
+mod flatpak;
 mod github;
+mod ipa;
+mod magic;
+mod range;
+mod snap;
+mod urls;
+mod web;
 
 /// Since artifact binary / image
 #[derive(Debug, Clone)]
@@ -42,6 +72,23 @@ pub struct RepoArtifact {
 
     /// SHA-256 hash of the artifact
     pub hash: Vec<u8>,
+
+    /// Local copy of a downloaded artifact
+    pub download_path: Option<PathBuf>,
+
+    /// How often the artifact was downloaded, when the source reports it
+    pub downloads: Option<u64>,
+}
+
+impl RepoArtifact {
+    /// Delete the downloaded copy of the artifact, if any
+    pub fn remove_download(&self) {
+        if let Some(path) = &self.download_path {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove download {}: {}", path.display(), e);
+            }
+        }
+    }
 }
 
 impl Display for RepoArtifact {
@@ -59,73 +106,356 @@ impl TryInto<EventBuilder> for RepoArtifact {
     type Error = anyhow::Error;
 
     fn try_into(self) -> Result<EventBuilder, Self::Error> {
-        let mut b = EventBuilder::new(Kind::FileMetadata, "").tags([
-            Tag::parse(["f", self.platform.to_string().as_str()])?,
+        Ok(EventBuilder::new(Kind::FileMetadata, "").tags(self.file_tags()?))
+    }
+}
+
+/// NIP-94 tags which are always emitted, they can't be omitted
+pub const REQUIRED_FILE_TAGS: &[&str] = &["f", "m", "size", "x", "url", "a"];
+
+impl RepoArtifact {
+    /// NIP-94 file metadata tags
+    pub fn file_tags(self) -> Result<Vec<Tag>> {
+        let mut tags = match &self.metadata {
+            // one platform per slice of a fat binary
+            ArtifactMetadata::IPA { ipa } if ipa.slices.len() > 1 => ipa
+                .slices
+                .iter()
+                .map(|arch| {
+                    let platform = Platform::IOS { arch: arch.clone() };
+                    Tag::parse(["f", platform.to_string().as_str()])
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![Tag::parse(["f", self.platform.to_string().as_str()])?],
+        };
+        tags.extend([
             Tag::parse(["m", self.content_type.as_str()])?,
             Tag::parse(["size", self.size.to_string().as_str()])?,
             Tag::parse(["x", &hex::encode(self.hash)])?,
         ]);
         if let RepoResource::Remote(u) = self.location {
-            b = b.tag(Tag::parse(["url", u.as_str()])?);
+            tags.push(Tag::parse(["url", u.as_str()])?);
+        }
+        if let Some(downloads) = self.downloads {
+            tags.push(Tag::parse(["downloads", downloads.to_string().as_str()])?);
         }
         match self.metadata {
             ArtifactMetadata::APK {
                 manifest,
                 signature_blocks: signatures,
+                page_size_16kb,
+                ..
             } => {
-                for signature in signatures {
-                    match signature {
-                        ApkSignatureBlock::Unknown { .. } => {
-                            warn!("No signature found in metadata");
-                        }
-                        ApkSignatureBlock::V2 { certificates, .. } => {
-                            for certificate in certificates {
-                                b = b.tag(Tag::parse([
-                                    "apk_signature_hash",
-                                    &hex::encode(Sha256::digest(certificate)),
-                                ])?);
-                            }
-                        }
-                        ApkSignatureBlock::V3 { certificates, .. } => {
-                            for certificate in certificates {
-                                b = b.tag(Tag::parse([
-                                    "apk_signature_hash",
-                                    &hex::encode(Sha256::digest(certificate)),
-                                ])?);
-                            }
-                        }
+                // the same signing key is usually present in both the v2 and v3 blocks
+                let mut signers = vec![];
+                for cert in signatures.iter().flat_map(|s| s.certificates()) {
+                    match certificate_public_key_hash(cert) {
+                        Ok(h) if !signers.contains(&h) => signers.push(h),
+                        Ok(_) => {}
+                        Err(e) => warn!("Failed to parse signing certificate: {}", e),
+                    }
+                }
+                for signer in signers {
+                    tags.push(Tag::parse(["apk_signer", &hex::encode(signer)])?);
+                }
+                for signature in &signatures {
+                    if let ApkSignatureBlock::SourceStamp { certificate } = signature {
+                        tags.push(Tag::parse([
+                            "apk_source_stamp",
+                            &hex::encode(Sha256::digest(certificate)),
+                        ])?);
                     }
                 }
+                let certificates = signer_certificates(&signatures);
+                if certificates.is_empty() {
+                    warn!("No signature found in metadata");
+                }
+                for certificate in certificates {
+                    tags.push(Tag::parse([
+                        "apk_signature_hash",
+                        &hex::encode(Sha256::digest(certificate)),
+                    ])?);
+                }
                 if let Some(vn) = manifest.version_name {
-                    b = b.tag(Tag::parse(["version", vn.as_str()])?);
+                    tags.push(Tag::parse(["version", vn.as_str()])?);
                 }
                 if let Some(vc) = manifest.version_code {
-                    b = b.tag(Tag::parse(["version_code", vc.to_string().as_str()])?);
+                    tags.push(Tag::parse(["version_code", vc.to_string().as_str()])?);
                 }
                 if let Some(min_sdk) = manifest.sdk.min_sdk_version {
-                    b = b.tag(Tag::parse([
+                    tags.push(Tag::parse([
                         "min_sdk_version",
                         min_sdk.to_string().as_str(),
                     ])?);
                 }
                 if let Some(target_sdk) = manifest.sdk.target_sdk_version {
-                    b = b.tag(Tag::parse([
+                    tags.push(Tag::parse([
                         "target_sdk_version",
                         target_sdk.to_string().as_str(),
                     ])?);
                 }
+                for permission in &manifest.uses_permission {
+                    tags.push(Tag::parse(["permission", permission.name.as_str()])?);
+                }
+                for feature in &manifest.uses_feature {
+                    if let Some(name) = &feature.name {
+                        let required = feature.required.unwrap_or(true).to_string();
+                        tags.push(Tag::parse(["feature", name.as_str(), required.as_str()])?);
+                    }
+                }
+                if let Some(supported) = page_size_16kb {
+                    tags.push(Tag::parse(["page_size_16kb", &supported.to_string()])?);
+                }
+            }
+            ArtifactMetadata::Flatpak {
+                app_id,
+                branch,
+                runtime,
+            } => {
+                tags.push(Tag::parse(["flatpak_id", app_id.as_str()])?);
+                tags.push(Tag::parse(["flatpak_branch", branch.as_str()])?);
+                if let Some(runtime) = runtime {
+                    tags.push(Tag::parse(["flatpak_runtime", runtime.as_str()])?);
+                }
+            }
+            ArtifactMetadata::Snap { snap } => {
+                tags.push(Tag::parse(["snap_name", snap.name.as_str()])?);
+                tags.push(Tag::parse(["version", snap.version.as_str()])?);
+                if let Some(base) = &snap.base {
+                    tags.push(Tag::parse(["snap_base", base.as_str()])?);
+                }
+                if let Some(grade) = &snap.grade {
+                    tags.push(Tag::parse(["snap_grade", grade.as_str()])?);
+                }
+                if let Some(confinement) = &snap.confinement {
+                    tags.push(Tag::parse(["snap_confinement", confinement.as_str()])?);
+                }
+            }
+            ArtifactMetadata::IPA { ipa } => {
+                tags.push(Tag::parse(["ios_bundle_id", ipa.info.bundle_id.as_str()])?);
+                if let Some(build) = &ipa.info.build {
+                    tags.push(Tag::parse(["ios_build", build.as_str()])?);
+                }
+            }
+            ArtifactMetadata::Web { manifest } => {
+                if let Some(name) = &manifest.name {
+                    tags.push(Tag::parse(["web_name", name.as_str()])?);
+                }
+                if let Some(short_name) = &manifest.short_name {
+                    tags.push(Tag::parse(["web_short_name", short_name.as_str()])?);
+                }
+                if let Some(start_url) = &manifest.start_url {
+                    tags.push(Tag::parse(["web_start_url", start_url.as_str()])?);
+                }
+                for icon in &manifest.icons {
+                    tags.push(Tag::parse([
+                        "web_icon",
+                        icon.src.as_str(),
+                        icon.sizes.as_deref().unwrap_or(""),
+                    ])?);
+                }
             }
         }
-        Ok(b)
+        Ok(tags)
+    }
+}
+
+/// Signing certificates of the highest signature scheme present, deduplicated
+///
+/// APKs are usually signed with the same key in every scheme, after a key rotation
+/// the v3 block holds the current signer while v2 keeps the original one.
+fn signer_certificates(blocks: &[ApkSignatureBlock]) -> Vec<&[u8]> {
+    let scheme = |b: &ApkSignatureBlock| match b {
+        ApkSignatureBlock::Unknown { .. } | ApkSignatureBlock::SourceStamp { .. } => 0,
+        ApkSignatureBlock::V2 { .. } => 2,
+        ApkSignatureBlock::V3 { .. } => 3,
+    };
+    let Some(highest) = blocks
+        .iter()
+        .filter(|b| !b.certificates().is_empty())
+        .map(scheme)
+        .max()
+    else {
+        return vec![];
+    };
+    let mut ret: Vec<&[u8]> = vec![];
+    for cert in blocks
+        .iter()
+        .filter(|b| scheme(b) == highest)
+        .flat_map(|b| b.certificates())
+    {
+        if !ret.contains(&cert.as_slice()) {
+            ret.push(cert);
+        }
     }
+    ret
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms, clippy::large_enum_variant)]
 pub enum ArtifactMetadata {
     APK {
         manifest: AndroidManifest,
         signature_blocks: Vec<ApkSignatureBlock>,
+        /// Localized application labels as `(locale, label)`
+        labels: Vec<(String, String)>,
+        /// Manifest attributes [AndroidManifest] has no field for
+        extras: ManifestExtras,
+        /// Whether the 64-bit native libs can run on 16KB page size devices, [None] without
+        /// 64-bit libs or when they could not be read
+        page_size_16kb: Option<bool>,
+    },
+    Flatpak {
+        app_id: String,
+        branch: String,
+        /// Runtime ref from the bundle `metadata` file
+        runtime: Option<String>,
+    },
+    Snap {
+        snap: SnapYaml,
+    },
+    IPA {
+        ipa: IpaInfo,
     },
+    Web {
+        manifest: WebManifest,
+    },
+}
+
+impl ArtifactMetadata {
+    /// Localized application names as `(locale, name)`
+    pub fn labels(&self) -> &[(String, String)] {
+        match self {
+            ArtifactMetadata::APK { labels, .. } => labels,
+            _ => &[],
+        }
+    }
+
+    /// Package name from the APK manifest
+    pub fn package(&self) -> Option<&str> {
+        match self {
+            ArtifactMetadata::APK { manifest, .. } => manifest.package.as_deref(),
+            ArtifactMetadata::IPA { ipa } => Some(ipa.info.bundle_id.as_str()),
+            _ => None,
+        }
+    }
+
+    /// App version from the APK manifest, `versionName` if it looks like a version, otherwise
+    /// `versionCode` as the major version
+    pub fn version(&self) -> Option<Version> {
+        match self {
+            ArtifactMetadata::APK { manifest, .. } => manifest
+                .version_name
+                .as_deref()
+                .and_then(parse_lenient_version)
+                .or(manifest.version_code.map(|c| Version::new(c as u64, 0, 0))),
+            ArtifactMetadata::IPA { ipa } => {
+                ipa.info.version.as_deref().and_then(parse_lenient_version)
+            }
+            _ => None,
+        }
+    }
+
+    /// Check if the artifact is signed with the Android SDK debug certificate
+    pub fn is_debug_signed(&self) -> bool {
+        match self {
+            ArtifactMetadata::APK {
+                signature_blocks, ..
+            } => signature_blocks
+                .iter()
+                .flat_map(|b| b.certificates())
+                .any(|c| is_debug_certificate(c)),
+            _ => false,
+        }
+    }
+
+    /// Check if the APK manifest sets `android:debuggable="true"`
+    pub fn is_debuggable(&self) -> bool {
+        match self {
+            ArtifactMetadata::APK { manifest, .. } => manifest.application.debuggable == Some(true),
+            _ => false,
+        }
+    }
+}
+
+/// Serializable view of the fields extracted from an APK manifest
+#[derive(Debug, Serialize)]
+pub struct ManifestInfo {
+    pub package: Option<String>,
+    pub version_name: Option<String>,
+    pub version_code: Option<u32>,
+    pub min_sdk_version: Option<u32>,
+    pub target_sdk_version: Option<u32>,
+    pub max_sdk_version: Option<u32>,
+    pub compile_sdk_version: Option<u32>,
+    pub label: Option<String>,
+    /// Localized labels keyed by locale
+    pub labels: BTreeMap<String, String>,
+    pub debuggable: Option<bool>,
+    pub allow_backup: Option<bool>,
+    pub install_location: Option<String>,
+    pub launcher_activity: Option<String>,
+    pub permissions: Vec<PermissionInfo>,
+    pub features: Vec<FeatureInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PermissionInfo {
+    pub name: String,
+    pub max_sdk_version: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureInfo {
+    pub name: String,
+    pub required: bool,
+}
+
+impl ArtifactMetadata {
+    /// Structured manifest data for JSON output, only APKs have a manifest
+    pub fn manifest_info(&self) -> Option<ManifestInfo> {
+        match self {
+            ArtifactMetadata::APK {
+                manifest,
+                labels,
+                extras,
+                ..
+            } => Some(ManifestInfo {
+                package: manifest.package.clone(),
+                version_name: manifest.version_name.clone(),
+                version_code: manifest.version_code,
+                min_sdk_version: manifest.sdk.min_sdk_version,
+                target_sdk_version: manifest.sdk.target_sdk_version,
+                max_sdk_version: manifest.sdk.max_sdk_version,
+                compile_sdk_version: manifest.compile_sdk_version,
+                label: manifest.application.label.clone(),
+                labels: labels.iter().cloned().collect(),
+                debuggable: manifest.application.debuggable,
+                allow_backup: extras.allow_backup,
+                install_location: extras.install_location.clone(),
+                launcher_activity: launcher_activity(manifest).map(|a| a.to_string()),
+                permissions: manifest
+                    .uses_permission
+                    .iter()
+                    .map(|p| PermissionInfo {
+                        name: p.name.clone(),
+                        max_sdk_version: p.max_sdk_version,
+                    })
+                    .collect(),
+                features: manifest
+                    .uses_feature
+                    .iter()
+                    .filter_map(|f| {
+                        Some(FeatureInfo {
+                            name: f.name.clone()?,
+                            required: f.required.unwrap_or(true),
+                        })
+                    })
+                    .collect(),
+            }),
+            _ => None,
+        }
+    }
 }
 
 impl Display for ArtifactMetadata {
@@ -134,6 +464,7 @@ impl Display for ArtifactMetadata {
             ArtifactMetadata::APK {
                 manifest,
                 signature_blocks: signatures,
+                ..
             } => {
                 write!(
                     f,
@@ -148,11 +479,67 @@ impl Display for ArtifactMetadata {
                         .join(", ")
                 )
             }
+            ArtifactMetadata::Flatpak {
+                app_id,
+                branch,
+                runtime,
+            } => write!(
+                f,
+                "Flatpak id={}, branch={}, runtime={}",
+                app_id,
+                branch,
+                runtime.as_deref().unwrap_or("none")
+            ),
+            ArtifactMetadata::Snap { snap } => write!(
+                f,
+                "Snap name={}, version={}, base={}, confinement={}",
+                snap.name,
+                snap.version,
+                snap.base.as_deref().unwrap_or("none"),
+                snap.confinement.as_deref().unwrap_or("strict")
+            ),
+            ArtifactMetadata::IPA { ipa } => write!(
+                f,
+                "IPA id={}, version={}, build={}, arch={}",
+                ipa.info.bundle_id,
+                ipa.info.version.as_deref().unwrap_or(""),
+                ipa.info.build.as_deref().unwrap_or(""),
+                ipa.slices
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            ArtifactMetadata::Web { manifest } => write!(
+                f,
+                "Web name={}, start_url={}",
+                manifest.name.as_deref().unwrap_or("missing"),
+                manifest.start_url.as_deref().unwrap_or("none")
+            ),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Platform an artifact runs on
+///
+/// The [Display] impl produces the `f` (platform filter) tag value, `<os>-<arch>`:
+///
+/// | Platform | OS        | Architectures                                          |
+/// |----------|-----------|--------------------------------------------------------|
+/// | Android  | `android` | `armeabi-v7a`, `arm64-v8a`, `x86`, `x86_64`, `universal` |
+/// | iOS      | `ios`     | `armv7`, `arm64`, `arm64e`, `universal`                |
+/// | macOS    | `darwin`  | `aarch64`, `x86`, `x86_64`, `universal`                |
+/// | Windows  | `windows` | `armv7`, `aarch64`, `x86`, `x86_64`                    |
+/// | Linux    | `linux`   | `armv7`, `aarch64`, `x86`, `x86_64`                    |
+/// | Web      | `web`     | (no architecture)                                      |
+///
+/// Android uses the ABI names from the APK `lib/` folders, the other platforms use the
+/// `uname -m` style names. Unsupported combinations render as `<os>-unknown`.
+///
+/// A fat iOS executable is published with one `ios-<arch>` tag per slice, the platform itself
+/// is `ios-universal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms, dead_code)]
 pub enum Platform {
     Android { arch: Architecture },
     IOS { arch: Architecture },
@@ -162,77 +549,125 @@ pub enum Platform {
     Web,
 }
 
+impl Platform {
+    /// Every platform/architecture combination
+    pub fn all() -> Vec<Platform> {
+        let mut ret = vec![];
+        for arch in Architecture::ALL {
+            ret.push(Platform::Android { arch: arch.clone() });
+            ret.push(Platform::IOS { arch: arch.clone() });
+            ret.push(Platform::MacOS { arch: arch.clone() });
+            ret.push(Platform::Windows { arch: arch.clone() });
+            ret.push(Platform::Linux { arch: arch.clone() });
+        }
+        ret.push(Platform::Web);
+        ret
+    }
+
+    /// OS part of the platform tag
+    pub fn os(&self) -> &'static str {
+        match self {
+            Platform::Android { .. } => "android",
+            Platform::IOS { .. } => "ios",
+            Platform::MacOS { .. } => "darwin",
+            Platform::Windows { .. } => "windows",
+            Platform::Linux { .. } => "linux",
+            Platform::Web => "web",
+        }
+    }
+
+    /// Architecture part of the platform tag, [None] if the combination is not supported
+    pub fn arch_name(&self) -> Option<&'static str> {
+        match self {
+            Platform::Android { arch } => Some(match arch {
+                Architecture::ARMv7 => "armeabi-v7a",
+                Architecture::ARM64 => "arm64-v8a",
+                Architecture::X86 => "x86",
+                Architecture::X86_64 => "x86_64",
+                Architecture::Universal => "universal",
+                Architecture::ARM64e => return None,
+            }),
+            Platform::IOS { arch } => match arch {
+                Architecture::ARMv7 => Some("armv7"),
+                Architecture::ARM64 => Some("arm64"),
+                Architecture::ARM64e => Some("arm64e"),
+                Architecture::Universal => Some("universal"),
+                // simulator builds can't be installed on devices
+                Architecture::X86 | Architecture::X86_64 => None,
+            },
+            Platform::MacOS { arch } => match arch {
+                Architecture::ARM64 => Some("aarch64"),
+                Architecture::X86 => Some("x86"),
+                Architecture::X86_64 => Some("x86_64"),
+                // fat binary containing multiple architectures
+                Architecture::Universal => Some("universal"),
+                Architecture::ARMv7 | Architecture::ARM64e => None,
+            },
+            Platform::Windows { arch } | Platform::Linux { arch } => match arch {
+                Architecture::ARMv7 => Some("armv7"),
+                Architecture::ARM64 => Some("aarch64"),
+                Architecture::X86 => Some("x86"),
+                Architecture::X86_64 => Some("x86_64"),
+                Architecture::Universal | Architecture::ARM64e => None,
+            },
+            Platform::Web => None,
+        }
+    }
+
+    /// Check if this platform/architecture combination has a defined tag value
+    pub fn is_supported(&self) -> bool {
+        matches!(self, Platform::Web) || self.arch_name().is_some()
+    }
+}
+
 impl Display for Platform {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Platform::Android { arch } => write!(
-                f,
-                "android-{}",
-                match arch {
-                    Architecture::ARMv7 => "armeabi-v7a",
-                    Architecture::ARM64 => "arm64-v8a",
-                    Architecture::X86 => "x86",
-                    Architecture::X86_64 => "x86_64",
-                    Architecture::Universal => "universal",
-                }
-            ),
-            Platform::IOS { arch } => write!(
-                f,
-                "ios-{}",
-                match arch {
-                    Architecture::ARM64 => "arm64",
-                    _ => "unknown",
-                }
-            ),
-            Platform::MacOS { arch } => write!(
-                f,
-                "darwin-{}",
-                match arch {
-                    Architecture::ARM64 => "aarch64",
-                    Architecture::X86 => "x86",
-                    Architecture::X86_64 => "x86_64",
-                    _ => "unknown",
-                }
-            ),
-            Platform::Windows { arch } => write!(
-                f,
-                "windows-{}",
-                match arch {
-                    Architecture::ARM64 => "aarch64",
-                    Architecture::X86 => "x86",
-                    Architecture::X86_64 => "x86_64",
-                    _ => "unknown",
-                }
-            ),
-            Platform::Linux { arch } => write!(
-                f,
-                "linux-{}",
-                match arch {
-                    Architecture::ARM64 => "aarch64",
-                    Architecture::X86 => "x86",
-                    Architecture::X86_64 => "x86_64",
-                    _ => "unknown",
-                }
-            ),
             Platform::Web => write!(f, "web"),
+            p => write!(f, "{}-{}", p.os(), p.arch_name().unwrap_or("unknown")),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::all()
+            .into_iter()
+            .find(|p| p.is_supported() && p.to_string() == s)
+            .ok_or(anyhow!("unknown platform: {s}"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Architecture {
     Universal,
     ARMv7,
     ARM64,
+    /// arm64 with pointer authentication, iOS only
+    ARM64e,
     X86,
     X86_64,
 }
 
+impl Architecture {
+    pub const ALL: [Architecture; 6] = [
+        Architecture::Universal,
+        Architecture::ARMv7,
+        Architecture::ARM64,
+        Architecture::ARM64e,
+        Architecture::X86,
+        Architecture::X86_64,
+    ];
+}
+
 impl Display for Architecture {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Architecture::ARMv7 => write!(f, "armeabi-v7a"),
             Architecture::ARM64 => write!(f, "arm64-v8a"),
+            Architecture::ARM64e => write!(f, "arm64e"),
             Architecture::X86 => write!(f, "x86"),
             Architecture::X86_64 => write!(f, "x86_64"),
             Architecture::Universal => write!(f, "universal"),
@@ -241,12 +676,54 @@ impl Display for Architecture {
 }
 
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 /// A local/remote location where the artifact is located
 pub enum RepoResource {
     Remote(String),
     Local(PathBuf),
 }
 
+/// Options for building the release events
+#[derive(Debug, Clone, Default)]
+pub struct EventOptions {
+    /// Date the events with this instead of now
+    pub created_at: Option<Timestamp>,
+
+    /// Optional NIP-94 tags to leave out of the file events
+    pub omit_tags: Vec<String>,
+
+    /// App icon, published as the `image` and `thumb` of the file events
+    pub icon: Option<PlatformValues<String>>,
+
+    /// Minimum OS version keyed by platform tag or OS, published as `min_os_version`
+    pub min_os: HashMap<String, String>,
+
+    /// Publish the release notes as markdown or plain text
+    pub notes_format: NotesFormat,
+
+    /// Extra tags appended to the release event
+    pub release_tags: Vec<Tag>,
+
+    /// Published file metadata events keyed by hex SHA-256, artifacts with a matching hash
+    /// reference them instead of publishing a new one
+    pub existing_files: HashMap<String, EventId>,
+}
+
+impl EventOptions {
+    /// Options from the manifest config
+    pub fn from_manifest(manifest: &Manifest) -> Self {
+        EventOptions {
+            created_at: None,
+            omit_tags: manifest.omit_tags.clone(),
+            icon: manifest.icon.clone(),
+            min_os: manifest.min_os.clone(),
+            notes_format: manifest.release_notes_format,
+            release_tags: parse_extra_tags(&manifest.release_extra_tags),
+            existing_files: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 /// A single release with one or more artifacts
 pub struct RepoRelease {
@@ -261,6 +738,12 @@ pub struct RepoRelease {
 
     /// List of artifacts in this release
     pub artifacts: Vec<RepoArtifact>,
+
+    /// App id from the manifest, used when no artifact provides a package name
+    pub manifest_id: Option<String>,
+
+    /// When the release was published
+    pub published_at: Option<DateTime<Utc>>,
 }
 
 impl RepoRelease {
@@ -273,9 +756,74 @@ impl RepoRelease {
                 }
                 _ => None,
             })
+            .or_else(|| self.manifest_id.clone())
             .ok_or(anyhow!("no app_id found"))
     }
 
+    /// Keep only artifacts for the given platforms, either full `f` tag values
+    /// (`android-arm64-v8a`) or just the OS (`android`)
+    pub fn retain_platforms(&mut self, platforms: &[String]) {
+        self.artifacts.retain(|a| {
+            let keep = platforms
+                .iter()
+                .any(|p| *p == a.platform.to_string() || p == a.platform.os());
+            if !keep {
+                warn!(
+                    "Skipping {}, platform {} is not in the manifest platforms",
+                    a.name, a.platform
+                );
+            }
+            keep
+        });
+    }
+
+    /// Indices of artifacts with a package name that is not one of `ids`
+    pub fn package_mismatches(&self, ids: &[&str]) -> Vec<usize> {
+        self.artifacts
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| a.metadata.package().is_some_and(|p| !ids.contains(&p)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Version of the first artifact which has one
+    pub fn artifact_version(artifacts: &[RepoArtifact]) -> Option<Version> {
+        artifacts.iter().find_map(|a| a.metadata.version())
+    }
+
+    /// SHA-256 of the sorted artifact SHA-256s, changes if and only if any artifact changes
+    pub fn release_hash(&self) -> String {
+        let mut hashes: Vec<&[u8]> = self.artifacts.iter().map(|a| a.hash.as_slice()).collect();
+        hashes.sort();
+        hex::encode(Sha256::digest(hashes.concat()))
+    }
+
+    /// Remove downloaded copies of the release artifacts
+    pub fn remove_downloads(&self) {
+        for a in &self.artifacts {
+            a.remove_download();
+        }
+    }
+
+    /// Keep only the artifacts at the given indices, returning the others
+    pub fn select_artifacts(&mut self, indices: &[usize]) -> Vec<RepoArtifact> {
+        let (keep, drop) = std::mem::take(&mut self.artifacts)
+            .into_iter()
+            .enumerate()
+            .partition::<Vec<_>, _>(|(i, _)| indices.contains(i));
+        self.artifacts = keep.into_iter().map(|(_, a)| a).collect();
+        drop.into_iter().map(|(_, a)| a).collect()
+    }
+
+    /// Publish time of the release, now if the repo does not provide it
+    pub fn published_timestamp(&self) -> Timestamp {
+        match self.published_at {
+            Some(t) => Timestamp::from_secs(t.timestamp().max(0) as u64),
+            None => Timestamp::now(),
+        }
+    }
+
     /// [app_id]@[version]
     pub fn release_tag(&self) -> Result<String> {
         Ok(format!("{}@{}", self.app_id()?, self.version))
@@ -286,31 +834,67 @@ impl RepoRelease {
         self,
         signer: &T,
         app_coord: Coordinate,
+        opts: &EventOptions,
     ) -> Result<Vec<Event>> {
         let mut ret = vec![];
-        let mut b = EventBuilder::new(
-            Kind::Custom(30063),
-            self.description.as_deref().unwrap_or(""),
-        )
-        .tags([
-            Tag::coordinate(app_coord),
+        let published_at = self.published_timestamp().to_string();
+        // GitHub release bodies often use \r\n line endings
+        let notes = normalize_notes(self.description.as_deref().unwrap_or(""));
+        let content = match opts.notes_format {
+            NotesFormat::Markdown => notes.clone(),
+            NotesFormat::Plain => markdown_to_plain(&notes),
+        };
+        let mut b = EventBuilder::new(Kind::Custom(30063), &content).tags([
+            Tag::coordinate(app_coord.clone()),
             Tag::parse(["d", &self.release_tag()?])?,
+            Tag::parse(["published_at", &published_at])?,
         ]);
+        if content != notes {
+            b = b.tag(Tag::parse(["markdown", &notes])?);
+        }
 
-        if let Some(url) = self.url {
-            b = b.tag(Tag::parse(["url", &url])?);
+        if let Some(url) = &self.url {
+            b = b.tag(Tag::parse(["url", url])?);
         }
+        b = b.tag(Tag::parse(["release_hash", &self.release_hash()])?);
         for a in &self.artifacts {
-            let eb: Result<EventBuilder> = a.clone().try_into();
-            match eb {
-                Ok(a) => {
-                    let e_build = a.sign(signer).await?;
+            if let Some(id) = opts.existing_files.get(&hex::encode(&a.hash)) {
+                info!("Reusing published file event {} for {}", id, a.name);
+                b = b.tag(Tag::event(*id));
+                continue;
+            }
+            match a.clone().file_tags() {
+                Ok(mut tags) => {
+                    tags.push(Tag::parse(["published_at", &published_at])?);
+                    if let Some(icon) = opts.icon.as_ref().and_then(|i| i.get(&a.platform)) {
+                        tags.push(Tag::parse(["image", icon])?);
+                        tags.push(Tag::parse(["thumb", icon])?);
+                    }
+                    if let Some(min_os) = opts
+                        .min_os
+                        .get(&a.platform.to_string())
+                        .or_else(|| opts.min_os.get(a.platform.os()))
+                    {
+                        tags.push(Tag::parse(["min_os_version", min_os])?);
+                    }
+                    tags.retain(|t| !opts.omit_tags.contains(&t.kind().to_string()));
+                    // link the file back to the app so it can be resolved on its own
+                    tags.push(Tag::coordinate(app_coord.clone()));
+                    let mut eb = EventBuilder::new(Kind::FileMetadata, "").tags(tags);
+                    if let Some(t) = opts.created_at {
+                        eb = eb.custom_created_at(t);
+                    }
+                    let e_build = eb.sign(signer).await?;
                     b = b.tag(Tag::event(e_build.id));
                     ret.push(e_build);
                 }
                 Err(e) => warn!("Failed to convert artifact: {} {}", a, e),
             }
         }
+        b = b.tags(opts.release_tags.clone());
+        if let Some(t) = opts.created_at {
+            b = b.custom_created_at(t);
+        }
         ret.push(b.sign(signer).await?);
         Ok(ret)
     }
@@ -323,71 +907,700 @@ pub trait Repo {
     async fn get_releases(&self) -> Result<Vec<RepoRelease>>;
 }
 
-impl TryInto<Box<dyn Repo>> for &Manifest {
-    type Error = anyhow::Error;
+/// Options used when loading releases and their artifacts
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Verify APK signature digests against the file contents
+    pub verify_signatures: bool,
+
+    /// Filter applied to release asset filenames before downloading
+    pub filter: AssetFilter,
+
+    /// Stop loading assets of a release once this many artifacts were loaded
+    pub max_artifacts: Option<usize>,
+
+    /// Where the release version comes from
+    pub version_source: VersionSource,
+
+    /// Directory to download artifacts into, defaults to the system temp dir
+    pub cache_dir: Option<PathBuf>,
+
+    /// Only consider releases with tags starting with this prefix (monorepos)
+    pub tag_prefix: Option<String>,
+
+    /// Require release assets to have a valid detached signature from this key
+    pub verify_with: Option<VerifyWith>,
+
+    /// GitHub API base URL, derived from the repository host when not set
+    pub github_api: Option<String>,
+
+    /// HTTP client shared by all requests, so connections are pooled
+    pub client: Client,
+
+    /// MIME types by file extension, replacing the built-in ones
+    pub content_types: HashMap<String, String>,
+
+    /// Progress bars of the downloads and the overall progress
+    pub progress: MultiProgress,
+
+    /// Time spent downloading and parsing each artifact
+    pub timings: Timings,
+
+    /// Read APKs with HTTP range requests instead of downloading them
+    pub range_requests: bool,
 
-    fn try_into(self) -> std::result::Result<Box<dyn Repo>, Self::Error> {
-        let repo = self
-            .repository
-            .as_ref()
-            .ok_or(anyhow!("repository not found"))?;
+    /// Load every release instead of only the latest one
+    pub all_releases: bool,
+}
+
+/// Include/exclude glob filter for release asset filenames
+#[derive(Debug, Clone, Default)]
+pub struct AssetFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    /// Ad-hoc pattern from the command line, assets must match it as well
+    glob: Option<Pattern>,
+}
+
+impl AssetFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(Self {
+            include: include
+                .iter()
+                .map(|p| Pattern::new(p))
+                .collect::<Result<_, _>>()?,
+            exclude: exclude
+                .iter()
+                .map(|p| Pattern::new(p))
+                .collect::<Result<_, _>>()?,
+            glob: None,
+        })
+    }
+
+    /// Also require assets to match `glob`, on top of the include/exclude lists
+    pub fn with_glob(mut self, glob: Option<&str>) -> Result<Self> {
+        self.glob = glob.map(Pattern::new).transpose()?;
+        Ok(self)
+    }
+
+    /// Check if an asset with this filename should be loaded
+    pub fn matches(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(name)))
+            && !self.exclude.iter().any(|p| p.matches(name))
+            && self.glob.as_ref().is_none_or(|g| g.matches(name))
+    }
+}
+
+/// Create a [Repo] for the repository configured in the manifest
+pub fn repo_from_manifest(manifest: &Manifest, opts: LoadOptions) -> Result<Box<dyn Repo>> {
+    if !manifest.artifacts.is_empty() {
+        return Ok(Box::new(UrlsRepo::new(
+            manifest.artifacts.clone(),
+            manifest.version.as_deref(),
+            &manifest.id,
+            opts,
+        )?));
+    }
+    let repo = manifest
+        .repository
+        .as_ref()
+        .ok_or(anyhow!("repository not found"))?;
+
+    let url: Url = repo.parse()?;
+    let host = url.host_str().unwrap_or_default();
+    // GitHub Enterprise hosts are recognized by name or an explicit API URL
+    if host != "github.com" && !host.starts_with("github.") && opts.github_api.is_none() {
+        bail!("Only github repos are supported");
+    }
+
+    Ok(Box::new(GithubRepo::from_url(repo, &manifest.id, opts)?))
+}
+
+/// Check if a release asset is a signature/checksum sidecar rather than an artifact
+pub fn is_sidecar(name: &str) -> bool {
+    const SIDECAR_EXTENSIONS: &[&str] = &["asc", "sig", "minisig", "sha256", "sha512", "pem"];
+
+    let name = name.to_lowercase();
+    let ext = name.rsplit_once('.').map(|(_, e)| e).unwrap_or("");
+    SIDECAR_EXTENSIONS.contains(&ext)
+        || (name.starts_with("sha") && name.contains("sums"))
+        || name.contains("checksums")
+}
+
+/// Published checksum of a release asset
+#[derive(Debug, Clone)]
+pub enum Checksum {
+    Sha256(Vec<u8>),
+    Sha512(Vec<u8>),
+}
 
-        if !repo.starts_with("https://github.com/") {
-            bail!("Only github repos are supported");
+impl Checksum {
+    /// Parse a `sha256sum`/`sha512sum` style checksum file
+    pub fn parse(name: &str, data: &str) -> Result<Self> {
+        let hash = hex::decode(
+            data.split_whitespace()
+                .next()
+                .ok_or(anyhow!("empty checksum file"))?,
+        )?;
+        match (name.rsplit_once('.').map(|(_, e)| e), hash.len()) {
+            (Some("sha256"), 32) => Ok(Checksum::Sha256(hash)),
+            (Some("sha512"), 64) => Ok(Checksum::Sha512(hash)),
+            _ => bail!("unsupported checksum file: {name}"),
         }
+    }
+
+    /// Check that a file matches this checksum
+    pub fn verify(&self, path: &Path) -> Result<bool> {
+        Ok(match self {
+            Checksum::Sha256(h) => hash_file::<Sha256>(path)? == *h,
+            Checksum::Sha512(h) => hash_file::<Sha512>(path)? == *h,
+        })
+    }
+}
+
+/// Timeout for establishing HTTP connections
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Build the HTTP client shared by all requests of a run
+///
+/// Proxies are taken from `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY` unless `proxy` overrides them,
+/// `NO_PROXY` still applies to the override.
+pub fn http_client(read_timeout: Duration, proxy: Option<&str>) -> Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent("nap/1.0 (https://github.com/v0l/nap)")
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(read_timeout);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(Proxy::all(proxy)?.no_proxy(NoProxy::from_env()));
+    }
+    Ok(builder.build()?)
+}
+
+/// Download a checksum sidecar file with a prepared request, `url` is its public location which
+/// names the hash
+async fn load_checksum_request(req: RequestBuilder, url: &str) -> Result<Checksum> {
+    let data = req.send().await?.error_for_status()?.text().await?;
+    Checksum::parse(Url::parse(url)?.path(), &data)
+}
 
-        Ok(Box::new(GithubRepo::from_url(repo)?))
+/// Download a detached signature sidecar file with a prepared request
+async fn load_signature_request(req: RequestBuilder) -> Result<Vec<u8>> {
+    Ok(req
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec())
+}
+
+/// Verify a downloaded artifact against its detached signature
+fn verify_detached(key: &VerifyWith, path: &Path, signature: &[u8]) -> Result<()> {
+    match key {
+        VerifyWith::Minisign(key) => {
+            let key = minisign_verify::PublicKey::from_base64(key)
+                .or_else(|_| minisign_verify::PublicKey::decode(key))?;
+            let signature = minisign_verify::Signature::decode(std::str::from_utf8(signature)?)?;
+            key.verify(&std::fs::read(path)?, &signature, true)?;
+        }
+        VerifyWith::Gpg(keyring) => {
+            // gpgv looks up relative keyring paths in the gpg home dir
+            let keyring = std::fs::canonicalize(keyring)
+                .map_err(|e| anyhow!("Failed to open keyring {}: {}", keyring.display(), e))?;
+            let mut sig_path = path.as_os_str().to_owned();
+            sig_path.push(".sig");
+            std::fs::write(&sig_path, signature)?;
+            let output = std::process::Command::new("gpgv")
+                .arg("--keyring")
+                .arg(&keyring)
+                .arg(&sig_path)
+                .arg(path)
+                .output();
+            std::fs::remove_file(&sig_path)?;
+            let output = output.map_err(|e| anyhow!("Failed to run gpgv: {}", e))?;
+            if !output.status.success() {
+                bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+            }
+        }
     }
+    Ok(())
 }
 
 /// Download an artifact and create a [RepoArtifact]
-async fn load_artifact_url(url: &str) -> Result<RepoArtifact> {
+async fn load_artifact_url(
+    url: &str,
+    opts: &LoadOptions,
+    checksum: Option<&Checksum>,
+) -> Result<RepoArtifact> {
+    load_artifact_request(opts.client.get(url), url, opts, checksum).await
+}
+
+/// Download an artifact with a prepared request, `url` is the public location used for publishing
+async fn load_artifact_request(
+    req: RequestBuilder,
+    url: &str,
+    opts: &LoadOptions,
+    checksum: Option<&Checksum>,
+) -> Result<RepoArtifact> {
     info!("Downloading artifact {}", url);
     let u = Url::parse(url)?;
-    let rsp = reqwest::get(u.clone()).await?;
     let id = hex::encode(Sha256::digest(url.as_bytes()));
-    let mut tmp = temp_dir().join(id);
-    tmp.set_extension(
-        PathBuf::from(u.path())
-            .extension()
-            .ok_or(anyhow!("Missing extension in URL"))?
-            .to_str()
-            .unwrap(),
-    );
-    if !tmp.exists() {
-        let mut tmp_file = tokio::fs::File::create(&tmp).await?;
+    let cache_dir = opts.cache_dir.clone().unwrap_or(temp_dir());
+    tokio::fs::create_dir_all(&cache_dir).await?;
+    // keep `tar.gz` whole, the cached file is loaded by its extension
+    let ext = u
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .and_then(artifact_extension)
+        .ok_or(anyhow!("Missing extension in URL"))?;
+    if ext == "apk" && opts.range_requests {
+        if opts.verify_signatures || opts.verify_with.is_some() {
+            info!("Verifying {} needs the whole file, downloading it", url);
+        } else {
+            match load_apk_ranges(
+                req.try_clone().ok_or(anyhow!("request can't be cloned"))?,
+                url,
+                checksum,
+            )
+            .await
+            {
+                Ok(mut a) => {
+                    apply_content_type(&mut a, &ext, opts);
+                    return Ok(a);
+                }
+                Err(e) => warn!(
+                    "Failed to read {} with range requests, downloading it: {}",
+                    url, e
+                ),
+            }
+        }
+    }
+    let tmp = cache_dir.join(format!("{id}.{ext}"));
+    let name = u
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .unwrap_or(url);
+    // incomplete downloads are kept here and resumed on the next run
+    let part = cache_dir.join(format!("{id}.{ext}.part"));
+
+    // another run downloading the same URL holds the lock, leave its partial download alone
+    let mut part_file = if part.exists() {
+        lock_partial_download(&part)?
+    } else {
+        None
+    };
+    let partial_len = match &part_file {
+        Some(f) => f.metadata()?.len(),
+        None => 0,
+    };
+    let mut resumed = None;
+    if let (1.., Some(range_req)) = (partial_len, req.try_clone()) {
+        let rsp = range_req
+            .header(RANGE, format!("bytes={partial_len}-"))
+            .send()
+            .await?;
+        if rsp.status() == StatusCode::PARTIAL_CONTENT
+            && content_range_start(&rsp) == Some(partial_len)
+        {
+            info!("Resuming download of {} at {} bytes", url, partial_len);
+            resumed = Some(rsp);
+        } else {
+            info!(
+                "Server does not support resuming {}, downloading again",
+                url
+            );
+        }
+    }
+    let (rsp, offset) = match resumed {
+        Some(rsp) => (rsp, partial_len),
+        None => (req.send().await?.error_for_status()?, 0),
+    };
+
+    let expected_len = rsp.content_length().map(|len| len + offset);
+    let cached = offset == 0
+        && match (tmp.metadata(), expected_len) {
+            (Ok(m), Some(len)) => m.len() == len,
+            (Ok(_), None) => true,
+            (Err(_), _) => false,
+        };
+    if !cached {
+        let pb = opts.progress.add(download_progress(expected_len, url));
+        pb.set_position(offset);
+        if part_file.is_none() {
+            part_file = lock_partial_download(&part)?;
+        }
+        let (file, download) = match part_file {
+            Some(f) => {
+                if offset == 0 {
+                    f.set_len(0)?;
+                }
+                (f, part.clone())
+            }
+            None => {
+                // not resumable, the shared partial download belongs to the other run
+                let unique = cache_dir.join(format!("{id}.{ext}.{}.tmp", std::process::id()));
+                warn!(
+                    "{} is being downloaded by another process, downloading a separate copy",
+                    url
+                );
+                (File::create(&unique)?, unique)
+            }
+        };
+        let mut part_file = tokio::fs::File::from_std(file);
+        let start = Instant::now();
+        let mut written = offset;
         let mut rsp_stream = rsp.bytes_stream();
         while let Some(data) = rsp_stream.next().await {
-            if let Ok(data) = data {
-                tmp_file.write_all(&data).await?;
+            let data = data?;
+            part_file.write_all(&data).await?;
+            written += data.len() as u64;
+            pb.inc(data.len() as u64);
+        }
+        part_file.flush().await?;
+        pb.finish_and_clear();
+        opts.timings
+            .record(format!("download {}", name), start.elapsed());
+        if let Some(len) = expected_len {
+            if written != len {
+                if written > len || download != part {
+                    tokio::fs::remove_file(&download).await?;
+                }
+                bail!(
+                    "Download truncated, got {} of {} bytes, run again to resume",
+                    written,
+                    len
+                );
             }
         }
+        // readers never see a partially written cache file, the lock is held until the partial
+        // download is moved so another run can't truncate it in between
+        tokio::fs::rename(&download, &tmp).await?;
+        drop(part_file);
+    }
+    if let Some(checksum) = checksum {
+        if checksum.verify(&tmp)? {
+            info!("Checksum verified for {}", url);
+        } else {
+            warn!("Checksum mismatch for {}, download may be corrupt!", url);
+        }
     }
-    let mut a = load_artifact(&tmp)?;
+    let start = Instant::now();
+    let loaded = load_artifact(&tmp, opts);
+    opts.timings
+        .record(format!("parse {}", name), start.elapsed());
+    let mut a = loaded.map_err(|e| anyhow!("Failed to load {}: {}", url, e))?;
     // replace location back to URL for publishing
     a.location = RepoResource::Remote(url.to_string());
+    a.download_path = Some(tmp);
     Ok(a)
 }
 
-fn load_artifact(path: &Path) -> Result<RepoArtifact> {
-    match path
-        .extension()
-        .ok_or(anyhow!("missing file extension"))?
-        .to_str()
-        .unwrap()
-    {
-        "apk" => load_apk_artifact(path),
-        v => bail!("unknown file extension: {v}"),
+/// Open the partial download of a URL for appending and take an exclusive lock on it, [None]
+/// if another process holds the lock
+///
+/// The lock is released when the file is closed or the process exits, so a crashed run never
+/// blocks resuming the download.
+fn lock_partial_download(part: &Path) -> Result<Option<File>> {
+    loop {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(part)?;
+        match file.try_lock() {
+            Ok(()) if is_same_file(&file, part)? => return Ok(Some(file)),
+            // the run holding the lock moved the finished download to the cache meanwhile
+            Ok(()) => continue,
+            Err(std::fs::TryLockError::WouldBlock) => return Ok(None),
+            Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+        }
     }
 }
 
-fn load_apk_artifact(path: &Path) -> Result<RepoArtifact> {
-    let file = File::open(path)?;
-    let mut file = std::io::BufReader::new(file);
-    let sig_block = ApkSigningBlock::from_reader(&mut file)?;
-
-    let mut zip = ZipArchive::new(file)?;
-    let manifest = load_manifest(&mut zip)?;
+/// Check `path` still names the open file, it may have been renamed since it was opened
+fn is_same_file(file: &File, path: &Path) -> Result<bool> {
+    let current = match std::fs::metadata(path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let open = file.metadata()?;
+        Ok(open.dev() == current.dev() && open.ino() == current.ino())
+    }
+    // open files can't be renamed on Windows
+    #[cfg(not(unix))]
+    {
+        let _ = (file, current);
+        Ok(true)
+    }
+}
+
+/// Start offset of a `Content-Range: bytes <start>-<end>/<total>` response header
+fn content_range_start(rsp: &Response) -> Option<u64> {
+    parse_content_range_start(rsp.headers().get(CONTENT_RANGE)?.to_str().ok()?)
+}
+
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    parse_content_range(value).map(|(start, _)| start)
+}
+
+/// Start offset and total length (if known) of a `Content-Range` header value
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.trim().parse().ok()?, total.trim().parse().ok()))
+}
+
+/// Overall progress of loading releases, shown above the download bars as
+/// `Release 2 of 10 (v1.2.0) [===>   ] 5/40 artifacts`
+pub struct TotalProgress {
+    bar: ProgressBar,
+    /// Number of releases to load
+    releases: usize,
+    /// Number of the current release
+    release: usize,
+    /// Position at the end of the current release
+    release_end: u64,
+}
+
+impl TotalProgress {
+    /// Progress of loading `releases` releases with `artifacts` assets to download in total
+    pub fn new(multi: &MultiProgress, releases: usize, artifacts: u64) -> Self {
+        let bar = if std::io::stdout().is_terminal() {
+            multi.add(
+                ProgressBar::new(0).with_style(
+                    ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len} artifacts ({eta})")
+                        .unwrap()
+                        .progress_chars("=> "),
+                ),
+            )
+        } else {
+            ProgressBar::hidden()
+        };
+        bar.set_length(artifacts);
+        Self {
+            bar,
+            releases,
+            release: 0,
+            release_end: 0,
+        }
+    }
+
+    /// Start loading a release with `assets` assets to download
+    pub fn start_release(&mut self, tag: &str, assets: u64) {
+        // assets which were skipped still count as done
+        self.bar.set_position(self.release_end);
+        self.release += 1;
+        self.release_end += assets;
+        // a release without artifacts was skipped for the next one
+        self.releases = self.releases.max(self.release);
+        if self.bar.length() < Some(self.release_end) {
+            self.bar.set_length(self.release_end);
+        }
+        self.bar.set_message(format!(
+            "Release {} of {} ({})",
+            self.release, self.releases, tag
+        ));
+    }
+
+    /// An asset of the current release was loaded or failed to load
+    pub fn inc(&self) {
+        if self.bar.position() < self.release_end {
+            self.bar.inc(1);
+        }
+    }
+}
+
+impl Drop for TotalProgress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Create a progress bar for a download, or a spinner if the length is unknown
+fn download_progress(len: Option<u64>, url: &str) -> ProgressBar {
+    if !std::io::stdout().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let name = url.rsplit('/').next().unwrap_or(url).to_string();
+    match len {
+        Some(len) => ProgressBar::new(len)
+            .with_style(
+                ProgressStyle::with_template(
+                    "{msg} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+                )
+                .unwrap()
+                .progress_chars("=> "),
+            )
+            .with_message(name),
+        None => ProgressBar::new_spinner()
+            .with_style(
+                ProgressStyle::with_template("{spinner} {msg} {bytes} ({bytes_per_sec})").unwrap(),
+            )
+            .with_message(name),
+    }
+}
+
+/// An artifact format which can be published, by file extension
+pub struct ArtifactFormat {
+    pub extensions: &'static [&'static str],
+    pub description: &'static str,
+    load: fn(&Path, &LoadOptions) -> Result<RepoArtifact>,
+}
+
+/// Every format [load_artifact] can read, also listed by `nap formats`
+pub const ARTIFACT_FORMATS: &[ArtifactFormat] = &[
+    ArtifactFormat {
+        extensions: &["apk"],
+        description: "Android app",
+        load: load_apk_artifact,
+    },
+    ArtifactFormat {
+        extensions: &["flatpak"],
+        description: "Flatpak single-file bundle",
+        load: |path, _| load_flatpak_artifact(path),
+    },
+    ArtifactFormat {
+        extensions: &["snap"],
+        description: "Snap package",
+        load: |path, _| load_snap_artifact(path),
+    },
+    ArtifactFormat {
+        extensions: &["ipa"],
+        description: "iOS app archive",
+        load: |path, _| load_ipa_artifact(path),
+    },
+    ArtifactFormat {
+        extensions: &["zip"],
+        description: "Web app bundle with a web manifest",
+        load: |path, _| load_web_artifact(path, WebManifest::from_zip(path)?, "application/zip"),
+    },
+    ArtifactFormat {
+        extensions: &["tar.gz", "tgz"],
+        description: "Web app tarball with a web manifest",
+        load: |path, _| {
+            load_web_artifact(path, WebManifest::from_tar_gz(path)?, "application/gzip")
+        },
+    },
+];
+
+impl ArtifactFormat {
+    /// Format of a file by its name
+    pub fn from_path(path: &Path) -> Option<&'static ArtifactFormat> {
+        let ext = artifact_extension(path.file_name()?.to_str()?)?;
+        ARTIFACT_FORMATS
+            .iter()
+            .find(|f| f.extensions.contains(&ext.as_str()))
+    }
+}
+
+/// Lowercase extension of an artifact file name, compound extensions of the known formats
+/// like `tar.gz` are kept whole
+pub fn artifact_extension(name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    ARTIFACT_FORMATS
+        .iter()
+        .flat_map(|f| f.extensions.iter())
+        .filter(|e| e.contains('.') && name.ends_with(&format!(".{}", e)))
+        .map(|e| e.to_string())
+        .next()
+        .or_else(|| Some(Path::new(&name).extension()?.to_str()?.to_string()))
+}
+
+fn load_artifact(path: &Path, opts: &LoadOptions) -> Result<RepoArtifact> {
+    let ext = path
+        .file_name()
+        .and_then(|n| artifact_extension(n.to_str()?))
+        .ok_or(anyhow!("missing file extension"))?;
+    let Some(format) = ArtifactFormat::from_path(path) else {
+        bail!(
+            "unknown file extension: {ext}, supported: {}",
+            ARTIFACT_FORMATS
+                .iter()
+                .flat_map(|f| f.extensions.iter().copied())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    };
+    check_file_type(path, &ext)?;
+    let mut artifact = (format.load)(path, opts)?;
+    apply_content_type(&mut artifact, &ext, opts);
+    Ok(artifact)
+}
+
+/// Override the MIME type with the one configured for the file extension, if any
+fn apply_content_type(artifact: &mut RepoArtifact, ext: &str, opts: &LoadOptions) {
+    if let Some((_, content_type)) = opts
+        .content_types
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+    {
+        artifact.content_type = content_type.clone();
+    }
+}
+
+fn load_apk_artifact(path: &Path, opts: &LoadOptions) -> Result<RepoArtifact> {
+    let file = std::io::BufReader::new(File::open(path)?);
+    let (platform, metadata) = read_apk(file, opts.verify_signatures)?;
+    let mut zip = ZipArchive::new(std::io::BufReader::new(File::open(path)?))?;
+    let misaligned = misaligned_entries(&mut zip)?;
+    if !misaligned.is_empty() {
+        let entries: Vec<String> = misaligned
+            .iter()
+            .take(5)
+            .map(|(name, offset, align)| format!("{} at {} ({}-byte)", name, offset, align))
+            .collect();
+        warn!(
+            "{} is not zipaligned, was it built by the release pipeline? Misaligned: {}{}",
+            path.display(),
+            entries.join(", "),
+            if misaligned.len() > entries.len() {
+                format!(" and {} more", misaligned.len() - entries.len())
+            } else {
+                String::new()
+            }
+        );
+    }
+    if opts.verify_signatures {
+        info!("Verified signature digests of {}", path.display());
+    }
+    Ok(RepoArtifact {
+        name: path.file_name().unwrap().to_str().unwrap().to_string(),
+        size: path.metadata()?.len(),
+        location: RepoResource::Local(path.to_path_buf()),
+        download_path: None,
+        downloads: None,
+        hash: hash_file::<Sha256>(path)?,
+        content_type: "application/vnd.android.package-archive".to_string(),
+        platform,
+        metadata,
+    })
+}
+
+/// Read the signatures, manifest and architecture of an APK, optionally verifying the signed
+/// digests against the content
+fn read_apk<R: Read + Seek>(
+    mut file: R,
+    verify_digests: bool,
+) -> Result<(Platform, ArtifactMetadata)> {
+    let sig_block = ApkSigningBlock::from_reader(&mut file)?;
+    if verify_digests {
+        sig_block.verify_digests(&mut file)?;
+    }
+
+    let mut zip = ZipArchive::new(file)?;
+    let (mut manifest, extras) = load_manifest(&mut zip)?;
+    let labels = load_labels(&mut zip, &mut manifest);
+    let page_size_16kb = match page_size_16kb(&mut zip) {
+        Ok(supported) => supported,
+        // range requests don't fetch the libs
+        Err(e) => {
+            debug!("Failed to read the native libs: {}", e);
+            None
+        }
+    };
 
     let lib_arch: HashSet<String> = list_libs(&mut zip)
         .iter()
@@ -399,35 +1612,156 @@ fn load_apk_artifact(path: &Path) -> Result<RepoArtifact> {
         })
         .collect();
 
+    Ok((
+        Platform::Android {
+            arch: apk_architecture(&lib_arch)?,
+        },
+        ArtifactMetadata::APK {
+            manifest,
+            signature_blocks: sig_block.get_signatures()?,
+            labels,
+            extras,
+            page_size_16kb,
+        },
+    ))
+}
+
+/// Parse a semver version, or pad versions like `1.2` or `v2024.05.01-beta` to `major.minor.patch`
+/// dropping any suffix
+pub fn parse_lenient_version(s: &str) -> Option<Version> {
+    let s = s.trim();
+    let s = s.strip_prefix('v').unwrap_or(s);
+    if let Ok(v) = Version::parse(s) {
+        return Some(v);
+    }
+    let numbers = s.split(|c: char| !c.is_ascii_digit() && c != '.').next()?;
+    let mut parts = numbers.split('.').take(3).map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    Some(Version::new(major, minor, patch))
+}
+
+/// Architecture of an APK from the ABI directories under `lib/`
+///
+/// APKs without native code run everywhere and fat APKs bundle every ABI they support, both are
+/// published as `android-universal`.
+fn apk_architecture(lib_arch: &HashSet<String>) -> Result<Architecture> {
+    let mut abis = lib_arch.iter();
+    match (abis.next().map(|a| a.as_str()), abis.next()) {
+        (None, _) | (Some(_), Some(_)) => Ok(Architecture::Universal),
+        (Some("arm64-v8a"), None) => Ok(Architecture::ARM64),
+        (Some("armeabi-v7a"), None) => Ok(Architecture::ARMv7),
+        (Some("x86_64"), None) => Ok(Architecture::X86_64),
+        (Some("x86"), None) => Ok(Architecture::X86),
+        (Some(v), None) => bail!("unknown architecture: {v}"),
+    }
+}
+
+fn load_flatpak_artifact(path: &Path) -> Result<RepoArtifact> {
+    let mut file = File::open(path)?;
+    let bundle = FlatpakBundle::from_reader(&mut file)?;
     Ok(RepoArtifact {
         name: path.file_name().unwrap().to_str().unwrap().to_string(),
         size: path.metadata()?.len(),
         location: RepoResource::Local(path.to_path_buf()),
-        hash: hash_file(path)?,
-        content_type: "application/vnd.android.package-archive".to_string(),
-        platform: Platform::Android {
-            arch: if lib_arch.is_empty() {
-                Architecture::Universal
-            } else {
-                match lib_arch.iter().next().unwrap().as_str() {
-                    "arm64-v8a" => Architecture::ARM64,
-                    "armeabi-v7a" => Architecture::ARMv7,
-                    "x86_64" => Architecture::X86_64,
-                    "x86" => Architecture::X86,
-                    v => bail!("unknown architecture: {v}"),
-                }
+        download_path: None,
+        downloads: None,
+        hash: hash_file::<Sha256>(path)?,
+        content_type: "application/vnd.flatpak".to_string(),
+        platform: Platform::Linux {
+            arch: match bundle.arch.as_str() {
+                "x86_64" => Architecture::X86_64,
+                "aarch64" => Architecture::ARM64,
+                "arm" => Architecture::ARMv7,
+                "i386" => Architecture::X86,
+                v => bail!("unknown architecture: {v}"),
             },
         },
-        metadata: ArtifactMetadata::APK {
-            manifest,
-            signature_blocks: sig_block.get_signatures()?,
+        metadata: ArtifactMetadata::Flatpak {
+            app_id: bundle.app_id,
+            branch: bundle.branch,
+            runtime: bundle.runtime,
         },
     })
 }
 
-fn hash_file(path: &Path) -> Result<Vec<u8>> {
+fn load_snap_artifact(path: &Path) -> Result<RepoArtifact> {
+    let snap = SnapYaml::from_snap(path)?;
+    if snap.confinement.as_deref() == Some("devmode") {
+        warn!(
+            "{} uses devmode confinement, it can only be installed with --devmode",
+            snap.name
+        );
+    }
+    if snap.architectures.len() > 1 {
+        warn!(
+            "{} is built for {}, using the first architecture",
+            snap.name,
+            snap.architectures.join(", ")
+        );
+    }
+    let arch = match snap.architectures.first().map(|a| a.as_str()) {
+        Some("amd64") => Architecture::X86_64,
+        Some("arm64") => Architecture::ARM64,
+        Some("armhf") => Architecture::ARMv7,
+        Some("i386") => Architecture::X86,
+        Some(v) => bail!("unknown architecture: {v}"),
+        None => bail!("{} does not list its architectures", snap.name),
+    };
+    Ok(RepoArtifact {
+        name: path.file_name().unwrap().to_str().unwrap().to_string(),
+        size: path.metadata()?.len(),
+        location: RepoResource::Local(path.to_path_buf()),
+        download_path: None,
+        downloads: None,
+        hash: hash_file::<Sha256>(path)?,
+        content_type: "application/vnd.snap".to_string(),
+        platform: Platform::Linux { arch },
+        metadata: ArtifactMetadata::Snap { snap },
+    })
+}
+
+fn load_ipa_artifact(path: &Path) -> Result<RepoArtifact> {
+    let ipa = IpaInfo::from_ipa(path)?;
+    let arch = match ipa.slices.as_slice() {
+        [arch] => arch.clone(),
+        _ => Architecture::Universal,
+    };
+    Ok(RepoArtifact {
+        name: path.file_name().unwrap().to_str().unwrap().to_string(),
+        size: path.metadata()?.len(),
+        location: RepoResource::Local(path.to_path_buf()),
+        download_path: None,
+        downloads: None,
+        hash: hash_file::<Sha256>(path)?,
+        content_type: "application/x-ios-app".to_string(),
+        platform: Platform::IOS { arch },
+        metadata: ArtifactMetadata::IPA { ipa },
+    })
+}
+
+fn load_web_artifact(
+    path: &Path,
+    manifest: WebManifest,
+    content_type: &str,
+) -> Result<RepoArtifact> {
+    Ok(RepoArtifact {
+        name: path.file_name().unwrap().to_str().unwrap().to_string(),
+        size: path.metadata()?.len(),
+        location: RepoResource::Local(path.to_path_buf()),
+        download_path: None,
+        downloads: None,
+        hash: hash_file::<Sha256>(path)?,
+        content_type: content_type.to_string(),
+        platform: Platform::Web,
+        metadata: ArtifactMetadata::Web { manifest },
+    })
+}
+
+fn hash_file<D: Digest>(path: &Path) -> Result<Vec<u8>> {
     let mut file = File::open(path)?;
-    let mut hash = Sha256::default();
+    let mut hash = D::new();
     let mut buf = vec![0; 4096];
     while let Ok(r) = file.read(&mut buf) {
         if r == 0 {
@@ -438,7 +1772,7 @@ fn hash_file(path: &Path) -> Result<Vec<u8>> {
     Ok(hash.finalize().to_vec())
 }
 
-fn load_manifest<T>(zip: &mut ZipArchive<T>) -> Result<AndroidManifest>
+fn load_manifest<T>(zip: &mut ZipArchive<T>) -> Result<(AndroidManifest, ManifestExtras)>
 where
     T: Read + Seek,
 {
@@ -447,8 +1781,113 @@ where
     let mut f = zip.by_name(ANDROID_MANIFEST)?;
     let mut manifest_data = Vec::with_capacity(8192);
     let r = f.read_to_end(&mut manifest_data)?;
-    let res: AndroidManifest = parse_android_manifest(&manifest_data[..r])?;
-    Ok(res)
+    parse_android_manifest_extras(&manifest_data[..r])
+}
+
+/// Resolve the application label from resources.arsc, setting the default label on the
+/// manifest and returning the localized variants
+fn load_labels<T>(zip: &mut ZipArchive<T>, manifest: &mut AndroidManifest) -> Vec<(String, String)>
+where
+    T: Read + Seek,
+{
+    const RESOURCES: &str = "resources.arsc";
+
+    let Some(id) = manifest.application.label.as_deref().and_then(resource_id) else {
+        return vec![];
+    };
+    let table = zip
+        .by_name(RESOURCES)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut f| {
+            let mut data = Vec::with_capacity(f.size() as usize);
+            f.read_to_end(&mut data)?;
+            ResourceTable::parse(&data)
+        });
+    let mut labels = match table {
+        Ok(table) => table.resolve_strings(id),
+        Err(e) => {
+            warn!("Failed to load {}: {}", RESOURCES, e);
+            return vec![];
+        }
+    };
+    manifest.application.label = labels
+        .iter()
+        .find(|(locale, _)| locale.is_empty())
+        .map(|(_, label)| label.clone());
+    labels.retain(|(locale, _)| !locale.is_empty());
+    labels
+}
+
+/// Alignment `zipalign` uses for the data of uncompressed entries
+const ZIP_ALIGNMENT: u64 = 4;
+
+/// Alignment of uncompressed native libraries, so they can be mapped straight from the APK
+/// (`zipalign -p`)
+const LIB_ALIGNMENT: u64 = 4096;
+
+/// Uncompressed entries whose data is not aligned like `zipalign` does, as
+/// `(name, data offset, required alignment)`
+fn misaligned_entries<T: Read + Seek>(zip: &mut ZipArchive<T>) -> Result<Vec<(String, u64, u64)>> {
+    let mut ret = vec![];
+    for i in 0..zip.len() {
+        let entry = zip.by_index_raw(i)?;
+        if entry.compression() != CompressionMethod::Stored || entry.is_dir() {
+            continue;
+        }
+        let align = if entry.name().starts_with("lib/") && entry.name().ends_with(".so") {
+            LIB_ALIGNMENT
+        } else {
+            ZIP_ALIGNMENT
+        };
+        if entry.data_start() % align != 0 {
+            ret.push((entry.name().to_string(), entry.data_start(), align));
+        }
+    }
+    Ok(ret)
+}
+
+/// Page size of Android 15+ devices with 16KB pages
+const PAGE_SIZE_16KB: u64 = 0x4000;
+
+/// Check the 64-bit native libs can be loaded on 16KB page size devices: their `LOAD` segments
+/// are 16KB aligned, and uncompressed libs are 16KB aligned in the APK, [None] without 64-bit libs
+///
+/// https://developer.android.com/guide/practices/page-sizes
+fn page_size_16kb<T: Read + Seek>(zip: &mut ZipArchive<T>) -> Result<Option<bool>> {
+    let libs: Vec<String> = list_libs(zip)
+        .into_iter()
+        .filter(|l| {
+            (l.starts_with("lib/arm64-v8a/") || l.starts_with("lib/x86_64/")) && l.ends_with(".so")
+        })
+        .collect();
+    let mut checked = 0;
+    let mut unsupported = vec![];
+    for lib in libs {
+        let mut entry = zip.by_name(&lib)?;
+        let stored = entry.compression() == CompressionMethod::Stored;
+        let data_start = entry.data_start();
+        let mut header = vec![];
+        (&mut entry)
+            .take(elf::HEADER_LEN)
+            .read_to_end(&mut header)?;
+        match elf::load_alignment(&header) {
+            Ok(align) => {
+                checked += 1;
+                if align < PAGE_SIZE_16KB || (stored && data_start % PAGE_SIZE_16KB != 0) {
+                    unsupported.push(lib);
+                }
+            }
+            Err(e) => warn!("Failed to read {}: {}", lib, e),
+        }
+    }
+    if !unsupported.is_empty() {
+        warn!(
+            "Native libs are not 16KB page size compatible, required on Android 15+ devices with \
+            16KB pages: {}",
+            unsupported.join(", ")
+        );
+    }
+    Ok((checked > 0).then_some(unsupported.is_empty()))
 }
 
 fn list_libs<T>(zip: &mut ZipArchive<T>) -> Vec<String>
@@ -469,13 +1908,1019 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    #[test]
+    fn manifest_info_json() -> Result<()> {
+        let mut manifest = AndroidManifest::default();
+        manifest.package = Some("com.example.app".to_string());
+        manifest.version_code = Some(42);
+        manifest.uses_permission.push(apk_parser::Permission {
+            name: "android.permission.INTERNET".to_string(),
+            max_sdk_version: None,
+        });
+        let metadata = ArtifactMetadata::APK {
+            manifest,
+            signature_blocks: vec![],
+            labels: vec![("de".to_string(), "Beispiel".to_string())],
+            extras: ManifestExtras {
+                allow_backup: Some(true),
+                install_location: None,
+            },
+            page_size_16kb: None,
+        };
+        let json = serde_json::to_value(metadata.manifest_info())?;
+        assert_eq!(json["package"], "com.example.app");
+        assert_eq!(json["version_code"], 42);
+        assert_eq!(json["labels"]["de"], "Beispiel");
+        assert_eq!(
+            json["permissions"][0]["name"],
+            "android.permission.INTERNET"
+        );
+        assert_eq!(json["features"], serde_json::json!([]));
+        assert_eq!(json["allow_backup"], true);
+        assert_eq!(json["debuggable"], serde_json::Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn select_artifacts() {
+        let mut release = test_release(vec![
+            test_artifact(
+                "a.apk",
+                Platform::Android {
+                    arch: Architecture::ARM64,
+                },
+            ),
+            test_artifact(
+                "b.apk",
+                Platform::Android {
+                    arch: Architecture::X86,
+                },
+            ),
+            test_artifact(
+                "c.apk",
+                Platform::Android {
+                    arch: Architecture::X86_64,
+                },
+            ),
+        ]);
+        let dropped = release.select_artifacts(&[0, 2]);
+        let names: Vec<&str> = release.artifacts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["a.apk", "c.apk"]);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].name, "b.apk");
+    }
+
+    #[test]
+    fn package_mismatches() {
+        let mut artifacts = vec![];
+        for package in ["com.example.app", "com.example.other", "com.example.wear"] {
+            let mut a = test_artifact(
+                &format!("{package}.apk"),
+                Platform::Android {
+                    arch: Architecture::Universal,
+                },
+            );
+            if let ArtifactMetadata::APK { manifest, .. } = &mut a.metadata {
+                manifest.package = Some(package.to_string());
+            }
+            artifacts.push(a);
+        }
+        let release = test_release(artifacts);
+        assert_eq!(
+            release.package_mismatches(&["com.example.app", "com.example.wear"]),
+            [1]
+        );
+    }
+
+    #[test]
+    fn minisign_signature() -> Result<()> {
+        let key = VerifyWith::Minisign(
+            "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3".to_string(),
+        );
+        let signature = b"untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==";
+        let path = temp_dir().join("nap-minisign-test");
+        std::fs::write(&path, b"test")?;
+        verify_detached(&key, &path, signature)?;
+        std::fs::write(&path, b"Test")?;
+        assert!(verify_detached(&key, &path, signature).is_err());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn partial_download_lock() -> Result<()> {
+        let part = std::env::temp_dir().join("nap-test-download.apk.part");
+        let first = lock_partial_download(&part)?;
+        let second = lock_partial_download(&part)?;
+        assert!(first.is_some());
+        assert!(second.is_none());
+        drop(first);
+        let third = lock_partial_download(&part)?;
+        std::fs::remove_file(&part)?;
+        assert!(third.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_downloads() -> Result<()> {
+        let fixture =
+            std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/fixture.apk"))?;
+        let hash = Sha256::digest(&fixture).to_vec();
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/app.apk", server.local_addr()?);
+        // no range support, a resumed download starts over
+        std::thread::spawn(move || {
+            for conn in server.incoming() {
+                let fixture = fixture.clone();
+                std::thread::spawn(move || -> Result<()> {
+                    let mut conn = conn?;
+                    let mut buf = vec![0; 1024];
+                    let _request = conn.read(&mut buf)?;
+                    conn.write_all(
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n",
+                            fixture.len()
+                        )
+                        .as_bytes(),
+                    )?;
+                    conn.write_all(&fixture)?;
+                    Ok(())
+                });
+            }
+        });
+
+        let cache_dir = std::env::temp_dir().join("nap-test-concurrent-downloads");
+        let opts = LoadOptions {
+            cache_dir: Some(cache_dir.clone()),
+            ..Default::default()
+        };
+        let mut results = vec![];
+        for _ in 0..5 {
+            let _ = std::fs::remove_dir_all(&cache_dir);
+            let (a, b, c) = tokio::join!(
+                load_artifact_url(&url, &opts, None),
+                load_artifact_url(&url, &opts, None),
+                load_artifact_url(&url, &opts, None)
+            );
+            results.extend([a, b, c]);
+        }
+        std::fs::remove_dir_all(&cache_dir)?;
+        for a in results {
+            assert_eq!(a?.hash, hash);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn total_progress() {
+        let mut progress = TotalProgress::new(&MultiProgress::new(), 2, 5);
+        progress.start_release("v1.0.0", 3);
+        progress.inc();
+        assert_eq!(progress.bar.message(), "Release 1 of 2 (v1.0.0)");
+        // skipped assets count as done
+        progress.start_release("v1.1.0", 2);
+        assert_eq!(progress.bar.position(), 3);
+        assert_eq!(progress.bar.message(), "Release 2 of 2 (v1.1.0)");
+        progress.inc();
+        progress.inc();
+        progress.inc();
+        assert_eq!(progress.bar.position(), 5);
+
+        // a release without artifacts makes room for the next one
+        progress.start_release("v0.9.0", 4);
+        assert_eq!(progress.bar.message(), "Release 3 of 3 (v0.9.0)");
+        assert_eq!(progress.bar.length(), Some(9));
+    }
+
+    #[test]
+    fn content_range() {
+        assert_eq!(
+            parse_content_range_start("bytes 1024-2047/2048"),
+            Some(1024)
+        );
+        assert_eq!(parse_content_range_start("bytes 0-99/*"), Some(0));
+        assert_eq!(parse_content_range_start("bytes */2048"), None);
+        assert_eq!(parse_content_range_start("items 0-1/2"), None);
+        assert_eq!(
+            parse_content_range("bytes 1024-2047/2048"),
+            Some((1024, Some(2048)))
+        );
+        assert_eq!(parse_content_range("bytes 0-99/*"), Some((0, None)));
+    }
+
+    fn signature_block(v3: bool, certificate: &[u8]) -> ApkSignatureBlock {
+        if v3 {
+            ApkSignatureBlock::V3 {
+                signatures: vec![],
+                certificates: vec![certificate.to_vec()],
+                public_key: vec![],
+                attributes: Default::default(),
+                min_sdk: 28,
+                max_sdk: u32::MAX,
+            }
+        } else {
+            ApkSignatureBlock::V2 {
+                signatures: vec![],
+                public_key: vec![],
+                certificates: vec![certificate.to_vec()],
+                attributes: Default::default(),
+            }
+        }
+    }
+
+    #[test]
+    fn dedupe_signature_hashes() -> Result<()> {
+        let hashes = |blocks: Vec<ApkSignatureBlock>| -> Result<Vec<String>> {
+            let mut a = test_artifact(
+                "app.apk",
+                Platform::Android {
+                    arch: Architecture::Universal,
+                },
+            );
+            a.metadata = ArtifactMetadata::APK {
+                manifest: AndroidManifest::default(),
+                signature_blocks: blocks,
+                labels: vec![],
+                extras: Default::default(),
+                page_size_16kb: None,
+            };
+            let eb: EventBuilder = a.try_into()?;
+            let ev = eb.sign_with_keys(&nostr_sdk::Keys::generate())?;
+            Ok(ev
+                .tags
+                .iter()
+                .filter_map(|t| match t.as_slice() {
+                    [n, v] if n == "apk_signature_hash" => Some(v.clone()),
+                    _ => None,
+                })
+                .collect())
+        };
+
+        // same key in v2 and v3
+        let dual = hashes(vec![
+            signature_block(false, b"cert-a"),
+            signature_block(true, b"cert-a"),
+        ])?;
+        assert_eq!(dual, [hex::encode(Sha256::digest(b"cert-a"))]);
+
+        // rotated key, the v3 signer wins
+        let rotated = hashes(vec![
+            signature_block(false, b"cert-a"),
+            signature_block(true, b"cert-b"),
+        ])?;
+        assert_eq!(rotated, [hex::encode(Sha256::digest(b"cert-b"))]);
+
+        let v2_only = hashes(vec![signature_block(false, b"cert-a")])?;
+        assert_eq!(v2_only, [hex::encode(Sha256::digest(b"cert-a"))]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn release_events_golden() -> Result<()> {
+        let mut artifact = test_artifact(
+            "app.apk",
+            Platform::Android {
+                arch: Architecture::ARM64,
+            },
+        );
+        if let ArtifactMetadata::APK { manifest, .. } = &mut artifact.metadata {
+            manifest.package = Some("com.example.app".to_string());
+            manifest.version_name = Some("1.2.3".to_string());
+            manifest.version_code = Some(42);
+            manifest.sdk.min_sdk_version = Some(24);
+            manifest.sdk.target_sdk_version = Some(34);
+        }
+        let release = RepoRelease {
+            version: Version::new(1, 2, 3),
+            description: Some("Release notes".to_string()),
+            url: Some("https://github.com/example/app/releases/v1.2.3".to_string()),
+            // the app id comes from the APK package
+            manifest_id: None,
+            published_at: Some("2021-03-04T05:06:07Z".parse()?),
+            ..test_release(vec![artifact])
+        };
+        let keys = nostr_sdk::Keys::generate();
+        let app_coord = app_coord(&keys);
+        let app_a = format!("32267:{}:com.example.app", keys.public_key);
+        let events = release
+            .into_release_list_event(&keys, app_coord, &Default::default())
+            .await?;
+        let tags = |ev: &Event| -> Vec<Vec<String>> {
+            ev.tags.iter().map(|t| t.as_slice().to_vec()).collect()
+        };
+
+        let file = &events[0];
+        assert_eq!(file.kind, Kind::FileMetadata);
+        assert_eq!(
+            tags(file),
+            [
+                vec!["f", "android-arm64-v8a"],
+                vec!["m", "application/vnd.android.package-archive"],
+                vec!["size", "1"],
+                vec!["x", &hex::encode([0; 32])],
+                vec!["url", "https://example.com/app.apk"],
+                vec!["version", "1.2.3"],
+                vec!["version_code", "42"],
+                vec!["min_sdk_version", "24"],
+                vec!["target_sdk_version", "34"],
+                vec!["published_at", "1614834367"],
+                vec!["a", &app_a],
+            ]
+        );
+
+        let list = &events[1];
+        assert_eq!(list.kind, Kind::Custom(30_063));
+        assert_eq!(list.content, "Release notes");
+        assert_eq!(
+            tags(list),
+            [
+                vec!["a", app_a.as_str()],
+                vec!["d", "com.example.app@1.2.3"],
+                vec!["published_at", "1614834367"],
+                vec!["url", "https://github.com/example/app/releases/v1.2.3"],
+                vec!["release_hash", &hex::encode(Sha256::digest([0; 32]))],
+                vec!["e", &file.id.to_hex()],
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn release_notes_format() -> Result<()> {
+        let release = RepoRelease {
+            description: Some("## Fixes\r\n- **Faster** sync\r\n".to_string()),
+            ..test_release(vec![])
+        };
+        let keys = nostr_sdk::Keys::generate();
+        let app_coord = app_coord(&keys);
+        let markdown_tag = |ev: &Event| {
+            ev.tags
+                .iter()
+                .find(|t| t.kind().to_string() == "markdown")
+                .and_then(|t| t.content().map(|c| c.to_string()))
+        };
+
+        let events = release
+            .clone()
+            .into_release_list_event(&keys, app_coord.clone(), &Default::default())
+            .await?;
+        assert_eq!(events[0].content, "## Fixes\n- **Faster** sync");
+        assert_eq!(markdown_tag(&events[0]), None);
+
+        let opts = EventOptions {
+            notes_format: NotesFormat::Plain,
+            ..Default::default()
+        };
+        let events = release
+            .into_release_list_event(&keys, app_coord, &opts)
+            .await?;
+        assert_eq!(events[0].content, "Fixes\n- Faster sync");
+        assert_eq!(
+            markdown_tag(&events[0]).as_deref(),
+            Some("## Fixes\n- **Faster** sync")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn release_hash_ignores_order() {
+        let artifact = |hash: u8| RepoArtifact {
+            hash: vec![hash; 32],
+            ..test_artifact("app.apk", Platform::Web)
+        };
+        let mut release = test_release(vec![artifact(1), artifact(2)]);
+        let hash = release.release_hash();
+        release.artifacts.reverse();
+        assert_eq!(release.release_hash(), hash);
+        release.artifacts[0] = artifact(3);
+        assert_ne!(release.release_hash(), hash);
+    }
+
+    #[tokio::test]
+    async fn file_event_icon() -> Result<()> {
+        let android = Platform::Android {
+            arch: Architecture::ARM64,
+        };
+        let release = test_release(vec![
+            test_artifact("app.apk", android.clone()),
+            test_artifact("app.zip", Platform::Web),
+        ]);
+        let keys = nostr_sdk::Keys::generate();
+        let app_coord = app_coord(&keys);
+        let opts = EventOptions {
+            icon: Some(PlatformValues::ByPlatform(BTreeMap::from([(
+                "android".to_string(),
+                "https://example.com/icon.png".to_string(),
+            )]))),
+            min_os: HashMap::from([
+                ("android-arm64-v8a".to_string(), "7.0".to_string()),
+                ("android".to_string(), "5.0".to_string()),
+                ("web".to_string(), "Chrome 109".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let events = release
+            .into_release_list_event(&keys, app_coord, &opts)
+            .await?;
+        let tag = |ev: &Event, name: &str| {
+            ev.tags
+                .iter()
+                .find(|t| t.kind().to_string() == name)
+                .map(|t| t.as_slice().to_vec())
+        };
+        let icon = "https://example.com/icon.png".to_string();
+        assert_eq!(
+            tag(&events[0], "image"),
+            Some(vec!["image".to_string(), icon.clone()])
+        );
+        assert_eq!(
+            tag(&events[0], "thumb"),
+            Some(vec!["thumb".to_string(), icon])
+        );
+        // no icon for web
+        assert_eq!(tag(&events[1], "image"), None);
+        assert_eq!(tag(&events[1], "thumb"), None);
+        // the full platform tag wins over the OS
+        assert_eq!(
+            tag(&events[0], "min_os_version"),
+            Some(vec!["min_os_version".to_string(), "7.0".to_string()])
+        );
+        assert_eq!(
+            tag(&events[1], "min_os_version"),
+            Some(vec!["min_os_version".to_string(), "Chrome 109".to_string()])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reuse_file_events() -> Result<()> {
+        let release = test_release(vec![
+            test_artifact(
+                "app.apk",
+                Platform::Android {
+                    arch: Architecture::ARM64,
+                },
+            ),
+            RepoArtifact {
+                hash: vec![1; 32],
+                ..test_artifact("app.zip", Platform::Web)
+            },
+        ]);
+        let keys = nostr_sdk::Keys::generate();
+        let app_coord = app_coord(&keys);
+        let published = EventId::all_zeros();
+        let opts = EventOptions {
+            existing_files: HashMap::from([(hex::encode([1; 32]), published)]),
+            ..Default::default()
+        };
+        let events = release
+            .into_release_list_event(&keys, app_coord, &opts)
+            .await?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, Kind::FileMetadata);
+        let refs: Vec<EventId> = events[1].tags.event_ids().copied().collect();
+        assert_eq!(refs, [events[0].id, published]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn release_published_at() -> Result<()> {
+        let release = RepoRelease {
+            published_at: Some("2021-03-04T05:06:07Z".parse()?),
+            ..test_release(vec![test_artifact(
+                "app.apk",
+                Platform::Android {
+                    arch: Architecture::Universal,
+                },
+            )])
+        };
+        let keys = nostr_sdk::Keys::generate();
+        let app_coord = app_coord(&keys);
+        let events = release
+            .clone()
+            .into_release_list_event(&keys, app_coord.clone(), &Default::default())
+            .await?;
+        assert_eq!(events.len(), 2);
+        for ev in &events {
+            assert!(ev
+                .tags
+                .iter()
+                .any(|t| t.as_slice() == ["published_at", "1614834367"]));
+        }
+
+        let published = release.published_timestamp();
+        let events = release
+            .clone()
+            .into_release_list_event(
+                &keys,
+                app_coord,
+                &EventOptions {
+                    created_at: Some(published),
+                    omit_tags: vec!["apk_signature_hash".to_string(), "published_at".to_string()],
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert!(events.iter().all(|e| e.created_at.as_u64() == 1614834367));
+        // omitted tags only apply to the file events
+        assert!(!events[0]
+            .tags
+            .iter()
+            .any(|t| t.kind().to_string() == "published_at"));
+        assert!(events[1]
+            .tags
+            .iter()
+            .any(|t| t.kind().to_string() == "published_at"));
+
+        let release = RepoRelease {
+            published_at: None,
+            ..release
+        };
+        let now = Timestamp::now();
+        assert!(release.published_timestamp() >= now);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn http_proxy() -> Result<()> {
+        let proxy = TcpListener::bind("127.0.0.1:0")?;
+        let client = http_client(
+            Duration::from_secs(5),
+            Some(&format!("http://{}", proxy.local_addr()?)),
+        )?;
+        let server = std::thread::spawn(move || -> Result<String> {
+            let (mut conn, _) = proxy.accept()?;
+            let mut buf = vec![0; 1024];
+            let len = conn.read(&mut buf)?;
+            conn.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")?;
+            Ok(String::from_utf8_lossy(&buf[..len]).to_string())
+        });
+
+        let rsp = client.get("http://example.invalid/app.apk").send().await?;
+        assert!(rsp.status().is_success());
+        let req = server.join().unwrap()?;
+        assert!(
+            req.starts_with("GET http://example.invalid/app.apk"),
+            "{}",
+            req
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn platform_tag_round_trip() -> Result<()> {
+        for p in Platform::all().into_iter().filter(|p| p.is_supported()) {
+            let tag = p.to_string();
+            assert!(!tag.contains("unknown"), "{:?} => {}", p, tag);
+            assert_eq!(Platform::from_str(&tag)?, p, "{}", tag);
+        }
+        assert_eq!(
+            Platform::Android {
+                arch: Architecture::ARM64
+            }
+            .to_string(),
+            "android-arm64-v8a"
+        );
+        assert_eq!(
+            Platform::MacOS {
+                arch: Architecture::ARM64
+            }
+            .to_string(),
+            "darwin-aarch64"
+        );
+        assert!(Platform::from_str("android-unknown").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn platform_tags_complete() {
+        let mut tags: Vec<String> = Platform::all()
+            .into_iter()
+            .filter(|p| p.is_supported())
+            .map(|p| p.to_string())
+            .collect();
+        tags.sort();
+        assert_eq!(
+            tags,
+            [
+                "android-arm64-v8a",
+                "android-armeabi-v7a",
+                "android-universal",
+                "android-x86",
+                "android-x86_64",
+                "darwin-aarch64",
+                "darwin-universal",
+                "darwin-x86",
+                "darwin-x86_64",
+                "ios-arm64",
+                "ios-arm64e",
+                "ios-armv7",
+                "ios-universal",
+                "linux-aarch64",
+                "linux-armv7",
+                "linux-x86",
+                "linux-x86_64",
+                "web",
+                "windows-aarch64",
+                "windows-armv7",
+                "windows-x86",
+                "windows-x86_64",
+            ]
+        );
+    }
+
+    fn test_artifact(name: &str, platform: Platform) -> RepoArtifact {
+        RepoArtifact {
+            name: name.to_string(),
+            size: 1,
+            location: RepoResource::Remote(format!("https://example.com/{name}")),
+            content_type: "application/vnd.android.package-archive".to_string(),
+            platform,
+            metadata: ArtifactMetadata::APK {
+                manifest: AndroidManifest::default(),
+                signature_blocks: vec![],
+                labels: vec![],
+                extras: Default::default(),
+                page_size_16kb: None,
+            },
+            hash: vec![0; 32],
+            download_path: None,
+            downloads: None,
+        }
+    }
+
+    /// Release 1.0.0 of `com.example.app` with the given artifacts
+    fn test_release(artifacts: Vec<RepoArtifact>) -> RepoRelease {
+        RepoRelease {
+            version: Version::new(1, 0, 0),
+            description: None,
+            url: None,
+            artifacts,
+            manifest_id: Some("com.example.app".to_string()),
+            published_at: None,
+        }
+    }
+
+    /// Coordinate of the `com.example.app` app event signed with `keys`
+    fn app_coord(keys: &nostr_sdk::Keys) -> Coordinate {
+        Coordinate::new(Kind::Custom(32_267), keys.public_key).identifier("com.example.app")
+    }
+
+    #[test]
+    fn retain_platforms() {
+        let mut release = test_release(vec![
+            test_artifact(
+                "app.apk",
+                Platform::Android {
+                    arch: Architecture::ARM64,
+                },
+            ),
+            test_artifact(
+                "app-linux",
+                Platform::Linux {
+                    arch: Architecture::X86_64,
+                },
+            ),
+            test_artifact(
+                "app-windows",
+                Platform::Windows {
+                    arch: Architecture::X86_64,
+                },
+            ),
+        ]);
+        release.retain_platforms(&["android".to_string(), "linux-x86_64".to_string()]);
+        let names: Vec<_> = release.artifacts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["app.apk", "app-linux"]);
+    }
+
+    #[test]
+    fn sidecar_files() {
+        assert!(is_sidecar("app.apk.asc"));
+        assert!(is_sidecar("app.apk.SHA256"));
+        assert!(is_sidecar("SHA256SUMS"));
+        assert!(is_sidecar("checksums.txt"));
+        assert!(!is_sidecar("app.apk"));
+        assert!(!is_sidecar("signal.apk"));
+    }
+
+    #[test]
+    fn parse_checksum() -> Result<()> {
+        let sha256 = "a".repeat(64);
+        let c = Checksum::parse("app.apk.sha256", &format!("{sha256}  app.apk\n"))?;
+        assert!(matches!(c, Checksum::Sha256(h) if h.len() == 32));
+        assert!(Checksum::parse("app.apk.sha512", &sha256).is_err());
+        assert!(Checksum::parse("app.apk.sha256", "").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn asset_filter() -> Result<()> {
+        let filter = AssetFilter::new(&["*.apk".to_string()], &["*-debug.apk".to_string()])?;
+        assert!(filter.matches("app-arm64-v8a.apk"));
+        assert!(!filter.matches("app-debug.apk"));
+        assert!(!filter.matches("app.apk.sha256"));
+
+        let filter = AssetFilter::new(&[], &["*.tar.gz".to_string()])?;
+        assert!(filter.matches("app.apk"));
+        assert!(!filter.matches("source.tar.gz"));
+
+        let filter = filter.with_glob(Some("*-arm64-*"))?;
+        assert!(filter.matches("app-arm64-v8a.apk"));
+        assert!(!filter.matches("app-x86_64.apk"));
+        assert!(!filter.matches("source-arm64-v8a.tar.gz"));
+        assert!(AssetFilter::default().with_glob(Some("[")).is_err());
+        Ok(())
+    }
+
+    /// `testdata/fixture.apk` is a synthetic APK with a binary manifest, an arm64 native lib and a
+    /// v2 signing block using the debug certificate, the content digest is valid but the
+    /// signature bytes are not
+    #[test]
+    fn load_fixture_apk() -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/fixture.apk");
+        let apk = load_apk_artifact(
+            &path,
+            &LoadOptions {
+                verify_signatures: true,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(
+            apk.platform,
+            Platform::Android {
+                arch: Architecture::ARM64
+            }
+        );
+        assert_eq!(apk.content_type, "application/vnd.android.package-archive");
+        let ArtifactMetadata::APK {
+            manifest,
+            signature_blocks,
+            ..
+        } = &apk.metadata
+        else {
+            bail!("missing apk metadata");
+        };
+        assert_eq!(manifest.package.as_deref(), Some("com.example.fixture"));
+        assert_eq!(manifest.version_name.as_deref(), Some("1.2.3"));
+        assert_eq!(manifest.version_code, Some(42));
+        assert_eq!(manifest.sdk.min_sdk_version, Some(24));
+        assert_eq!(signature_blocks.len(), 1);
+        assert!(matches!(signature_blocks[0], ApkSignatureBlock::V2 { .. }));
+        assert!(apk.metadata.is_debug_signed());
+        assert!(!apk.metadata.is_debuggable());
+
+        let apk = load_artifact(
+            &path,
+            &LoadOptions {
+                content_types: HashMap::from([(
+                    "apk".to_string(),
+                    "application/x-example".to_string(),
+                )]),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(apk.content_type, "application/x-example");
+
+        // Windows builds often have uppercase extensions
+        let upper = temp_dir().join("nap-test-Fixture.APK");
+        std::fs::copy(&path, &upper)?;
+        let apk = load_artifact(&upper, &LoadOptions::default());
+        std::fs::remove_file(&upper)?;
+        assert!(matches!(apk?.metadata, ArtifactMetadata::APK { .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn page_size_16kb_libs() -> Result<()> {
+        use apk_parser::zip::write::{FileOptions, ZipWriter};
+        use std::io::{Cursor, Write};
+
+        let apk = |libs: &[(&str, u64)]| -> Result<Option<bool>> {
+            let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+            let mut zip = ZipWriter::new(Cursor::new(vec![]));
+            for (name, align) in libs {
+                zip.start_file_aligned(*name, stored, PAGE_SIZE_16KB as u16)?;
+                zip.write_all(&elf::elf64(&[*align, *align]))?;
+            }
+            let data = zip.finish()?.into_inner();
+            page_size_16kb(&mut ZipArchive::new(Cursor::new(data))?)
+        };
+        assert_eq!(apk(&[])?, None);
+        assert_eq!(apk(&[("lib/armeabi-v7a/libapp.so", 0x1000)])?, None);
+        assert_eq!(
+            apk(&[
+                ("lib/arm64-v8a/libapp.so", 0x4000),
+                ("lib/x86_64/libapp.so", 0x10000),
+                ("lib/armeabi-v7a/libapp.so", 0x1000),
+            ])?,
+            Some(true)
+        );
+        assert_eq!(
+            apk(&[
+                ("lib/arm64-v8a/libapp.so", 0x4000),
+                ("lib/arm64-v8a/libold.so", 0x1000),
+            ])?,
+            Some(false)
+        );
+
+        let mut artifact = test_artifact(
+            "app.apk",
+            Platform::Android {
+                arch: Architecture::ARM64,
+            },
+        );
+        if let ArtifactMetadata::APK { page_size_16kb, .. } = &mut artifact.metadata {
+            *page_size_16kb = Some(false);
+        }
+        artifact.downloads = Some(1234);
+        let tags = artifact.file_tags()?;
+        assert!(tags
+            .iter()
+            .any(|t| t.as_slice() == ["page_size_16kb", "false"]));
+        assert!(tags.iter().any(|t| t.as_slice() == ["downloads", "1234"]));
+        Ok(())
+    }
+
+    #[test]
+    fn apk_alignment() -> Result<()> {
+        use apk_parser::zip::write::{FileOptions, ZipWriter};
+        use std::io::{Cursor, Write};
+
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("AndroidManifest.xml", FileOptions::default())?;
+        zip.write_all(b"compressed entries are never aligned")?;
+        zip.start_file_aligned("resources.arsc", stored, 4)?;
+        zip.write_all(b"arsc")?;
+        zip.start_file_aligned("lib/arm64-v8a/libok.so", stored, 4096)?;
+        zip.write_all(b"elf")?;
+        // no padding after the 3 byte lib, so the data is not 4-byte aligned
+        zip.start_file("assets/a.bin", stored)?;
+        zip.write_all(b"a")?;
+        zip.start_file_aligned("lib/arm64-v8a/libbad.so", stored, 4)?;
+        zip.write_all(b"elf")?;
+        let mut zip = ZipArchive::new(Cursor::new(zip.finish()?.into_inner()))?;
+
+        let misaligned: Vec<(String, u64)> = misaligned_entries(&mut zip)?
+            .into_iter()
+            .map(|(name, _, align)| (name, align))
+            .collect();
+        assert_eq!(
+            misaligned,
+            [
+                ("assets/a.bin".to_string(), ZIP_ALIGNMENT),
+                ("lib/arm64-v8a/libbad.so".to_string(), LIB_ALIGNMENT),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn artifact_formats() {
+        let format = |name: &str| ArtifactFormat::from_path(Path::new(name)).map(|f| f.extensions);
+        assert_eq!(format("app-arm64-v8a.apk"), Some(&["apk"][..]));
+        assert_eq!(format("site.tar.gz"), Some(&["tar.gz", "tgz"][..]));
+        assert_eq!(format("notes.gz"), None);
+        assert_eq!(format("App-Release.APK"), Some(&["apk"][..]));
+        assert_eq!(format("SITE.TAR.GZ"), Some(&["tar.gz", "tgz"][..]));
+        assert_eq!(artifact_extension("Site.Tar.Gz").as_deref(), Some("tar.gz"));
+        assert_eq!(artifact_extension("notes.GZ").as_deref(), Some("gz"));
+        assert_eq!(artifact_extension("README"), None);
+        assert_eq!(format("app.dmg"), None);
+
+        let err = load_artifact(Path::new("app.dmg"), &LoadOptions::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown file extension: dmg, supported: apk, flatpak, snap, ipa, zip, tar.gz, tgz"
+        );
+    }
+
+    /// `testdata/fixture-zip64.apk` is `fixture.apk` rewritten with a ZIP64 end of central
+    /// directory and ZIP64 local header offsets, as written for APKs over 4GB
+    #[test]
+    fn load_zip64_fixture_apk() -> Result<()> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let zip64 = dir.join("fixture-zip64.apk");
+        let apk = load_apk_artifact(&zip64, &LoadOptions::default())?;
+        let expected = load_apk_artifact(&dir.join("fixture.apk"), &LoadOptions::default())?;
+        assert_eq!(apk.platform, expected.platform);
+        assert_eq!(apk.metadata.package(), expected.metadata.package());
+        assert_eq!(apk.metadata.version(), expected.metadata.version());
+
+        let sig_block = ApkSigningBlock::from_path(&zip64)?;
+        assert_eq!(
+            sig_block.offset,
+            ApkSigningBlock::from_path(dir.join("fixture.apk"))?.offset
+        );
+        assert!(matches!(
+            sig_block.get_signatures()?.as_slice(),
+            [ApkSignatureBlock::V2 { .. }]
+        ));
+
+        let verify = load_apk_artifact(
+            &zip64,
+            &LoadOptions {
+                verify_signatures: true,
+                ..Default::default()
+            },
+        );
+        assert!(verify
+            .unwrap_err()
+            .to_string()
+            .contains("ZIP64 APKs can't be verified"));
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_version() {
+        let v = |s| parse_lenient_version(s);
+        assert_eq!(v("v1.2.3-rc1"), Some(Version::parse("1.2.3-rc1").unwrap()));
+        assert_eq!(v("1.2"), Some(Version::new(1, 2, 0)));
+        assert_eq!(v("2024.05.01-beta"), Some(Version::new(2024, 5, 1)));
+        assert_eq!(v("7 (build 123)"), Some(Version::new(7, 0, 0)));
+        assert_eq!(v("nightly"), None);
+
+        let mut manifest = AndroidManifest::default();
+        manifest.version_name = Some("nightly".to_string());
+        manifest.version_code = Some(120);
+        let mut artifact = test_artifact("app.apk", Platform::Web);
+        artifact.metadata = ArtifactMetadata::APK {
+            manifest,
+            signature_blocks: vec![],
+            labels: vec![],
+            extras: Default::default(),
+            page_size_16kb: None,
+        };
+        assert_eq!(
+            RepoRelease::artifact_version(&[test_artifact("app.apk", Platform::Web), artifact]),
+            Some(Version::new(120, 0, 0))
+        );
+    }
+
+    #[test]
+    fn apk_architectures() -> Result<()> {
+        let abis = |a: &[&str]| -> HashSet<String> { a.iter().map(|s| s.to_string()).collect() };
+        assert_eq!(apk_architecture(&abis(&[]))?, Architecture::Universal);
+        assert_eq!(apk_architecture(&abis(&["x86_64"]))?, Architecture::X86_64);
+        assert_eq!(
+            apk_architecture(&abis(&["arm64-v8a", "armeabi-v7a", "x86_64"]))?,
+            Architecture::Universal
+        );
+        assert!(apk_architecture(&abis(&["mips"])).is_err());
+        assert_eq!(
+            Platform::Android {
+                arch: Architecture::Universal
+            }
+            .to_string(),
+            "android-universal"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ipa_slice_tags() -> Result<()> {
+        let mut artifact = test_artifact(
+            "app.ipa",
+            Platform::IOS {
+                arch: Architecture::Universal,
+            },
+        );
+        artifact.metadata = ArtifactMetadata::IPA {
+            ipa: IpaInfo {
+                info: ipa::InfoPlist {
+                    bundle_id: "com.example.app".to_string(),
+                    name: None,
+                    executable: "Example".to_string(),
+                    version: Some("1.2".to_string()),
+                    build: None,
+                },
+                slices: vec![Architecture::ARM64, Architecture::ARM64e],
+            },
+        };
+        assert_eq!(artifact.metadata.version(), Some(Version::new(1, 2, 0)));
+        let platforms: Vec<String> = artifact
+            .file_tags()?
+            .into_iter()
+            .filter(|t| t.kind() == nostr_sdk::TagKind::custom("f"))
+            .filter_map(|t| t.content().map(|c| c.to_string()))
+            .collect();
+        assert_eq!(platforms, ["ios-arm64", "ios-arm64e"]);
+        Ok(())
+    }
 
     #[ignore]
     #[test]
+    #[allow(irrefutable_let_patterns)]
     fn read_apk() -> Result<()> {
         let path = "/home/kieran/Downloads/snort-arm64-v8a-v0.3.0.apk";
 
-        let apk = load_apk_artifact(&PathBuf::from(path))?;
+        let apk = load_apk_artifact(&PathBuf::from(path), &LoadOptions::default())?;
 
         eprintln!("{:?}", apk);
         if let ArtifactMetadata::APK { .. } = apk.metadata {
@@ -0,0 +1,472 @@
+use crate::repo::{parse_content_range, read_apk, Checksum, RepoArtifact, RepoResource};
+use anyhow::{anyhow, bail, ensure, Result};
+use log::{info, warn};
+use nostr_sdk::prelude::StreamExt;
+use reqwest::header::RANGE;
+use reqwest::{RequestBuilder, StatusCode};
+use sha2::{Digest, Sha256, Sha512};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Bytes fetched from the end of the file, the EOCD record plus a maximum length comment
+const TAIL_LEN: u64 = 22 + u16::MAX as u64;
+
+/// Entries read from the APK besides the central directory and signing block
+const APK_ENTRIES: &[&str] = &["AndroidManifest.xml", "resources.arsc"];
+
+/// A remote file of which only some byte ranges were fetched, reading anything else fails
+#[derive(Debug, Default)]
+pub struct SparseFile {
+    len: u64,
+    /// Fetched `(offset, data)` chunks
+    chunks: Vec<(u64, Vec<u8>)>,
+    pos: u64,
+}
+
+impl SparseFile {
+    /// Check if `start..end` was fetched, within a single chunk
+    fn contains(&self, start: u64, end: u64) -> bool {
+        self.chunks
+            .iter()
+            .any(|(o, d)| *o <= start && end <= o + d.len() as u64)
+    }
+
+    /// Bytes at `start..end`, which must be fetched
+    fn slice(&self, start: u64, end: u64) -> Result<&[u8]> {
+        let (o, d) = self
+            .chunks
+            .iter()
+            .find(|(o, d)| *o <= start && end <= o + d.len() as u64)
+            .ok_or(anyhow!("bytes {}..{} were not fetched", start, end))?;
+        Ok(&d[(start - o) as usize..(end - o) as usize])
+    }
+}
+
+impl Read for SparseFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let pos = self.pos;
+        let Some((o, d)) = self
+            .chunks
+            .iter()
+            .find(|(o, d)| *o <= pos && pos < o + d.len() as u64)
+        else {
+            return Err(std::io::Error::other(format!(
+                "byte {} was not fetched",
+                pos
+            )));
+        };
+        let avail = &d[(pos - o) as usize..];
+        let n = avail.len().min(buf.len());
+        buf[..n].copy_from_slice(&avail[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SparseFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        self.pos = pos.ok_or(std::io::Error::other("seek before the start of the file"))?;
+        Ok(self.pos)
+    }
+}
+
+/// Fetches byte ranges of a remote file with HTTP `Range` requests
+struct RangeFetcher {
+    req: RequestBuilder,
+    file: SparseFile,
+}
+
+impl RangeFetcher {
+    /// Fetch the last `tail_len` bytes of the file, which also gives its length
+    async fn open(req: RequestBuilder, tail_len: u64) -> Result<RangeFetcher> {
+        let rsp = clone_request(&req)?
+            .header(RANGE, format!("bytes=-{}", tail_len))
+            .send()
+            .await?
+            .error_for_status()?;
+        ensure!(
+            rsp.status() == StatusCode::PARTIAL_CONTENT,
+            "server does not support range requests"
+        );
+        let (start, len) = rsp
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| parse_content_range(v.to_str().ok()?))
+            .ok_or(anyhow!("missing Content-Range"))?;
+        let len = len.ok_or(anyhow!("unknown file length"))?;
+        let data = rsp.bytes().await?.to_vec();
+        Ok(RangeFetcher {
+            req,
+            file: SparseFile {
+                len,
+                chunks: vec![(start, data)],
+                pos: 0,
+            },
+        })
+    }
+
+    /// Fetch `start..end` unless it was fetched before
+    async fn fetch(&mut self, start: u64, end: u64) -> Result<()> {
+        let end = end.min(self.file.len);
+        if start >= end || self.file.contains(start, end) {
+            return Ok(());
+        }
+        let rsp = clone_request(&self.req)?
+            .header(RANGE, format!("bytes={}-{}", start, end - 1))
+            .send()
+            .await?
+            .error_for_status()?;
+        ensure!(
+            rsp.status() == StatusCode::PARTIAL_CONTENT,
+            "server ignored the range request"
+        );
+        let data = rsp.bytes().await?.to_vec();
+        ensure!(
+            data.len() as u64 == end - start,
+            "expected {} bytes, got {}",
+            end - start,
+            data.len()
+        );
+        self.file.chunks.push((start, data));
+        Ok(())
+    }
+
+    fn u16_at(&self, at: u64) -> Result<u64> {
+        Ok(u16::from_le_bytes(self.file.slice(at, at + 2)?.try_into()?) as u64)
+    }
+
+    fn u32_at(&self, at: u64) -> Result<u64> {
+        Ok(u32::from_le_bytes(self.file.slice(at, at + 4)?.try_into()?) as u64)
+    }
+
+    fn u64_at(&self, at: u64) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.file.slice(at, at + 8)?.try_into()?))
+    }
+
+    /// Compressed size and local header offset of a central directory entry, taken from the
+    /// ZIP64 extra field when they don't fit in 32 bits
+    fn entry_location(&self, entry: u64) -> Result<(u64, u64)> {
+        const ZIP64_EXTRA_ID: u64 = 0x0001;
+
+        let mut values = [
+            self.u32_at(entry + 24)?,
+            self.u32_at(entry + 20)?,
+            self.u32_at(entry + 42)?,
+        ];
+        if values.contains(&(u32::MAX as u64)) {
+            let mut at = entry + 46 + self.u16_at(entry + 28)?;
+            let end = at + self.u16_at(entry + 30)?;
+            while at + 4 <= end {
+                let (id, len) = (self.u16_at(at)?, self.u16_at(at + 2)?);
+                if id == ZIP64_EXTRA_ID {
+                    // only the values which overflowed are present, in this order
+                    let mut field = at + 4;
+                    for v in values.iter_mut().filter(|v| **v == u32::MAX as u64) {
+                        *v = self.u64_at(field)?;
+                        field += 8;
+                    }
+                    break;
+                }
+                at += 4 + len;
+            }
+        }
+        let [_, compressed, local] = values;
+        Ok((compressed, local))
+    }
+
+    /// Fetch the parts of an APK needed to read its signatures and manifest
+    async fn fetch_apk(&mut self) -> Result<()> {
+        const EOCD_MAGIC: &[u8] = &[0x50, 0x4b, 0x05, 0x06];
+        const ZIP64_EOCD_MAGIC: &[u8] = &[0x50, 0x4b, 0x06, 0x06];
+        const ZIP64_LOCATOR_MAGIC: &[u8] = &[0x50, 0x4b, 0x06, 0x07];
+        const SIG_BLOCK_MAGIC: &[u8] = b"APK Sig Block 42";
+
+        let (tail_start, tail) = &self.file.chunks[0];
+        let eocd = tail_start
+            + tail
+                .windows(4)
+                .rposition(|w| w == EOCD_MAGIC)
+                .ok_or(anyhow!("Failed to find end of central directory"))? as u64;
+        let mut cd_size = self.u32_at(eocd + 12)?;
+        let mut cd_offset = self.u32_at(eocd + 16)?;
+
+        // ZIP64 archives keep the 64-bit offsets in a record found through the locator
+        // right before the EOCD
+        let mut cd_end = eocd;
+        if eocd >= 20 {
+            self.fetch(eocd - 20, eocd).await?;
+            if self.file.slice(eocd - 20, eocd - 16)? == ZIP64_LOCATOR_MAGIC {
+                let record = self.u64_at(eocd - 12)?;
+                self.fetch(record, record + 56).await?;
+                ensure!(
+                    self.file.slice(record, record + 4)? == ZIP64_EOCD_MAGIC,
+                    "Invalid ZIP64 end of central directory"
+                );
+                cd_size = self.u64_at(record + 40)?;
+                cd_offset = self.u64_at(record + 48)?;
+                cd_end = record;
+            }
+        }
+        ensure!(
+            cd_offset
+                .checked_add(cd_size)
+                .is_some_and(|end| end <= cd_end),
+            "Invalid central directory location"
+        );
+
+        // the signing block ends with its size and magic right before the central directory
+        let footer = cd_offset.saturating_sub(24);
+        self.fetch(footer, self.file.len).await?;
+        if self.file.slice(footer + 8, cd_offset)? == SIG_BLOCK_MAGIC {
+            let block_len = self.u64_at(footer)?;
+            let block_start = (cd_offset - 8)
+                .checked_sub(block_len)
+                .ok_or(anyhow!("Signing block is larger than the file"))?;
+            self.fetch(block_start, cd_offset).await?;
+        }
+
+        // central directory entries point at the local headers
+        let mut entry = cd_offset;
+        while entry + 46 <= cd_offset + cd_size {
+            let name_len = self.u16_at(entry + 28)?;
+            let name = self.file.slice(entry + 46, entry + 46 + name_len)?;
+            if APK_ENTRIES.iter().any(|e| e.as_bytes() == name) {
+                let (compressed, local) = self.entry_location(entry)?;
+                self.fetch(local, local + 30).await?;
+                let header_len = 30 + self.u16_at(local + 26)? + self.u16_at(local + 28)?;
+                self.fetch(local, local + header_len + compressed).await?;
+            }
+            entry += 46 + name_len + self.u16_at(entry + 30)? + self.u16_at(entry + 32)?;
+        }
+        Ok(())
+    }
+}
+
+fn clone_request(req: &RequestBuilder) -> Result<RequestBuilder> {
+    req.try_clone()
+        .ok_or(anyhow!("request can't be sent more than once"))
+}
+
+/// Load an APK by fetching only its central directory, signing block, manifest and resource
+/// table with HTTP range requests
+///
+/// The file is streamed through the hasher without storing it, a checksum asset is checked
+/// against the streamed bytes like a downloaded file.
+///
+/// Files which aren't an APK fail to parse here, the full download then reports the mismatched
+/// content type.
+pub async fn load_apk_ranges(
+    req: RequestBuilder,
+    url: &str,
+    checksum: Option<&Checksum>,
+) -> Result<RepoArtifact> {
+    let mut fetcher = RangeFetcher::open(clone_request(&req)?, TAIL_LEN).await?;
+    fetcher.fetch_apk().await?;
+    let fetched: usize = fetcher.file.chunks.iter().map(|(_, d)| d.len()).sum();
+    info!(
+        "Fetched {} of {} bytes of {} with range requests",
+        fetched, fetcher.file.len, url
+    );
+    let len = fetcher.file.len;
+    let (platform, metadata) = read_apk(fetcher.file, false)?;
+
+    let mut sha256 = Sha256::new();
+    let mut sha512 = matches!(checksum, Some(Checksum::Sha512(_))).then(Sha512::new);
+    let mut stream = req.send().await?.error_for_status()?.bytes_stream();
+    let mut hashed = 0;
+    while let Some(data) = stream.next().await {
+        let data = data?;
+        hashed += data.len() as u64;
+        sha256.update(&data);
+        if let Some(sha512) = &mut sha512 {
+            sha512.update(&data);
+        }
+    }
+    if hashed != len {
+        bail!("Download truncated, got {} of {} bytes", hashed, len);
+    }
+    let hash = sha256.finalize().to_vec();
+    if let Some(checksum) = checksum {
+        let verified = match checksum {
+            Checksum::Sha256(h) => *h == hash,
+            Checksum::Sha512(h) => sha512.map(|d| d.finalize().to_vec()).as_ref() == Some(h),
+        };
+        if verified {
+            info!("Checksum verified for {}", url);
+        } else {
+            warn!("Checksum mismatch for {}, download may be corrupt!", url);
+        }
+    }
+    Ok(RepoArtifact {
+        name: url.rsplit('/').next().unwrap_or(url).to_string(),
+        size: len,
+        location: RepoResource::Remote(url.to_string()),
+        content_type: "application/vnd.android.package-archive".to_string(),
+        platform,
+        metadata,
+        hash,
+        download_path: None,
+        downloads: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{load_apk_artifact, ArtifactMetadata, LoadOptions};
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    /// Serve a file with `Range` support, counting the bytes sent
+    fn serve(data: Vec<u8>, sent: Arc<AtomicU64>) -> Result<String> {
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/app.apk", server.local_addr()?);
+        std::thread::spawn(move || -> Result<()> {
+            loop {
+                let (mut conn, _) = server.accept()?;
+                let mut req = vec![];
+                let mut buf = [0; 1024];
+                while !req.ends_with(b"\r\n\r\n") {
+                    let n = conn.read(&mut buf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    req.extend_from_slice(&buf[..n]);
+                }
+                let req = String::from_utf8_lossy(&req).to_lowercase();
+                let len = data.len();
+                let range = req
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .map(|r| match r.trim().split_once('-') {
+                        Some(("", n)) => len - n.parse::<usize>().unwrap().min(len)..len,
+                        Some((a, b)) => a.parse().unwrap()..b.parse::<usize>().unwrap() + 1,
+                        None => 0..len,
+                    });
+                let head = match &range {
+                    Some(r) => format!(
+                        "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes {}-{}/{}\r\n",
+                        r.start,
+                        r.end - 1,
+                        len
+                    ),
+                    None => "HTTP/1.1 200 OK\r\n".to_string(),
+                };
+                let body = &data[range.unwrap_or(0..len)];
+                sent.fetch_add(body.len() as u64, Ordering::Relaxed);
+                conn.write_all(
+                    format!(
+                        "{}content-length: {}\r\nconnection: close\r\n\r\n",
+                        head,
+                        body.len()
+                    )
+                    .as_bytes(),
+                )?;
+                conn.write_all(body)?;
+            }
+        });
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn load_fixture_with_ranges() -> Result<()> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/fixture.apk");
+        let local = load_apk_artifact(&path, &LoadOptions::default())?;
+        let sent = Arc::new(AtomicU64::new(0));
+        let url = serve(std::fs::read(&path)?, sent.clone())?;
+        let client = reqwest::Client::new();
+
+        let remote = load_apk_ranges(
+            client.get(&url),
+            &url,
+            Some(&Checksum::Sha256(local.hash.clone())),
+        )
+        .await?;
+        assert_eq!(remote.size, local.size);
+        assert_eq!(remote.hash, local.hash);
+        assert_eq!(remote.platform, local.platform);
+        let (
+            ArtifactMetadata::APK {
+                manifest: a,
+                signature_blocks: sa,
+                ..
+            },
+            ArtifactMetadata::APK {
+                manifest: b,
+                signature_blocks: sb,
+                ..
+            },
+        ) = (&remote.metadata, &local.metadata)
+        else {
+            bail!("expected APK metadata");
+        };
+        assert_eq!(a.package, b.package);
+        assert_eq!(sa.len(), sb.len());
+
+        // without a checksum the file is streamed through the hash
+        let remote = load_apk_ranges(client.get(&url), &url, None).await?;
+        assert_eq!(remote.hash, local.hash);
+
+        // a wrong checksum asset is never published as the hash
+        let remote =
+            load_apk_ranges(client.get(&url), &url, Some(&Checksum::Sha256(vec![0; 32]))).await?;
+        assert_eq!(remote.hash, local.hash);
+
+        // the fixture fits in the tail, fetch only the EOCD first to read the rest in pieces
+        sent.store(0, Ordering::Relaxed);
+        let mut fetcher = RangeFetcher::open(client.get(&url), 64).await?;
+        fetcher.fetch_apk().await?;
+        assert!(sent.load(Ordering::Relaxed) <= local.size);
+        assert!(fetcher.file.chunks.len() > 2);
+        let (platform, _) = read_apk(fetcher.file, false)?;
+        assert_eq!(platform, local.platform);
+
+        // configured MIME types apply like for downloaded files
+        let opts = LoadOptions {
+            range_requests: true,
+            content_types: [("apk".to_string(), "application/x-apk".to_string())].into(),
+            ..Default::default()
+        };
+        let remote = crate::repo::load_artifact_url(&url, &opts, None).await?;
+        assert!(remote.download_path.is_none());
+        assert_eq!(remote.content_type, "application/x-apk");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn load_zip64_with_ranges() -> Result<()> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/fixture-zip64.apk");
+        let url = serve(std::fs::read(&path)?, Arc::new(AtomicU64::new(0)))?;
+
+        let mut fetcher = RangeFetcher::open(reqwest::Client::new().get(&url), 64).await?;
+        fetcher.fetch_apk().await?;
+        let (platform, metadata) = read_apk(fetcher.file, false)?;
+        assert_eq!(
+            platform,
+            crate::repo::Platform::Android {
+                arch: crate::repo::Architecture::ARM64
+            }
+        );
+        let ArtifactMetadata::APK {
+            manifest,
+            signature_blocks,
+            ..
+        } = metadata
+        else {
+            bail!("expected APK metadata");
+        };
+        assert_eq!(manifest.package.as_deref(), Some("com.example.fixture"));
+        assert_eq!(signature_blocks.len(), 1);
+        Ok(())
+    }
+}
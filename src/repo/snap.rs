@@ -0,0 +1,85 @@
+use anyhow::{anyhow, bail, Result};
+use config::{Config, File, FileFormat};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Snap metadata from `meta/snap.yaml`
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct SnapYaml {
+    pub name: String,
+    pub version: String,
+    /// Debian architecture names (`amd64`, `arm64`)
+    #[serde(default)]
+    pub architectures: Vec<String>,
+    /// Base snap providing the runtime (`core22`)
+    pub base: Option<String>,
+    /// `stable` or `devel`
+    pub grade: Option<String>,
+    /// `strict`, `classic` or `devmode`
+    pub confinement: Option<String>,
+}
+
+impl SnapYaml {
+    pub fn parse(yaml: &str) -> Result<SnapYaml> {
+        Ok(Config::builder()
+            .add_source(File::from_str(yaml, FileFormat::Yaml))
+            .build()?
+            .try_deserialize()?)
+    }
+
+    /// Read `meta/snap.yaml` from a snap file
+    ///
+    /// Snaps are squashfs images, the file is extracted with `unsquashfs` from squashfs-tools.
+    pub fn from_snap(path: &Path) -> Result<SnapYaml> {
+        let output = std::process::Command::new("unsquashfs")
+            .arg("-cat")
+            .arg(path)
+            .arg("meta/snap.yaml")
+            .output()
+            .map_err(|e| anyhow!("Failed to run unsquashfs: {}", e))?;
+        if !output.status.success() {
+            bail!(
+                "Failed to read meta/snap.yaml from {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        SnapYaml::parse(std::str::from_utf8(&output.stdout)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_snap_yaml() -> Result<()> {
+        let snap = SnapYaml::parse(
+            r#"
+name: example
+version: "1.2.3"
+summary: Example app
+architectures:
+  - arm64
+base: core22
+grade: stable
+confinement: devmode
+apps:
+  example:
+    command: bin/example
+"#,
+        )?;
+        assert_eq!(
+            snap,
+            SnapYaml {
+                name: "example".to_string(),
+                version: "1.2.3".to_string(),
+                architectures: vec!["arm64".to_string()],
+                base: Some("core22".to_string()),
+                grade: Some("stable".to_string()),
+                confinement: Some("devmode".to_string()),
+            }
+        );
+        Ok(())
+    }
+}
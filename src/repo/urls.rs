@@ -0,0 +1,171 @@
+use crate::manifest::VersionSource;
+use crate::repo::{
+    load_artifact_url, parse_lenient_version, LoadOptions, Repo, RepoRelease, TotalProgress,
+};
+use anyhow::{anyhow, bail, Result};
+use apk_parser::DigestMismatch;
+use log::{info, warn};
+use nostr_sdk::Url;
+use semver::Version;
+
+/// A single release from a list of direct download URLs, for self-hosted artifacts
+pub struct UrlsRepo {
+    urls: Vec<String>,
+    /// Version from the manifest, the artifact version is used when not set
+    version: Option<Version>,
+    app_id: String,
+    opts: LoadOptions,
+}
+
+impl UrlsRepo {
+    pub fn new(
+        urls: Vec<String>,
+        version: Option<&str>,
+        app_id: &str,
+        opts: LoadOptions,
+    ) -> Result<UrlsRepo> {
+        let version = version
+            .map(|v| parse_lenient_version(v).ok_or(anyhow!("'{}' is not a version", v)))
+            .transpose()?;
+        Ok(UrlsRepo {
+            urls,
+            version,
+            app_id: app_id.to_string(),
+            opts,
+        })
+    }
+}
+
+/// File name of a download URL
+fn url_file_name(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()?
+        .path_segments()?
+        .next_back()
+        .filter(|n| !n.is_empty())
+        .map(|n| n.to_string())
+}
+
+#[async_trait::async_trait]
+impl Repo for UrlsRepo {
+    async fn get_releases(&self) -> Result<Vec<RepoRelease>> {
+        let urls: Vec<&String> = self
+            .urls
+            .iter()
+            .filter(|u| match url_file_name(u) {
+                Some(name) if self.opts.filter.matches(&name) => true,
+                _ => {
+                    info!("Skipping excluded asset {}", u);
+                    false
+                }
+            })
+            .take(self.opts.max_artifacts.unwrap_or(usize::MAX))
+            .collect();
+
+        let mut progress = TotalProgress::new(&self.opts.progress, 1, urls.len() as u64);
+        progress.start_release(&self.app_id, urls.len() as u64);
+        let mut artifacts = vec![];
+        for url in urls {
+            let loaded = load_artifact_url(url, &self.opts, None).await;
+            progress.inc();
+            match loaded {
+                Ok(a) => artifacts.push(a),
+                // never skip over a tampered artifact
+                Err(e) if e.is::<DigestMismatch>() => return Err(e),
+                Err(e) => warn!("Failed to load artifact {}: {}", url, e),
+            }
+        }
+        if artifacts.is_empty() {
+            warn!("No artifacts found in the artifact URLs");
+            return Ok(vec![]);
+        }
+
+        let version = match &self.version {
+            Some(v) if self.opts.version_source == VersionSource::Tag => v.clone(),
+            v => match (RepoRelease::artifact_version(&artifacts), v) {
+                (Some(v), _) => v,
+                (None, Some(v)) => {
+                    warn!("No artifact version found, using the manifest version");
+                    v.clone()
+                }
+                (None, None) => bail!("No version in the manifest and the artifacts have none"),
+            },
+        };
+        Ok(vec![RepoRelease {
+            version,
+            description: None,
+            url: None,
+            artifacts,
+            manifest_id: Some(self.app_id.clone()),
+            published_at: None,
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::ArtifactMetadata;
+    use apk_parser::zip::write::{FileOptions, ZipWriter};
+    use std::io::{Cursor, Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn file_names() {
+        assert_eq!(
+            url_file_name("https://example.com/dl/app.apk?token=1").as_deref(),
+            Some("app.apk")
+        );
+        assert_eq!(url_file_name("https://example.com/dl/"), None);
+    }
+
+    #[tokio::test]
+    async fn load_url_list() -> Result<()> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("manifest.webmanifest", FileOptions::default())?;
+        zip.write_all(br#"{"name": "Example"}"#)?;
+        let bundle = zip.finish()?.into_inner();
+        let bundle_len = bundle.len() as u64;
+
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/dist/app.zip", server.local_addr()?);
+        std::thread::spawn(move || -> Result<()> {
+            let (mut conn, _) = server.accept()?;
+            let mut buf = vec![0; 1024];
+            let _request = conn.read(&mut buf)?;
+            conn.write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n",
+                    bundle.len()
+                )
+                .as_bytes(),
+            )?;
+            conn.write_all(&bundle)?;
+            Ok(())
+        });
+
+        let cache_dir = std::env::temp_dir().join("nap-test-urls-repo");
+        let repo = UrlsRepo::new(
+            vec![url, "https://example.com/app.apk".to_string()],
+            Some("1.2"),
+            "com.example.app",
+            LoadOptions {
+                cache_dir: Some(cache_dir.clone()),
+                filter: crate::repo::AssetFilter::new(&[], &["*.apk".to_string()])?,
+                ..Default::default()
+            },
+        )?;
+        let releases = repo.get_releases().await;
+        std::fs::remove_dir_all(&cache_dir)?;
+        let releases = releases?;
+        assert_eq!(releases.len(), 1);
+        assert_eq!(releases[0].version, Version::new(1, 2, 0));
+        assert_eq!(releases[0].app_id()?, "com.example.app");
+        let [artifact] = releases[0].artifacts.as_slice() else {
+            bail!("expected one artifact");
+        };
+        assert!(matches!(artifact.metadata, ArtifactMetadata::Web { .. }));
+        assert_eq!(artifact.size, bundle_len);
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use apk_parser::zip::ZipArchive;
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// File name of the web app manifest inside a bundle
+const WEB_MANIFEST: &str = "manifest.webmanifest";
+
+/// Web app manifest of a PWA bundle
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct WebManifest {
+    pub name: Option<String>,
+    pub short_name: Option<String>,
+    pub start_url: Option<String>,
+    #[serde(default)]
+    pub icons: Vec<WebManifestIcon>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct WebManifestIcon {
+    pub src: String,
+    /// Space separated sizes, eg. `192x192 512x512`
+    pub sizes: Option<String>,
+    #[serde(rename = "type")]
+    pub content_type: Option<String>,
+}
+
+impl WebManifest {
+    /// Read the web app manifest from a `.zip` bundle
+    pub fn from_zip(path: &Path) -> Result<WebManifest> {
+        let mut zip = ZipArchive::new(File::open(path)?)?;
+        let name = shallowest(zip.file_names())
+            .ok_or(anyhow!("No {} found in {}", WEB_MANIFEST, path.display()))?
+            .to_string();
+        let mut data = vec![];
+        zip.by_name(&name)?.read_to_end(&mut data)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Read the web app manifest from a `.tar.gz` bundle
+    pub fn from_tar_gz(path: &Path) -> Result<WebManifest> {
+        // tar can only be read front to back, keep the top-most manifest seen so far
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+        let mut found: Option<(usize, Vec<u8>)> = None;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().to_string();
+            if !is_web_manifest(&name) {
+                continue;
+            }
+            let depth = name.matches('/').count();
+            if found.as_ref().is_some_and(|(d, _)| *d <= depth) {
+                continue;
+            }
+            let mut data = vec![];
+            entry.read_to_end(&mut data)?;
+            found = Some((depth, data));
+        }
+        let (_, data) = found.ok_or(anyhow!("No {} found in {}", WEB_MANIFEST, path.display()))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+/// Path of the top-most web app manifest in a list of archive entries
+fn shallowest<'a>(names: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    names
+        .filter(|n| is_web_manifest(n))
+        .min_by_key(|n| n.matches('/').count())
+}
+
+fn is_web_manifest(name: &str) -> bool {
+    name.rsplit('/').next() == Some(WEB_MANIFEST)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apk_parser::zip::write::{FileOptions, ZipWriter};
+    use std::io::Write;
+
+    const MANIFEST: &str = r#"{
+        "name": "Example App",
+        "short_name": "Example",
+        "start_url": "/index.html",
+        "icons": [{"src": "icons/512.png", "sizes": "512x512", "type": "image/png"}]
+    }"#;
+
+    #[test]
+    fn read_zip_bundle() -> Result<()> {
+        let path = std::env::temp_dir().join("nap-test-web-bundle.zip");
+        let mut zip = ZipWriter::new(File::create(&path)?);
+        zip.start_file("dist/index.html", FileOptions::default())?;
+        zip.write_all(b"<html></html>")?;
+        zip.start_file("dist/manifest.webmanifest", FileOptions::default())?;
+        zip.write_all(MANIFEST.as_bytes())?;
+        zip.start_file(
+            "dist/vendor/pkg/manifest.webmanifest",
+            FileOptions::default(),
+        )?;
+        zip.write_all(b"{}")?;
+        zip.finish()?;
+
+        let manifest = WebManifest::from_zip(&path);
+        std::fs::remove_file(&path)?;
+        let manifest = manifest?;
+        assert_eq!(manifest.name.as_deref(), Some("Example App"));
+        assert_eq!(manifest.short_name.as_deref(), Some("Example"));
+        assert_eq!(manifest.start_url.as_deref(), Some("/index.html"));
+        assert_eq!(manifest.icons[0].src, "icons/512.png");
+        assert_eq!(manifest.icons[0].sizes.as_deref(), Some("512x512"));
+        Ok(())
+    }
+
+    #[test]
+    fn read_tar_gz_bundle() -> Result<()> {
+        let path = std::env::temp_dir().join("nap-test-web-bundle.tar.gz");
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            File::create(&path)?,
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(MANIFEST.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "manifest.webmanifest", MANIFEST.as_bytes())?;
+        tar.into_inner()?.finish()?;
+
+        let manifest = WebManifest::from_tar_gz(&path);
+        std::fs::remove_file(&path)?;
+        assert_eq!(manifest?.name.as_deref(), Some("Example App"));
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Durations of the publish phases, collected for `--timings`
+///
+/// Disabled by default, recording is then a no-op. Clones share the same records.
+#[derive(Debug, Clone, Default)]
+pub struct Timings(Option<Arc<Mutex<Vec<Record>>>>);
+
+/// Phase name and the time spent
+type Record = (String, Duration);
+
+impl Timings {
+    pub fn enabled() -> Self {
+        Timings(Some(Default::default()))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Add the time spent in a phase, repeated phases are summed in the report
+    pub fn record(&self, phase: impl Into<String>, duration: Duration) {
+        if let Some(records) = &self.0 {
+            records.lock().unwrap().push((phase.into(), duration));
+        }
+    }
+
+    /// Run a future and record how long it took
+    pub async fn time<F: Future>(&self, phase: impl Into<String>, f: F) -> F::Output {
+        let start = Instant::now();
+        let ret = f.await;
+        self.record(phase, start.elapsed());
+        ret
+    }
+
+    /// One line per phase in the order they were first recorded, with the total time and how
+    /// often it ran
+    pub fn report(&self) -> Vec<String> {
+        let Some(records) = &self.0 else {
+            return vec![];
+        };
+        let mut phases: Vec<(String, Duration, usize)> = vec![];
+        for (phase, duration) in records.lock().unwrap().iter() {
+            match phases.iter_mut().find(|(p, _, _)| p == phase) {
+                Some((_, total, count)) => {
+                    *total += *duration;
+                    *count += 1;
+                }
+                None => phases.push((phase.clone(), *duration, 1)),
+            }
+        }
+        let width = phases.iter().map(|(p, _, _)| p.len()).max().unwrap_or(0);
+        phases
+            .into_iter()
+            .map(|(phase, total, count)| {
+                let line = format!("{:width$}  {:>9.3}s", phase, total.as_secs_f64());
+                if count > 1 {
+                    format!("{} ({}x)", line, count)
+                } else {
+                    line
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_sums_phases() {
+        let timings = Timings::enabled();
+        timings.record("fetch release list", Duration::from_millis(250));
+        timings
+            .clone()
+            .record("publish wss://relay.example", Duration::from_millis(100));
+        timings.record("publish wss://relay.example", Duration::from_millis(150));
+        assert_eq!(
+            timings.report(),
+            [
+                "fetch release list               0.250s",
+                "publish wss://relay.example      0.250s (2x)",
+            ]
+        );
+
+        let disabled = Timings::default();
+        disabled.record("fetch release list", Duration::from_secs(1));
+        assert!(disabled.report().is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{bail, Result};
+use nostr_sdk::prelude::JsonUtil;
+use nostr_sdk::{Event, Kind, Timestamp};
+
+/// How far in the future `created_at` may be, relays reject events beyond a few minutes
+const MAX_FUTURE_SECS: u64 = 15 * 60;
+
+/// Longest tag value most relays accept
+const MAX_TAG_VALUE_LEN: usize = 1024;
+
+/// Largest serialized event most relays accept
+const MAX_EVENT_SIZE: usize = 64 * 1024;
+
+/// Tags the event must have with a non-empty value
+fn required_tags(kind: Kind) -> &'static [&'static str] {
+    match kind {
+        Kind::FileMetadata => &["f", "m", "size", "x", "url"],
+        Kind::Custom(30_063) => &["d", "a"],
+        Kind::Custom(32_267) => &["d", "name"],
+        _ => &[],
+    }
+}
+
+/// Check an event against the rules relays commonly enforce, returning all problems
+pub fn validate_event(ev: &Event, now: Timestamp) -> Vec<String> {
+    let mut problems = vec![];
+    if let Err(e) = ev.verify() {
+        problems.push(format!("invalid id or signature: {}", e));
+    }
+    if ev.created_at.as_u64() > now.as_u64() + MAX_FUTURE_SECS {
+        problems.push(format!(
+            "created_at is {}s in the future",
+            ev.created_at.as_u64() - now.as_u64()
+        ));
+    }
+    for name in required_tags(ev.kind) {
+        let value = ev
+            .tags
+            .iter()
+            .find(|t| t.kind().to_string() == *name)
+            .and_then(|t| t.content());
+        if value.is_none_or(|v| v.trim().is_empty()) {
+            problems.push(format!("missing or empty '{}' tag", name));
+        }
+    }
+    for tag in ev.tags.iter() {
+        let values = tag.as_slice();
+        if values[0].is_empty() {
+            problems.push("tag with an empty name".to_string());
+        }
+        if let Some(v) = values.iter().find(|v| v.len() > MAX_TAG_VALUE_LEN) {
+            problems.push(format!(
+                "'{}' tag value is {} bytes, the limit is {}",
+                values[0],
+                v.len(),
+                MAX_TAG_VALUE_LEN
+            ));
+        }
+    }
+    let size = ev.as_json().len();
+    if size > MAX_EVENT_SIZE {
+        problems.push(format!(
+            "event is {} bytes, the limit is {}",
+            size, MAX_EVENT_SIZE
+        ));
+    }
+    problems
+}
+
+/// Validate a batch of events, failing with every problem found
+pub fn validate_events(events: &[Event]) -> Result<()> {
+    let now = Timestamp::now();
+    let mut invalid = vec![];
+    for ev in events {
+        for problem in validate_event(ev, now) {
+            invalid.push(format!("kind {} event {}: {}", ev.kind, ev.id, problem));
+        }
+    }
+    if !invalid.is_empty() {
+        bail!("Events failed validation:\n  {}", invalid.join("\n  "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Tag};
+
+    fn file_event(tags: &[&[&str]], created_at: Timestamp) -> Event {
+        EventBuilder::new(Kind::FileMetadata, "")
+            .tags(tags.iter().map(|t| Tag::parse(t.iter().copied()).unwrap()))
+            .custom_created_at(created_at)
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+    }
+
+    #[test]
+    fn event_rules() {
+        let now = Timestamp::from(1_700_000_000);
+        let tags: &[&[&str]] = &[
+            &["f", "android-arm64-v8a"],
+            &["m", "application/vnd.android.package-archive"],
+            &["size", "1"],
+            &["x", "00"],
+            &["url", "https://example.com/app.apk"],
+        ];
+        let ev = file_event(tags, now);
+        assert!(validate_event(&ev, now).is_empty());
+
+        let mut tampered = ev.clone();
+        tampered.content = "changed".to_string();
+        assert_eq!(validate_event(&tampered, now).len(), 1);
+
+        let future = file_event(tags, Timestamp::from(now.as_u64() + 3600));
+        assert_eq!(
+            validate_event(&future, now),
+            ["created_at is 3600s in the future"]
+        );
+
+        let long = "a".repeat(2000);
+        let ev = file_event(
+            &[
+                &["f", "android-arm64-v8a"],
+                &["m", ""],
+                &["size", "1"],
+                &["x", "00"],
+                &["summary", &long],
+            ],
+            now,
+        );
+        assert_eq!(
+            validate_event(&ev, now),
+            [
+                "missing or empty 'm' tag",
+                "missing or empty 'url' tag",
+                "'summary' tag value is 2000 bytes, the limit is 1024",
+            ]
+        );
+    }
+}